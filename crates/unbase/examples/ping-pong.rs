@@ -66,7 +66,6 @@ async fn player_one() {
 
 async fn player_two() {
     let net2 = Network::new();
-    net2.hack_set_next_slab_id(200);
 
     let udp2 = TransportUDP::new("127.0.0.1:12002".to_string());
     net2.add_transport(Box::new(udp2.clone()));
@@ -0,0 +1,124 @@
+//! Edits to several entities which are published together.
+//!
+//! Each edit made directly upon an entity is filed in the root index on its own, so a context on another slab may see
+//! some edits of a logical change before the others. The edits of a batch are instead staged, and then filed by way of
+//! a single new head of the root index node, which is all that other slabs are sent. Whichever context applies that
+//! head sees every edit in the batch.
+
+use super::Context;
+use crate::{
+    entity::Entity,
+    error::WriteError,
+    head::Head,
+    slab::{
+        EntityId,
+        MemoBody,
+        RelationSet,
+        SlotId,
+    },
+    value::Value,
+};
+
+use std::collections::HashMap;
+
+/// The edits staged for one entity, each kind of which is written by way of a single memo
+struct StagedEdits {
+    entity_id: EntityId,
+    /// The head of the entity as of the first edit
+    head:      Head,
+    /// Values to set, or None to remove them
+    vals:      HashMap<String, Option<Value>>,
+    relations: RelationSet,
+}
+
+/// Edits to several entities, staged by way of `Context::batch`, none of which take effect until `commit` is called
+pub struct Batch {
+    context:  Context,
+    // In the order in which the entities were first staged
+    entities: Vec<StagedEdits>,
+}
+
+impl Batch {
+    pub(crate) fn new(context: &Context) -> Batch {
+        Batch { context:  context.clone(),
+                entities: Vec::new(), }
+    }
+
+    pub fn set_value<V>(&mut self, entity: &Entity, key: &str, value: V) -> Result<(), WriteError>
+        where V: Into<Value>
+    {
+        self.staged(entity)?.vals.insert(key.to_string(), Some(value.into()));
+        Ok(())
+    }
+
+    pub fn remove_value(&mut self, entity: &Entity, key: &str) -> Result<(), WriteError> {
+        self.staged(entity)?.vals.insert(key.to_string(), None);
+        Ok(())
+    }
+
+    pub fn set_relation(&mut self, entity: &Entity, key: SlotId, relation: &Entity) -> Result<(), WriteError> {
+        self.staged(entity)?.relations.insert(key, relation.id);
+        Ok(())
+    }
+
+    /// Whether no edits have been staged
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    fn staged(&mut self, entity: &Entity) -> Result<&mut StagedEdits, WriteError> {
+        entity.check_writable()?;
+
+        let index = match self.entities.iter().position(|staged| staged.entity_id == entity.id) {
+            Some(index) => index,
+            None => {
+                self.entities.push(StagedEdits { entity_id: entity.id,
+                                                 head:      entity.head.clone(),
+                                                 vals:      HashMap::new(),
+                                                 relations: RelationSet::empty(), });
+                self.entities.len() - 1
+            },
+        };
+
+        Ok(&mut self.entities[index])
+    }
+
+    /// Write the staged edits, and publish them as one. Handles to the edited entities pick up the edits when next read.
+    ///
+    /// The memos of the edits aren't sent to anyone until they've all been filed, such that no slab hears of some of
+    /// them before the rest.
+    pub async fn commit(self) -> Result<(), WriteError> {
+        let Batch { context, entities } = self;
+        let slab = &context.slab;
+
+        let mut heads = Vec::with_capacity(entities.len());
+        let mut memorefs = Vec::new();
+        for StagedEdits { entity_id,
+                          mut head,
+                          vals,
+                          relations, } in entities
+        {
+            context.mut_update_record_head_for_consistency(&mut head).await?;
+
+            if !vals.is_empty() {
+                memorefs.push(head.write_unannounced(slab, MemoBody::Edit(vals)));
+            }
+            if !relations.is_empty() {
+                memorefs.push(head.write_unannounced(slab, MemoBody::Relation(relations)));
+            }
+            if let Some(body) = head.keyframe_body_if_due(slab).await? {
+                memorefs.push(head.write_unannounced(slab, body));
+            }
+
+            heads.push((entity_id, head));
+        }
+
+        context.update_indices_batch(&heads).await?;
+
+        for memoref in memorefs.iter() {
+            slab.announce_memo(memoref);
+        }
+
+        Ok(())
+    }
+}
@@ -1,5 +1,8 @@
+mod batch;
 pub mod stash;
 
+pub use self::batch::Batch;
+
 use crate::{
     entity::Entity,
     error::{
@@ -8,8 +11,17 @@ use crate::{
         WriteError,
     },
     head::Head,
-    index::IndexFixed,
+    index::{
+        FieldIndex,
+        IndexFixed,
+        ROOT_INDEX_DEPTH,
+    },
+    query::{
+        Query,
+        QueryObserver,
+    },
     slab::{
+        subscriber::head_channel,
         EdgeLink,
         EdgeSet,
         EntityId,
@@ -20,6 +32,7 @@ use crate::{
         SlabHandle,
         SlotId,
     },
+    value::Value,
 };
 
 use self::stash::Stash;
@@ -40,7 +53,6 @@ use std::{
 };
 
 use futures::{
-    channel::mpsc,
     future::RemoteHandle,
     StreamExt,
 };
@@ -75,15 +87,23 @@ impl Context {
     pub fn new(slab: SlabHandle) -> Context {
         let stash = Stash::new();
 
-        let (tx, mut rx) = mpsc::channel(1000);
+        let (tx, mut rx) = head_channel();
         slab.observe_index(tx);
+        slab.register_stash(stash.downgrade());
 
         let applier_slab = slab.clone();
         let applier_stash = stash.clone();
 
+        // If the slab was reopened from storage, pick up the index where the previous contexts left off
+        let restored_heads = slab.agent.restored_index_heads();
+
         let span = span!(Level::TRACE, "Context Applier");
 
         let applier: RemoteHandle<()> = crate::util::task::spawn_with_handle(async move {
+            for head in restored_heads {
+                let _merged_head = applier_stash.apply_head(&applier_slab, &head).await.unwrap();
+            }
+
             while let Some(head) = rx.next().await {
                 let _guard = span.enter();
 
@@ -108,19 +128,67 @@ impl Context {
         Context(Arc::new(inner))
     }
 
+    /// Declare a secondary index on the given field, such that `try_fetch_kv` and queries for that field may probe the
+    /// index rather than scanning every record. The declaration is shared by every context in the system. Records
+    /// already present in the root index are filed immediately, and subsequent writes made through any context are
+    /// filed by `update_indices`.
+    pub async fn add_field_index(&self, field: &str) -> Result<(), WriteError> {
+        if self.field_index(field).await?.is_some() {
+            return Ok(());
+        }
+
+        let mut index = FieldIndex::declare(self, field).await?;
+
+        for head in self.root_index().await?.leaves(self).await? {
+            if let Some(entity_id) = head.entity_id() {
+                index.update(self, entity_id, None, &head).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn field_index(&self, field: &str) -> Result<Option<FieldIndex>, RetrieveError> {
+        FieldIndex::open(self, field).await
+    }
+
+    /// The value of the key of the given head, as presented by the merge policy in effect for it
+    pub(crate) async fn get_head_value(&self, head: &Head, key: &str) -> Result<Option<Value>, RetrieveError> {
+        let stype = match head.entity_id() {
+            Some(entity_id) => entity_id.stype,
+            None => return Ok(None),
+        };
+
+        head.get_value(&self.slab, &*self.slab.merge_policy(stype, key), key).await
+    }
+
+    /// Begin staging edits to several entities, such that other contexts see either all of them or none
+    pub fn batch(&self) -> Batch {
+        Batch::new(self)
+    }
+
+    /// Begin a query against the entities visible to this context
+    pub fn query(&self) -> Query {
+        Query::new(self)
+    }
+
+    /// Observe the result set of a query as it changes, rather than polling for it
+    pub fn observe_query(&self, query: Query) -> QueryObserver {
+        query.in_context(self).observe()
+    }
+
     pub async fn try_fetch_kv(&self, key: &str, val: &str) -> Result<Option<Entity>, RetrieveError> {
-        // TODO implement field-specific indexes
-        // if I have an index for that field {
-        //    use it
-        //} else if I am allowed to scan this index...
+        if let Some(field_index) = self.field_index(key).await? {
+            return match field_index.find(self, val).await? {
+                Some(head) => self.get_entity_from_head(head).await,
+                None => Ok(None),
+            };
+        }
+
         let mut index = self.root_index().await?;
 
         match index.scan_first_kv(self, key, val).await? {
-            Some(head) => {
-                let entity = self.get_entity_from_head(head).await?;
-
-                Ok(Some(entity))
-            },
+            Some(head) => self.get_entity_from_head(head).await,
             None => Ok(None),
         }
     }
@@ -145,15 +213,17 @@ impl Context {
         }
     }
 
-    /// Retrive a Entity from the root index by ID
+    /// Retrive a Entity from the root index by ID. Deleted entities are absent.
     pub async fn get_entity_by_id(&self, entity_id: EntityId) -> Result<Option<Entity>, RetrieveError> {
         let root_index = self.root_index().await?;
 
-        match root_index.get(&self, entity_id.id).await? {
+        match root_index.get(&self, entity_id.index_key()).await? {
+            Some(s) if s.is_deleted(&self.slab).await? => Ok(None),
             Some(s) => {
                 let sh = Entity { id:      entity_id,
                                   head:    s,
-                                  context: self.clone(), };
+                                  context: self.clone(),
+                                  pinned:  false, };
 
                 Ok(Some(sh))
             },
@@ -216,7 +286,7 @@ impl Context {
             }
 
             if let Ok(node) = self.try_root_index_node() {
-                let index = IndexFixed::new_from_head(5, node);
+                let index = IndexFixed::new_from_head(ROOT_INDEX_DEPTH, node);
                 return Ok(index);
             }
 
@@ -257,6 +327,7 @@ impl Context {
                                  MemoBody::FullyMaterialized { v: HashMap::new(),
                                                                r: RelationSet::empty(),
                                                                e: edgeset,
+                                                               o: HashMap::new(),
                                                                t: entity_id.stype, })
                        .to_head();
 
@@ -324,9 +395,68 @@ impl Context {
         return Ok(true);
     }
 
+    /// File the entity head in the root index, and in each field index under its present values. It is withdrawn from
+    /// the postings for the values of the head which was previously filed.
     pub(crate) async fn update_indices(&self, entity_id: EntityId, head: &Head) -> Result<(), WriteError> {
-        self.root_index().await?.insert(self, entity_id.id, head.clone()).await
-        // TODO - update
+        let mut root_index = self.root_index().await?;
+        let previous = root_index.get(self, entity_id.index_key()).await?;
+
+        root_index.insert(self, entity_id.index_key(), head.clone()).await?;
+
+        self.update_field_indices(&[(entity_id, previous, head.clone())]).await
+    }
+
+    /// As with `update_indices`, but for several entities whose heads are filed in the root index as one
+    pub(crate) async fn update_indices_batch(&self, heads: &[(EntityId, Head)]) -> Result<(), WriteError> {
+        let mut root_index = self.root_index().await?;
+
+        let mut updates = Vec::with_capacity(heads.len());
+        for (entity_id, head) in heads {
+            let previous = root_index.get(self, entity_id.index_key()).await?;
+            updates.push((*entity_id, previous, head.clone()));
+        }
+
+        let entries = heads.iter().map(|(entity_id, head)| (entity_id.index_key(), head.clone())).collect();
+        root_index.insert_batch(self, entries).await?;
+
+        self.update_field_indices(&updates).await
+    }
+
+    /// The root index merges each head with that which was previously filed, if any, so the field indices are updated
+    /// to match
+    async fn update_field_indices(&self, updates: &[(EntityId, Option<Head>, Head)]) -> Result<(), WriteError> {
+        let field_indices = FieldIndex::declared(self).await?;
+        if field_indices.is_empty() {
+            return Ok(());
+        }
+
+        for (entity_id, previous, head) in updates {
+            let current = match previous {
+                Some(previous) => previous.apply(head, &self.slab).await?.0,
+                None => head.clone(),
+            };
+
+            for mut field_index in field_indices.iter().cloned() {
+                field_index.update(self, *entity_id, previous.as_ref(), &current).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Withdraw a deleted entity from our indices. The entity is withdrawn from the secondary indices under the values
+    /// of its `previous` head, whereas the root index retains the tombstone, such that any edit made concurrently with
+    /// the deletion is merged with it rather than taking the place of the entity, and the deletion prevails everywhere.
+    /// Lookups treat such entities as absent.
+    pub(crate) async fn remove_from_indices(&self, entity_id: EntityId, previous: &Head, tombstone: &Head)
+                                            -> Result<(), WriteError> {
+        self.root_index().await?.insert(self, entity_id.index_key(), tombstone.clone()).await?;
+
+        for mut field_index in FieldIndex::declared(self).await? {
+            field_index.remove(self, entity_id, previous).await?;
+        }
+
+        Ok(())
     }
 
     /// Called by the Slab whenever memos matching one of our subscriptions comes in, or by the Entity when an edit is
@@ -336,21 +466,47 @@ impl Context {
         self.stash.apply_head(&self.slab, head).await
     }
 
+    /// Retrieve an entity from the root index by ID. Deleted entities are absent.
     pub async fn get_entity(&self, entity_id: EntityId) -> Result<Option<Entity>, RetrieveError> {
         let root_index = self.root_index().await?;
 
-        match root_index.get(self, entity_id.id).await? {
+        match root_index.get(self, entity_id.index_key()).await? {
+            Some(head) if head.is_deleted(&self.slab).await? => Ok(None),
             Some(head) => {
                 Ok(Some(Entity { id:
                                      head.entity_id()
                                          .ok_or(RetrieveError::InvalidHead(InvalidHead::MissingEntityId))?,
                                  head,
-                                 context: self.clone() }))
+                                 context: self.clone(),
+                                 pinned: false }))
             },
             None => Ok(None),
         }
     }
 
+    /// The entity as it was at the head comprised of the given memos, each of which must belong to it. See `Entity::at`.
+    pub fn get_entity_at(&self, entity_id: EntityId, memo_ids: &[MemoId]) -> Result<Entity, RetrieveError> {
+        if memo_ids.is_empty() {
+            return Err(RetrieveError::InvalidHead(InvalidHead::Empty));
+        }
+
+        let mut memorefs = Vec::with_capacity(memo_ids.len());
+        for memo_id in memo_ids {
+            match self.slab.get_memoref(*memo_id) {
+                Some(memoref) if memoref.entity_id == Some(entity_id) => memorefs.push(memoref),
+                Some(_) => return Err(RetrieveError::MemoLineageError),
+                None => return Err(RetrieveError::NotFound),
+            }
+        }
+
+        Ok(Entity { id:      entity_id,
+                    head:    Head::Entity { owning_slab_id: self.slab.my_ref.slab_id,
+                                            entity_id,
+                                            head: memorefs },
+                    context: self.clone(),
+                    pinned:  true, })
+    }
+
     /// Update a given Head with any relevant information to ensure that our consistency model invariants are met
     #[tracing::instrument(level = "info")]
     pub(crate) async fn mut_update_index_head_for_consistency(&self, mut_head: &mut Head) -> Result<bool, RetrieveError> {
@@ -389,7 +545,7 @@ impl Context {
                 //       was pulled against a sufficiently identical context stash state.
                 //       Perhaps stash edit increment? how can we get this to be really granular?
 
-                match self.root_index().await?.get(&self, entity_id.id).await? {
+                match self.root_index().await?.get(&self, entity_id.index_key()).await? {
                     Some(head) => head,
                     None => return Ok(false),
                 }
@@ -402,17 +558,23 @@ impl Context {
         Ok(applied)
     }
 
-    pub(crate) async fn get_entity_from_head(&self, mut head: Head) -> Result<Entity, RetrieveError> {
+    /// The entity with the given head, brought up to date, or `None` if it has been deleted
+    pub(crate) async fn get_entity_from_head(&self, mut head: Head) -> Result<Option<Entity>, RetrieveError> {
         self.mut_update_record_head_for_consistency(&mut head).await?;
 
         if head.entity_id().is_none() {
             panic!("get_entity_from_head - no entity_id for {:?}", head);
         }
 
-        Ok(Entity { id: head.entity_id()
-                            .ok_or(RetrieveError::InvalidHead(InvalidHead::MissingEntityId))?,
-                    head,
-                    context: self.clone() })
+        if head.is_deleted(&self.slab).await? {
+            return Ok(None);
+        }
+
+        Ok(Some(Entity { id: head.entity_id()
+                                 .ok_or(RetrieveError::InvalidHead(InvalidHead::MissingEntityId))?,
+                         head,
+                         context: self.clone(),
+                         pinned: false }))
     }
 }
 
@@ -451,7 +613,7 @@ mod test {
         let _head4 = context.add_test_head(EntityId::index_test(4), vec![head3]).await;
 
         // each preceeding entity should be pruned, leaving us with a fully compacted stash
-        assert_eq!(context.stash.concise_contents(), "I4>I3", "Valid contents");
+        assert_eq!(context.stash.concise_contents(), "I0.4>I0.3", "Valid contents");
     }
 
     #[unbase_test_util::async_test]
@@ -475,7 +637,7 @@ mod test {
         let head3 = context.add_test_head(EntityId::index_test(3), vec![head2.clone()]).await;
         let head4 = context.add_test_head(EntityId::index_test(4), vec![head3.clone()]).await;
 
-        assert_eq!(context.stash.concise_contents(), "I2>I1;I4>I3", "Valid contents");
+        assert_eq!(context.stash.concise_contents(), "I0.2>I0.1;I0.4>I0.3", "Valid contents");
 
         {
             // manually perform compaction
@@ -487,7 +649,7 @@ mod test {
             context.apply_head(&head).await.unwrap();
         }
 
-        assert_eq!(context.stash.concise_contents(), "I3>I2;I4>I3", "Valid contents");
+        assert_eq!(context.stash.concise_contents(), "I0.3>I0.2;I0.4>I0.3", "Valid contents");
 
         {
             // manually perform compaction
@@ -497,7 +659,7 @@ mod test {
             context.apply_head(&head).await.unwrap();
         }
 
-        assert_eq!(context.stash.concise_contents(), "I4>I3", "Valid contents");
+        assert_eq!(context.stash.concise_contents(), "I0.4>I0.3", "Valid contents");
     }
 
     #[unbase_test_util::async_test]
@@ -536,11 +698,11 @@ mod test {
         // apply_head(head4)
         let _head4 = context.add_test_head(EntityId::index_test(4), vec![head3]).await;
 
-        assert_eq!(context.stash.concise_contents(), "I2>I1;I3>I2;I4>I3", "Valid contents");
+        assert_eq!(context.stash.concise_contents(), "I0.2>I0.1;I0.3>I0.2;I0.4>I0.3", "Valid contents");
 
         context.compact().await.unwrap();
 
-        assert_eq!(context.stash.concise_contents(), "I4>I3", "Valid contents");
+        assert_eq!(context.stash.concise_contents(), "I0.4>I0.3", "Valid contents");
     }
 
     // TODO POSTMERGE - restore these tests
@@ -1,10 +1,12 @@
 //#![allow(dead_code)]
 use std::{
+    collections::HashMap,
     iter,
     mem,
     sync::{
         Arc,
         Mutex,
+        Weak,
     },
 };
 
@@ -85,7 +87,7 @@ use crate::{
 /// let head3 = stash.add_test_head(&slab, EntityId::index_test(3), vec![head1, Head::Null, head2])
 ///                  .await;
 ///
-/// assert_eq!(stash.concise_contents(), "I3>I1,_,I2");
+/// assert_eq!(stash.concise_contents(), "I0.3>I0.1,_,I0.2");
 /// # });
 /// ```
 ///
@@ -99,14 +101,38 @@ pub(super) struct StashInner {
     items:     Vec<Option<StashItem>>,
     index:     Vec<(EntityId, ItemId)>,
     vacancies: Vec<ItemId>,
+    /// The head each entity had when it was last pruned, for so long as it has none in the stash
+    pruned:    HashMap<EntityId, Head>,
 }
 type ItemId = usize;
 
+/// A reference to a stash which doesn't keep it alive, by which the slab learns which heads its contexts retain
+#[derive(Clone)]
+pub(crate) struct WeakStash(Weak<Mutex<StashInner>>);
+
+impl WeakStash {
+    pub(crate) fn is_alive(&self) -> bool {
+        self.0.strong_count() > 0
+    }
+
+    /// The heads in the stash, or None if the context has gone away
+    pub(crate) fn heads(&self) -> Option<Vec<Head>> {
+        let inner = self.0.upgrade()?;
+        let inner = inner.lock().unwrap();
+
+        Some(inner.items.iter().flatten().filter(|item| item.head.is_some()).map(|item| item.head.clone()).collect())
+    }
+}
+
 impl Stash {
     pub fn new() -> Stash {
         Default::default()
     }
 
+    pub(crate) fn downgrade(&self) -> WeakStash {
+        WeakStash(Arc::downgrade(&self.inner))
+    }
+
     /// Returns the number of entities in the `Stash` including placeholders.
     pub fn _count(&self) -> usize {
         self.inner.lock().unwrap().index.len()
@@ -138,7 +164,9 @@ impl Stash {
     ///
     /// For example:
     ///
-    ///   I2>I1;I7>I2,_,I4
+    ///   I0.2>I0.1;I0.7>I0.2,_,I0.4
+    ///
+    ///   (each entity id is written as its issuing slab and slab-local id, so I0.2 is shortened to I2 below)
     ///
     ///   would indicate that the stash contains:
     ///   * A Head for entity I2 (EntityType::Index) with slot 0 pointing to I1.
@@ -240,6 +268,17 @@ impl Stash {
             // Get the head and editcount for this specific entity id.
             let mut item: ItemEditGuard = self.get_head_for_edit(entity_id);
 
+            // A head which was pruned adds nothing when it's applied anew, as when the slab echoes our own writes back
+            // to us, and mustn't be brought back
+            if let Head::Null = item.get_head() {
+                let pruned = self.inner.lock().unwrap().pruned.get(&entity_id).cloned();
+                if let Some(pruned) = pruned {
+                    if pruned.descends_or_contains(apply_head, slab).await? {
+                        return Ok(apply_head.clone());
+                    }
+                }
+            }
+
             if !item.apply_head(apply_head, slab).await? {
                 return Ok(item.get_head().clone());
             }
@@ -316,7 +355,6 @@ impl Stash {
             MemoBody,
             RelationSet,
         };
-        use std::collections::HashMap;
 
         let mut edgeset = EdgeSet::empty();
 
@@ -331,6 +369,7 @@ impl Stash {
                                  MemoBody::FullyMaterialized { v: HashMap::new(),
                                                                r: RelationSet::empty(),
                                                                e: edgeset,
+                                                               o: HashMap::new(),
                                                                t: entity_id.stype, })
                        .to_head();
 
@@ -543,9 +582,16 @@ impl ItemEditGuard {
         // set the new head itself
 
         let item = inner.items[self.item_id].as_mut().unwrap();
-        item.head = self.head.clone();
+        let previous = mem::replace(&mut item.head, self.head.clone());
         item.edit_counter += 1;
 
+        let entity_id = item.entity_id;
+        if self.head.is_some() {
+            inner.pruned.remove(&entity_id);
+        } else if previous.is_some() {
+            inner.pruned.insert(entity_id, previous);
+        }
+
         // IMPORTANT - because we consume self, drop will run after we return, ths calling decrement_item
         //             which is crucial for the evaluation of item removal in the case that we
         //             just set head to Head::Null (essentially the same as unsetting)
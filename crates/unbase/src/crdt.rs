@@ -0,0 +1,339 @@
+//! Conflict-free replicated data types for entity fields.
+//!
+//! Rather than writing the value of such a field, each memo carries an operation upon it, and the value is projected
+//! from every operation in the causal history of the entity. The projection doesn't depend on the order in which the
+//! operations are encountered, so slabs which have seen the same operations agree on the value without coordination.
+
+use crate::{
+    entity::Entity,
+    slab::{
+        HeadReceiver,
+        MemoId,
+    },
+};
+
+use futures::{
+    channel::mpsc,
+    future::RemoteHandle,
+    task::{
+        Context,
+        Poll,
+    },
+    SinkExt,
+    Stream,
+    StreamExt,
+};
+use std::{
+    cmp::Reverse,
+    collections::{
+        BTreeMap,
+        BTreeSet,
+        HashMap,
+        HashSet,
+    },
+    pin::Pin,
+};
+
+use tracing::debug;
+
+/// An operation upon a field, as carried by `MemoBody::Operation`
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum CrdtOp {
+    /// Add to a PN-counter. Negative amounts decrement it.
+    Increment(i64),
+    /// Add an item to an OR-set. The add is tagged with the id of the memo which carries it.
+    SetAdd(String),
+    /// Remove an item from an OR-set, cancelling the adds with the given tags, which are those the writer had observed.
+    /// Adds which were concurrent with the removal are unaffected, so the item remains.
+    SetRemove(String, Vec<MemoId>),
+    /// Apply the operation to the given entry of a map
+    Map(String, Box<CrdtOp>),
+    /// Insert text into an RGA sequence, immediately after the given character, or at the start. Character `i` of the
+    /// text is identified by the id of the memo carrying the insertion and `i`. Insertions at the same position are
+    /// ordered by `seq`, which exceeds that of every insertion the writer had observed, such that the later of two
+    /// causally ordered insertions comes first.
+    TextInsert {
+        after: Option<TextId>,
+        seq:   u64,
+        text:  String,
+    },
+    /// Delete the given characters from an RGA sequence
+    TextDelete(Vec<TextId>),
+}
+
+/// Identifies a character of an RGA sequence by the memo which inserted it and its offset within that insertion
+pub type TextId = (MemoId, u32);
+
+/// The operations upon each of several fields, along with the id of the memo which first carried each
+pub type FieldOperations = HashMap<String, Vec<(MemoId, CrdtOp)>>;
+
+/// The projected value of a field
+#[derive(Clone, Debug, PartialEq)]
+pub enum CrdtValue {
+    Counter(i64),
+    Set(BTreeSet<String>),
+    Map(BTreeMap<String, CrdtValue>),
+    Text(String),
+}
+
+/// The projected value along with the tags of the adds which constitute any sets within it
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum CrdtState {
+    Counter(i64),
+    Set(BTreeMap<String, BTreeSet<MemoId>>),
+    Map(BTreeMap<String, CrdtState>),
+    Text {
+        /// The characters which remain, in order
+        chars: Vec<(TextId, char)>,
+        /// The greatest `seq` of any insertion
+        seq:   u64,
+    },
+}
+
+impl CrdtOp {
+    /// Nest the operation within a map, one level per element of the path
+    pub fn nested(path: &[&str], op: CrdtOp) -> CrdtOp {
+        path.iter().rev().fold(op, |op, entry| CrdtOp::Map(entry.to_string(), Box::new(op)))
+    }
+}
+
+impl CrdtState {
+    /// Project the state from a set of operations, each identified by the memo which carries it.
+    ///
+    /// Operations of differing kinds shouldn't be applied to the same field, but where that has happened concurrently,
+    /// maps take precedence over sets, sets over text, and text over counters, such that the outcome is the same
+    /// everywhere.
+    pub fn project(ops: Vec<(MemoId, CrdtOp)>) -> Option<CrdtState> {
+        if ops.is_empty() {
+            return None;
+        }
+
+        if ops.iter().any(|(_, op)| matches!(op, CrdtOp::Map(..))) {
+            let mut entries: HashMap<String, Vec<(MemoId, CrdtOp)>> = HashMap::new();
+            for (memo_id, op) in ops {
+                if let CrdtOp::Map(entry, op) = op {
+                    entries.entry(entry).or_default().push((memo_id, *op));
+                }
+            }
+
+            let map = entries.into_iter()
+                             .filter_map(|(entry, ops)| CrdtState::project(ops).map(|state| (entry, state)))
+                             .collect();
+
+            return Some(CrdtState::Map(map));
+        }
+
+        if ops.iter().any(|(_, op)| matches!(op, CrdtOp::SetAdd(_) | CrdtOp::SetRemove(..))) {
+            let mut removed: HashSet<(&str, MemoId)> = HashSet::new();
+            for (_, op) in ops.iter() {
+                if let CrdtOp::SetRemove(item, tags) = op {
+                    removed.extend(tags.iter().map(|tag| (item.as_str(), *tag)));
+                }
+            }
+
+            let mut set: BTreeMap<String, BTreeSet<MemoId>> = BTreeMap::new();
+            for (memo_id, op) in ops.iter() {
+                if let CrdtOp::SetAdd(item) = op {
+                    if !removed.contains(&(item.as_str(), *memo_id)) {
+                        set.entry(item.clone()).or_default().insert(*memo_id);
+                    }
+                }
+            }
+
+            return Some(CrdtState::Set(set));
+        }
+
+        if ops.iter().any(|(_, op)| matches!(op, CrdtOp::TextInsert { .. } | CrdtOp::TextDelete(_))) {
+            return Some(Self::project_text(ops));
+        }
+
+        let total = ops.iter()
+                       .map(|(_, op)| if let CrdtOp::Increment(n) = op { *n } else { 0 })
+                       .fold(0i64, i64::wrapping_add);
+
+        Some(CrdtState::Counter(total))
+    }
+
+    /// Each insertion hangs off the character it was made after. Walking that tree depth first, with siblings in
+    /// descending order of `seq`, yields the sequence.
+    fn project_text(ops: Vec<(MemoId, CrdtOp)>) -> CrdtState {
+        let mut deleted: HashSet<TextId> = HashSet::new();
+        let mut children: HashMap<Option<TextId>, Vec<(u64, TextId, char)>> = HashMap::new();
+        let mut max_seq = 0;
+
+        for (memo_id, op) in ops {
+            match op {
+                CrdtOp::TextInsert { after, seq, text } => {
+                    max_seq = max_seq.max(seq);
+
+                    let mut anchor = after;
+                    for (i, c) in text.chars().enumerate() {
+                        let id = (memo_id, i as u32);
+                        children.entry(anchor).or_default().push((seq, id, c));
+                        anchor = Some(id);
+                    }
+                },
+                CrdtOp::TextDelete(ids) => deleted.extend(ids),
+                _ => {},
+            }
+        }
+
+        for siblings in children.values_mut() {
+            siblings.sort_by_key(|&(seq, id, _)| Reverse((seq, id)));
+        }
+
+        let mut chars = Vec::new();
+        let mut stack: Vec<(TextId, char)> = Vec::new();
+        if let Some(siblings) = children.get(&None) {
+            stack.extend(siblings.iter().rev().map(|(_, id, c)| (*id, *c)));
+        }
+
+        while let Some((id, c)) = stack.pop() {
+            if !deleted.contains(&id) {
+                chars.push((id, c));
+            }
+            if let Some(siblings) = children.get(&Some(id)) {
+                stack.extend(siblings.iter().rev().map(|(_, id, c)| (*id, *c)));
+            }
+        }
+
+        CrdtState::Text { chars, seq: max_seq }
+    }
+
+    /// The state at the given path through nested maps, if any
+    pub fn at_path(&self, path: &[&str]) -> Option<&CrdtState> {
+        match path.split_first() {
+            None => Some(self),
+            Some((entry, rest)) => {
+                match self {
+                    CrdtState::Map(map) => map.get(*entry).and_then(|state| state.at_path(rest)),
+                    _ => None,
+                }
+            },
+        }
+    }
+
+    /// The tags of the adds of the item which are presently observed, should this be a set
+    pub fn observed_tags(&self, item: &str) -> Vec<MemoId> {
+        match self {
+            CrdtState::Set(set) => set.get(item).map(|tags| tags.iter().cloned().collect()).unwrap_or_default(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn to_value(&self) -> CrdtValue {
+        match self {
+            CrdtState::Counter(n) => CrdtValue::Counter(*n),
+            CrdtState::Set(set) => CrdtValue::Set(set.keys().cloned().collect()),
+            CrdtState::Map(map) => {
+                CrdtValue::Map(map.iter().map(|(entry, state)| (entry.clone(), state.to_value())).collect())
+            },
+            CrdtState::Text { chars, .. } => CrdtValue::Text(chars.iter().map(|(_, c)| c).collect()),
+        }
+    }
+}
+
+/// A change to the text of an observed sequence field. Applying the events in order to the previous text yields the
+/// current text. Indices are in characters.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TextEvent {
+    Insert { index: usize, text: String },
+    Delete { index: usize, len: usize },
+}
+
+impl TextEvent {
+    /// The events which transform one sequence into another. Deletions come first, from the end backwards, such that
+    /// each index refers to the text as it stands once the preceding events have been applied.
+    fn diff(previous: &[(TextId, char)], current: &[(TextId, char)]) -> Vec<TextEvent> {
+        let current_ids: HashSet<TextId> = current.iter().map(|(id, _)| *id).collect();
+        let previous_ids: HashSet<TextId> = previous.iter().map(|(id, _)| *id).collect();
+
+        let mut events = Vec::new();
+
+        let mut i = previous.len();
+        while i > 0 {
+            if current_ids.contains(&previous[i - 1].0) {
+                i -= 1;
+                continue;
+            }
+
+            let end = i;
+            while i > 0 && !current_ids.contains(&previous[i - 1].0) {
+                i -= 1;
+            }
+            events.push(TextEvent::Delete { index: i,
+                                            len:   end - i, });
+        }
+
+        let mut i = 0;
+        while i < current.len() {
+            if previous_ids.contains(&current[i].0) {
+                i += 1;
+                continue;
+            }
+
+            let start = i;
+            let mut text = String::new();
+            while i < current.len() && !previous_ids.contains(&current[i].0) {
+                text.push(current[i].1);
+                i += 1;
+            }
+            events.push(TextEvent::Insert { index: start, text });
+        }
+
+        events
+    }
+}
+
+/// The stream of changes to a sequence field. The field is no longer observed once this is dropped.
+pub struct TextObserver {
+    rx:    mpsc::Receiver<TextEvent>,
+    _task: RemoteHandle<()>,
+}
+
+impl TextObserver {
+    pub(crate) fn new(mut entity: Entity, key: &str, mut heads: HeadReceiver) -> TextObserver {
+        let (mut tx, rx) = mpsc::channel(1000);
+        let key = key.to_string();
+
+        let task = crate::util::task::spawn_with_handle(async move {
+            let mut previous = Vec::new();
+
+            while let Some(head) = heads.next().await {
+                if let Err(e) = entity.head.mut_apply(&head, &entity.context.slab).await {
+                    debug!("TextObserver - failed to apply head: {:?}", e);
+                    continue;
+                }
+
+                let current = match entity.get_crdt_state(&key).await {
+                    Ok(Some(CrdtState::Text { chars, .. })) => chars,
+                    Ok(_) => Vec::new(),
+                    Err(e) => {
+                        debug!("TextObserver - failed to project {}: {:?}", key, e);
+                        continue;
+                    },
+                };
+
+                for event in TextEvent::diff(&previous, &current) {
+                    if tx.send(event).await.is_err() {
+                        // The observer was dropped
+                        return;
+                    }
+                }
+
+                previous = current;
+            }
+        });
+
+        TextObserver { rx,
+                       _task: task }
+    }
+}
+
+impl Stream for TextObserver {
+    type Item = TextEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<TextEvent>> {
+        self.get_mut().rx.poll_next_unpin(cx)
+    }
+}
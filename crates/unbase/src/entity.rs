@@ -1,26 +1,39 @@
 use crate::{
     context::Context,
+    crdt::{
+        CrdtOp,
+        CrdtState,
+        CrdtValue,
+        TextId,
+        TextObserver,
+    },
     error::{
+        InvalidHead,
         RetrieveError,
         WriteError,
     },
     head::Head,
+    history::History,
     slab::{
+        subscriber::head_channel,
+        EdgeLink,
         EdgeSet,
         EntityId,
         EntityType,
+        HeadReceiver,
         MemoBody,
         MemoId,
         RelationSet,
         SlabHandle,
         SlotId,
     },
+    value::Value,
 };
 
-use futures::channel::mpsc;
 use std::{
     collections::HashMap,
     fmt,
+    time::SystemTime,
 };
 
 use tracing::debug;
@@ -31,13 +44,18 @@ pub struct Entity {
     pub id:             EntityId,
     pub(crate) head:    Head,
     pub(crate) context: Context,
+    /// Whether the entity is pinned to a historical head, rather than being kept up to date with the context
+    pub(crate) pinned:  bool,
 }
 
 /// Entity contains a Context (which is an Arc internally) because it IS an enforcer of consistency, and
 /// therefore must use the context. Becasuse Entity contains a Context reference, it *MUST NOT BE STORED*
 /// anywhere other than user code, otherwise we will create a cycle and thus a memory leak
 impl Entity {
-    pub async fn new(context: &Context, vals: HashMap<String, String>) -> Result<Entity, WriteError> {
+    pub async fn new<V>(context: &Context, vals: HashMap<String, V>) -> Result<Entity, WriteError>
+        where V: Into<Value>
+    {
+        let vals = vals.into_iter().map(|(key, value)| (key, value.into())).collect();
         let slab: &SlabHandle = &context.slab;
         let id = slab.generate_entity_id(EntityType::Record);
 
@@ -48,6 +66,7 @@ impl Entity {
                                  MemoBody::FullyMaterialized { v: vals,
                                                                r: RelationSet::empty(),
                                                                e: EdgeSet::empty(),
+                                                               o: HashMap::new(),
                                                                t: id.stype.clone(), })
                        .to_head();
 
@@ -55,45 +74,134 @@ impl Entity {
 
         let handle = Entity { id,
                               head,
-                              context: context.clone() };
+                              context: context.clone(),
+                              pinned: false };
 
         Ok(handle)
     }
 
     pub async fn new_blank(context: &Context) -> Result<Entity, WriteError> {
-        Self::new(context, HashMap::new()).await
+        Self::new(context, HashMap::<String, Value>::new()).await
     }
 
-    pub async fn new_with_single_kv(context: &Context, key: &str, value: &str) -> Result<Entity, WriteError> {
+    pub async fn new_with_single_kv<V>(context: &Context, key: &str, value: V) -> Result<Entity, WriteError>
+        where V: Into<Value>
+    {
         let mut vals = HashMap::new();
-        vals.insert(key.to_string(), value.to_string());
+        vals.insert(key.to_string(), value.into());
 
         Self::new(context, vals).await
     }
 
+    /// The value of the key, as presented by its merge policy, and rendered as a string. Where the policy presents several
+    /// concurrent values, this is the first of them. See `get_values`, and `get_typed_value` for the value as written.
     #[tracing::instrument(level = "info")]
     pub async fn get_value(&mut self, key: &str) -> Result<Option<String>, RetrieveError> {
+        Ok(self.get_typed_value(key).await?.map(|value| value.to_string()))
+    }
+
+    /// The value of the key, as presented by its merge policy. Where the policy presents several concurrent values, this
+    /// is the first of them. See `get_typed_values`.
+    pub async fn get_typed_value(&mut self, key: &str) -> Result<Option<Value>, RetrieveError> {
         let copy = self.head.clone();
-        let applied = self.context.mut_update_record_head_for_consistency(&mut self.head).await?;
-        tracing::info!("called mut_update_record_head_for_consistency. Applied: {:?}\n\tWas {:?}\n\tNow {:?}",
+        let applied = self.update_head().await?;
+        tracing::info!("called update_head. Applied: {:?}\n\tWas {:?}\n\tNow {:?}",
                        applied,
                        copy,
                        self.head);
 
-        self.head.get_value(&self.context.slab, key).await
+        Ok(self.merged_values(key).await?.into_iter().next())
+    }
+
+    /// Every value presented for the key by its merge policy, rendered as strings. Under the default policy there is at
+    /// most one, but a multi-value policy presents each of the values which were written concurrently.
+    pub async fn get_values(&mut self, key: &str) -> Result<Vec<String>, RetrieveError> {
+        Ok(self.get_typed_values(key).await?.iter().map(|value| value.to_string()).collect())
+    }
+
+    /// Every value presented for the key by its merge policy. See `get_values`.
+    pub async fn get_typed_values(&mut self, key: &str) -> Result<Vec<Value>, RetrieveError> {
+        self.update_head().await?;
+
+        self.merged_values(key).await
+    }
+
+    /// Every key which has a value, along with the value presented for it by its merge policy. Where the policy presents
+    /// several concurrent values, this is the first of them.
+    pub async fn get_all_values(&mut self) -> Result<HashMap<String, Value>, RetrieveError> {
+        self.update_head().await?;
+
+        let mut values = HashMap::new();
+        for key in self.head.get_all_keys(&self.context.slab).await? {
+            if let Some(value) = self.merged_values(&key).await?.into_iter().next() {
+                values.insert(key, value);
+            }
+        }
+
+        Ok(values)
+    }
+
+    pub async fn get_bool(&mut self, key: &str) -> Result<Option<bool>, RetrieveError> {
+        self.get_typed(key, Value::as_bool).await
+    }
+
+    pub async fn get_i64(&mut self, key: &str) -> Result<Option<i64>, RetrieveError> {
+        self.get_typed(key, Value::as_i64).await
+    }
+
+    /// Integer values are widened
+    pub async fn get_f64(&mut self, key: &str) -> Result<Option<f64>, RetrieveError> {
+        self.get_typed(key, Value::as_f64).await
+    }
+
+    pub async fn get_string(&mut self, key: &str) -> Result<Option<String>, RetrieveError> {
+        self.get_typed(key, |value| value.as_str().map(str::to_string)).await
+    }
+
+    pub async fn get_bytes(&mut self, key: &str) -> Result<Option<Vec<u8>>, RetrieveError> {
+        self.get_typed(key, |value| value.as_bytes().map(<[u8]>::to_vec)).await
+    }
+
+    pub async fn get_timestamp(&mut self, key: &str) -> Result<Option<SystemTime>, RetrieveError> {
+        self.get_typed(key, Value::as_timestamp).await
+    }
+
+    pub async fn get_list(&mut self, key: &str) -> Result<Option<Vec<Value>>, RetrieveError> {
+        self.get_typed(key, |value| value.as_list().map(<[Value]>::to_vec)).await
+    }
+
+    /// The value of the key, converted by the given accessor. Absent and null values are `None`, and values of any other
+    /// type are a `TypeMismatch`.
+    async fn get_typed<T, F>(&mut self, key: &str, accessor: F) -> Result<Option<T>, RetrieveError>
+        where F: Fn(&Value) -> Option<T>
+    {
+        match self.get_typed_value(key).await? {
+            None | Some(Value::Null) => Ok(None),
+            Some(value) => {
+                match accessor(&value) {
+                    Some(v) => Ok(Some(v)),
+                    None => Err(RetrieveError::TypeMismatch(value.type_name())),
+                }
+            },
+        }
+    }
+
+    async fn merged_values(&self, key: &str) -> Result<Vec<Value>, RetrieveError> {
+        let policy = self.context.slab.merge_policy(self.id.stype, key);
+        self.head.get_merged_values(&self.context.slab, &*policy, key).await
     }
 
     pub async fn get_edge(&mut self, key: SlotId) -> Result<Option<Entity>, RetrieveError> {
-        self.context.mut_update_record_head_for_consistency(&mut self.head).await?;
+        self.update_head().await?;
 
         match self.head.get_edge(&self.context.slab, key).await? {
-            Some(head) => Ok(Some(self.context.get_entity_from_head(head).await?)),
+            Some(head) => self.context.get_entity_from_head(head).await,
             None => Ok(None),
         }
     }
 
     pub async fn get_relation(&mut self, key: SlotId) -> Result<Option<Entity>, RetrieveError> {
-        self.context.mut_update_record_head_for_consistency(&mut self.head).await?;
+        self.update_head().await?;
 
         match self.head.get_relation(&self.context.slab, key).await? {
             Some(rel_entity_id) => self.context.get_entity(rel_entity_id).await,
@@ -101,8 +209,59 @@ impl Entity {
         }
     }
 
-    pub async fn set_value(&mut self, key: &str, value: &str) -> Result<(), WriteError> {
-        self.head.set_value(&self.context.slab, key, value).await?;
+    /// Every occupied relation, by slot. Relations to entities which have since been deleted are omitted.
+    pub async fn get_all_relations(&mut self) -> Result<HashMap<SlotId, Entity>, RetrieveError> {
+        self.update_head().await?;
+
+        let mut relations = HashMap::new();
+        for (slot_id, entity_id) in self.head.get_all_relations(&self.context.slab).await? {
+            if let Some(entity) = self.context.get_entity(entity_id).await? {
+                relations.insert(slot_id, entity);
+            }
+        }
+
+        Ok(relations)
+    }
+
+    pub async fn set_value<V>(&mut self, key: &str, value: V) -> Result<(), WriteError>
+        where V: Into<Value>
+    {
+        self.check_writable()?;
+        self.head.set_value(&self.context.slab, key, value.into()).await?;
+        self.head.keyframe_if_due(&self.context.slab).await?;
+
+        // Update our indices before returning to ensure that subsequence queries against this context are
+        // self-consistent
+        self.context.update_indices(self.id, &self.head).await?;
+
+        Ok(())
+    }
+
+    /// Set several values by way of a single memo, as when a whole form is saved at once. Keys given `None` are removed.
+    pub async fn set_values<V>(&mut self, vals: HashMap<String, Option<V>>) -> Result<(), WriteError>
+        where V: Into<Value>
+    {
+        self.check_writable()?;
+        if vals.is_empty() {
+            return Ok(());
+        }
+
+        let vals = vals.into_iter().map(|(key, value)| (key, value.map(Into::into))).collect();
+        self.head.set_values(&self.context.slab, vals).await?;
+        self.head.keyframe_if_due(&self.context.slab).await?;
+
+        // Update our indices before returning to ensure that subsequence queries against this context are
+        // self-consistent
+        self.context.update_indices(self.id, &self.head).await?;
+
+        Ok(())
+    }
+
+    /// Unset the key. A value written concurrently with the removal survives it.
+    pub async fn remove_value(&mut self, key: &str) -> Result<(), WriteError> {
+        self.check_writable()?;
+        self.head.remove_value(&self.context.slab, key).await?;
+        self.head.keyframe_if_due(&self.context.slab).await?;
 
         // Update our indices before returning to ensure that subsequence queries against this context are
         // self-consistent
@@ -112,7 +271,47 @@ impl Entity {
     }
 
     pub async fn set_relation(&mut self, key: SlotId, relation: &Self) -> Result<(), WriteError> {
+        self.check_writable()?;
         self.head.set_relation(&self.context.slab, key, &relation.head).await?;
+        self.head.keyframe_if_due(&self.context.slab).await?;
+
+        // Update our indices before returning to ensure that subsequence queries against this context are
+        // self-consistent
+        self.context.update_indices(self.id, &self.head).await?;
+
+        Ok(())
+    }
+
+    /// Set several relations by way of a single memo
+    pub async fn set_relations(&mut self, relations: HashMap<SlotId, &Self>) -> Result<(), WriteError> {
+        self.check_writable()?;
+        if relations.is_empty() {
+            return Ok(());
+        }
+
+        let relationset = RelationSet(relations.into_iter()
+                                               .map(|(key, relation)| (key, Some(relation.id)))
+                                               .collect());
+        self.head.set_relations(&self.context.slab, relationset).await?;
+        self.head.keyframe_if_due(&self.context.slab).await?;
+
+        // Update our indices before returning to ensure that subsequence queries against this context are
+        // self-consistent
+        self.context.update_indices(self.id, &self.head).await?;
+
+        Ok(())
+    }
+
+    /// Set several edges by way of a single memo, each pointing at the present head of the given entity
+    pub async fn set_edges(&mut self, edges: HashMap<SlotId, &Self>) -> Result<(), WriteError> {
+        self.check_writable()?;
+        if edges.is_empty() {
+            return Ok(());
+        }
+
+        let edgeset = EdgeSet(edges.into_iter().map(|(key, target)| (key, target.head.clone())).collect());
+        self.head.set_edges(&self.context.slab, edgeset);
+        self.head.keyframe_if_due(&self.context.slab).await?;
 
         // Update our indices before returning to ensure that subsequence queries against this context are
         // self-consistent
@@ -121,22 +320,245 @@ impl Entity {
         Ok(())
     }
 
+    /// Delete the entity. Once a slab has seen the deletion, the entity is absent from lookups and queries, and it has no
+    /// values or relations. Edits made concurrently with the deletion, or afterwards through an outdated handle, don't
+    /// bring it back.
+    pub async fn delete(&mut self) -> Result<(), WriteError> {
+        self.check_writable()?;
+        self.update_head().await?;
+        let previous = self.head.clone();
+
+        self.head = self.context
+                        .slab
+                        .new_memo(Some(self.id), previous.clone(), MemoBody::Tombstone(self.id.stype))
+                        .to_head();
+
+        self.context.remove_from_indices(self.id, &previous, &self.head).await?;
+
+        Ok(())
+    }
+
+    pub async fn is_deleted(&mut self) -> Result<bool, RetrieveError> {
+        self.update_head().await?;
+
+        self.head.is_deleted(&self.context.slab).await
+    }
+
+    /// Restore the values, relations and edges the entity had at the given head, which is one of its earlier heads. The
+    /// restoration is itself a change, which supersedes everything up to now, so it propagates like any other write,
+    /// and a write made concurrently with it survives it. Reverting a deleted entity brings it back, and reverting to a
+    /// head at which it had been deleted deletes it.
+    ///
+    /// CRDT fields are unaffected, as their operations are merged rather than superseded.
+    pub async fn revert_to(&mut self, head: &Head) -> Result<(), WriteError> {
+        self.check_writable()?;
+
+        let mut then = self.at(head.clone())?;
+        if then.is_deleted().await? {
+            return self.delete().await;
+        }
+
+        self.update_head().await?;
+        let slab = &self.context.slab;
+
+        let v = then.get_all_values().await?;
+        let r = RelationSet(then.head
+                                .get_all_relations(slab)
+                                .await?
+                                .into_iter()
+                                .map(|(slot_id, entity_id)| (slot_id, Some(entity_id)))
+                                .collect());
+        let e = EdgeSet(then.head
+                            .project_occupied_edges(slab)
+                            .await?
+                            .into_iter()
+                            .filter_map(|link| {
+                                match link {
+                                    EdgeLink::Occupied { slot_id, head } => Some((slot_id, head)),
+                                    EdgeLink::Vacant { .. } => None,
+                                }
+                            })
+                            .collect());
+        let o = self.head.get_all_operations(slab).await?;
+
+        self.head = slab.new_memo(Some(self.id),
+                                  self.head.clone(),
+                                  MemoBody::FullyMaterialized { v,
+                                                                r,
+                                                                e,
+                                                                o,
+                                                                t: self.id.stype })
+                        .to_head();
+
+        // Update our indices before returning to ensure that subsequence queries against this context are
+        // self-consistent
+        self.context.update_indices(self.id, &self.head).await?;
+
+        Ok(())
+    }
+
+    /// The value of a CRDT field, projected from every operation upon it which this entity has seen
+    pub async fn get_crdt(&mut self, key: &str) -> Result<Option<CrdtValue>, RetrieveError> {
+        Ok(self.get_crdt_state(key).await?.map(|state| state.to_value()))
+    }
+
+    /// Add to the counter in the given field. Negative amounts decrement it.
+    pub async fn increment(&mut self, key: &str, n: i64) -> Result<(), WriteError> {
+        self.apply_operation(key, CrdtOp::Increment(n)).await
+    }
+
+    /// Add an item to the set in the given field
+    pub async fn set_add(&mut self, key: &str, item: &str) -> Result<(), WriteError> {
+        self.apply_operation(key, CrdtOp::SetAdd(item.to_string())).await
+    }
+
+    /// Remove an item from the set in the given field. An add made concurrently elsewhere is unaffected.
+    pub async fn set_remove(&mut self, key: &str, item: &str) -> Result<(), WriteError> {
+        self.map_set_remove(key, &[], item).await
+    }
+
+    /// Add to the counter at the given path within the map in the given field
+    pub async fn map_increment(&mut self, key: &str, path: &[&str], n: i64) -> Result<(), WriteError> {
+        self.apply_operation(key, CrdtOp::nested(path, CrdtOp::Increment(n))).await
+    }
+
+    /// Add an item to the set at the given path within the map in the given field
+    pub async fn map_set_add(&mut self, key: &str, path: &[&str], item: &str) -> Result<(), WriteError> {
+        self.apply_operation(key, CrdtOp::nested(path, CrdtOp::SetAdd(item.to_string()))).await
+    }
+
+    /// Remove an item from the set at the given path within the map in the given field
+    pub async fn map_set_remove(&mut self, key: &str, path: &[&str], item: &str) -> Result<(), WriteError> {
+        let observed = match self.get_crdt_state(key).await? {
+            Some(state) => state.at_path(path).map(|set| set.observed_tags(item)).unwrap_or_default(),
+            None => Vec::new(),
+        };
+
+        if observed.is_empty() {
+            return Ok(());
+        }
+
+        self.apply_operation(key, CrdtOp::nested(path, CrdtOp::SetRemove(item.to_string(), observed))).await
+    }
+
+    /// Insert text into the sequence in the given field, such that it begins at the given character index. Indices
+    /// beyond the end of the sequence append to it.
+    pub async fn text_insert(&mut self, key: &str, index: usize, text: &str) -> Result<(), WriteError> {
+        let (chars, seq) = match self.get_crdt_state(key).await? {
+            Some(CrdtState::Text { chars, seq }) => (chars, seq),
+            _ => (Vec::new(), 0),
+        };
+
+        let after = match index.min(chars.len()) {
+            0 => None,
+            index => Some(chars[index - 1].0),
+        };
+
+        self.apply_operation(key, CrdtOp::TextInsert { after,
+                                                       seq: seq + 1,
+                                                       text: text.to_string() })
+            .await
+    }
+
+    /// Delete `len` characters from the sequence in the given field, starting at the given character index
+    pub async fn text_delete(&mut self, key: &str, index: usize, len: usize) -> Result<(), WriteError> {
+        let ids: Vec<TextId> = match self.get_crdt_state(key).await? {
+            Some(CrdtState::Text { chars, .. }) => chars.iter().skip(index).take(len).map(|(id, _)| *id).collect(),
+            _ => Vec::new(),
+        };
+
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        self.apply_operation(key, CrdtOp::TextDelete(ids)).await
+    }
+
+    /// Observe the sequence in the given field as it changes, by way of the insertions and deletions which bring the
+    /// previous text up to date. The text present at the outset is delivered as an insertion.
+    pub fn observe_text(&self, key: &str) -> TextObserver {
+        TextObserver::new(self.clone(), key, self.observe())
+    }
+
+    pub(crate) async fn get_crdt_state(&mut self, key: &str) -> Result<Option<CrdtState>, RetrieveError> {
+        self.update_head().await?;
+
+        let operations = self.head.get_operations(&self.context.slab, key).await?;
+        Ok(CrdtState::project(operations))
+    }
+
+    async fn apply_operation(&mut self, key: &str, op: CrdtOp) -> Result<(), WriteError> {
+        self.check_writable()?;
+        self.head.apply_operation(&self.context.slab, key, op);
+        self.head.keyframe_if_due(&self.context.slab).await?;
+
+        // Update our indices before returning to ensure that subsequence queries against this context are
+        // self-consistent
+        self.context.update_indices(self.id, &self.head).await?;
+
+        Ok(())
+    }
+
+    /// The entity as it was at the given head, which is one of its earlier heads, such as that of a `HistoryEntry`.
+    /// Its values are projected from that head alone, disregarding anything since, and it can't be written to.
+    /// Related entities are presented as they are now.
+    pub fn at(&self, head: Head) -> Result<Entity, RetrieveError> {
+        match head.entity_id() {
+            Some(entity_id) if entity_id == self.id => {},
+            Some(_) => return Err(RetrieveError::MemoLineageError),
+            None => return Err(RetrieveError::InvalidHead(InvalidHead::Empty)),
+        }
+
+        Ok(Entity { id: self.id,
+                    head,
+                    context: self.context.clone(),
+                    pinned: true })
+    }
+
+    /// The changes made to the entity, most recent first
+    pub async fn history(&mut self) -> Result<History, RetrieveError> {
+        self.update_head().await?;
+
+        Ok(History::new(&self.head, &self.context.slab))
+    }
+
+    /// The head of the entity as of its last read or write, such as for filing it in an index
+    pub fn head(&self) -> &Head {
+        &self.head
+    }
+
     pub async fn get_all_memo_ids(&self) -> Result<Vec<MemoId>, RetrieveError> {
         self.head.get_all_memo_ids(self.context.slab.clone()).await
     }
 
-    pub fn observe(&self) -> mpsc::Receiver<Head> {
-        let (mut tx, rx) = mpsc::channel(1000);
+    pub fn observe(&self) -> HeadReceiver {
+        let (tx, rx) = head_channel();
 
         // get an initial value, rather than waiting for the value to change?
-        tx.try_send(self.head.clone())
-          .expect("Haven't implemented queue backpressure yet");
+        tx.send(self.head.clone()).ok();
 
         // BUG HERE? - not applying Head to our head here, but double check as to what we were expecting from indexes
         self.context.slab.observe_entity(self.id, tx);
 
         rx
     }
+
+    /// Bring our head up to date with the context, unless we're pinned to a historical one
+    async fn update_head(&mut self) -> Result<bool, RetrieveError> {
+        if self.pinned {
+            return Ok(false);
+        }
+
+        self.context.mut_update_record_head_for_consistency(&mut self.head).await
+    }
+
+    pub(crate) fn check_writable(&self) -> Result<(), WriteError> {
+        if self.pinned {
+            return Err(WriteError::Historical);
+        }
+
+        Ok(())
+    }
 }
 
 // TODO POSTMERGE dig into https://docs.rs/futures-signals/0.3.11/futures_signals/tutorial/index.html and think about API
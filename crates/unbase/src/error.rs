@@ -7,6 +7,8 @@ pub enum RetrieveError {
     IndexNotInitialized,
     SlabError,
     MemoLineageError,
+    /// The value was of the named type, rather than that which was asked for
+    TypeMismatch(&'static str),
     WriteError(Box<WriteError>),
 }
 
@@ -22,6 +24,8 @@ pub enum WriteError {
     RetrieveError(Box<RetrieveError>),
     // This is silly. TODO - break this cycle and remove the Box
     BadTarget,
+    /// The entity is a view of an earlier state, which can't be written to
+    Historical,
 }
 
 #[derive(PartialEq, Debug)]
@@ -50,6 +54,19 @@ pub enum StorageOpDeclined {
     InsufficientPeering,
 }
 
+#[derive(PartialEq, Debug)]
+pub enum StorageError {
+    Io(std::io::ErrorKind),
+    Serialization,
+    Corrupt,
+}
+
+impl core::convert::From<std::io::Error> for StorageError {
+    fn from(error: std::io::Error) -> Self {
+        StorageError::Io(error.kind())
+    }
+}
+
 #[derive(PartialEq, Debug)]
 pub enum PeeringError {
     InsufficientPeering,
@@ -0,0 +1,199 @@
+//! The differences between two heads of the same entity.
+//!
+//! Each head has memos in its causal history which the other lacks, unless they're the same. Where one head descends
+//! from the other, only the descendant has any, whereas two heads which were written concurrently each have their own
+//! branch of the fork. Every field, relation and edge which those memos touch is a candidate change, which is reported
+//! against the side whose memos made it, provided that the two heads actually present it differently.
+
+use super::Head;
+use crate::{
+    crdt::{
+        CrdtState,
+        CrdtValue,
+    },
+    error::RetrieveError,
+    slab::{
+        EntityId,
+        Memo,
+        MemoId,
+        SlabHandle,
+        SlotId,
+    },
+    value::Value,
+};
+
+use std::collections::{
+    BTreeSet,
+    HashMap,
+    HashSet,
+    VecDeque,
+};
+
+/// The head whose memos made a change
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Side {
+    /// The head upon which `diff` was called
+    This,
+    /// The head it was compared with
+    Other,
+}
+
+/// What was changed
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ChangeTarget {
+    /// A value or CRDT field
+    Field(String),
+    Relation(SlotId),
+    Edge(SlotId),
+    /// The entity was deleted, or brought back
+    Deletion,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Change {
+    pub side:   Side,
+    pub target: ChangeTarget,
+}
+
+/// The state of a change target as presented by one head, for comparison with the other
+#[derive(PartialEq)]
+enum Projection {
+    Field(Vec<Value>, Option<CrdtValue>),
+    Relation(Option<EntityId>),
+    Edge(Vec<MemoId>),
+    Deletion(bool),
+}
+
+impl Head {
+    /// The changes between this head and another of the same entity, each attributed to the head whose memos made it.
+    /// Where the heads were written concurrently, the same target may have been changed on both sides.
+    pub async fn diff(&self, other: &Head, slab: &SlabHandle) -> Result<BTreeSet<Change>, RetrieveError> {
+        match (self.entity_id(), other.entity_id()) {
+            (Some(a), Some(b)) if a != b => return Err(RetrieveError::MemoLineageError),
+            _ => {},
+        }
+
+        let this_descends = self.descends_or_contains(other, slab).await?;
+        let other_descends = other.descends_or_contains(self, slab).await?;
+        if this_descends && other_descends {
+            return Ok(BTreeSet::new());
+        }
+
+        let this_memos = self.causal_memos(slab).await?;
+        let other_memos = other.causal_memos(slab).await?;
+
+        let mut candidates = BTreeSet::new();
+        if !other_descends {
+            candidates.extend(Self::changes_made_by(Side::This, &this_memos, &other_memos));
+        }
+        if !this_descends {
+            candidates.extend(Self::changes_made_by(Side::Other, &other_memos, &this_memos));
+        }
+
+        // Changes may have been undone, or made identically on both sides of a fork
+        let mut differs: HashMap<ChangeTarget, bool> = HashMap::new();
+        let mut changes = BTreeSet::new();
+        for change in candidates {
+            let differ = match differs.get(&change.target) {
+                Some(differ) => *differ,
+                None => {
+                    let differ = self.project(&change.target, slab).await? != other.project(&change.target, slab).await?;
+                    differs.insert(change.target.clone(), differ);
+                    differ
+                },
+            };
+
+            if differ {
+                changes.insert(change);
+            }
+        }
+
+        Ok(changes)
+    }
+
+    /// Every memo in the causal history of this head, once each
+    async fn causal_memos(&self, slab: &SlabHandle) -> Result<HashMap<MemoId, Memo>, RetrieveError> {
+        let mut queue: VecDeque<_> = self.to_vecdeque();
+        let mut memos = HashMap::new();
+
+        while let Some(memoref) = queue.pop_front() {
+            if memos.contains_key(&memoref.id) {
+                continue;
+            }
+
+            let memo = memoref.get_memo(slab.clone()).await?;
+            queue.extend(memo.get_parent_head().iter().cloned());
+            memos.insert(memo.id, memo);
+        }
+
+        Ok(memos)
+    }
+
+    /// The targets touched by those of our memos which the other history lacks
+    fn changes_made_by(side: Side, ours: &HashMap<MemoId, Memo>, theirs: &HashMap<MemoId, Memo>) -> HashSet<Change> {
+        let mut changes = HashSet::new();
+        let mut add = |target| {
+            changes.insert(Change { side, target });
+        };
+
+        for (memo_id, memo) in ours.iter() {
+            if theirs.contains_key(memo_id) {
+                continue;
+            }
+
+            for key in memo.get_changed_keys() {
+                add(ChangeTarget::Field(key));
+            }
+            if let Some((relations, _)) = memo.get_relations() {
+                for slot_id in relations.0.keys() {
+                    add(ChangeTarget::Relation(*slot_id));
+                }
+            }
+            if let Some((edges, _)) = memo.get_edges() {
+                for slot_id in edges.0.keys() {
+                    add(ChangeTarget::Edge(*slot_id));
+                }
+            }
+            // A materialized memo which follows a tombstone brings the entity back
+            if memo.get_values().is_some_and(|(_, materialized)| materialized) {
+                add(ChangeTarget::Deletion);
+            }
+        }
+
+        changes
+    }
+
+    async fn project(&self, target: &ChangeTarget, slab: &SlabHandle) -> Result<Projection, RetrieveError> {
+        if let Head::Null = self {
+            return Ok(match target {
+                          ChangeTarget::Field(_) => Projection::Field(Vec::new(), None),
+                          ChangeTarget::Relation(_) => Projection::Relation(None),
+                          ChangeTarget::Edge(_) => Projection::Edge(Vec::new()),
+                          ChangeTarget::Deletion => Projection::Deletion(false),
+                      });
+        }
+
+        Ok(match target {
+               ChangeTarget::Field(key) => {
+                   let mut values: Vec<Value> =
+                       self.get_concurrent_values(slab, key).await?.into_iter().map(|v| v.value).collect();
+                   values.sort_by_key(|v| v.to_string());
+
+                   let crdt = CrdtState::project(self.get_operations(slab, key).await?).map(|state| state.to_value());
+
+                   Projection::Field(values, crdt)
+               },
+               ChangeTarget::Relation(slot_id) => {
+                   Projection::Relation(self.get_all_relations(slab).await?.get(slot_id).cloned())
+               },
+               ChangeTarget::Edge(slot_id) => {
+                   let edge = self.clone().get_edge(slab, *slot_id).await?;
+                   let mut memo_ids = edge.map(|head| head.memo_ids()).unwrap_or_default();
+                   memo_ids.sort_unstable();
+
+                   Projection::Edge(memo_ids)
+               },
+               ChangeTarget::Deletion => Projection::Deletion(self.is_deleted(slab).await?),
+           })
+    }
+}
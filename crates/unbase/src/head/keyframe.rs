@@ -0,0 +1,171 @@
+//! Keyframes, which bound the number of memos a projection must traverse.
+//!
+//! Every edit lengthens the chain of memos which `get_value` and friends walk back through, fetching them from other
+//! slabs where they aren't resident, until they reach a materialized memo. A keyframe is a fully materialized memo which
+//! presents the same state as the head it descends, such that projections stop there. CRDT fields are merged from every
+//! operation in the causal history, so the keyframe carries those operations as they are. Keyframes on concurrent
+//! branches carry the operations they have in common, which are merged as one.
+
+use super::Head;
+use crate::{
+    error::{
+        RetrieveError,
+        WriteError,
+    },
+    slab::{
+        EdgeSet,
+        EntityId,
+        EntityType,
+        KeyframePolicy,
+        MemoBody,
+        MemoId,
+        RelationSet,
+        SlabHandle,
+    },
+};
+
+use std::collections::{
+    HashMap,
+    VecDeque,
+};
+
+impl Head {
+    /// Rewrite a record as a keyframe if the slab's `KeyframePolicy` calls for one, returning whether it did. Heads which
+    /// can't be materialized are left as they are.
+    pub async fn keyframe_if_due(&mut self, slab: &SlabHandle) -> Result<bool, WriteError> {
+        let body = match self.keyframe_body_if_due(slab).await? {
+            Some(body) => body,
+            None => return Ok(false),
+        };
+
+        let entity_id = self.entity_id();
+        let mut parents = Head::Null;
+        std::mem::swap(self, &mut parents);
+
+        let mut new_head = slab.new_memo(entity_id, parents, body).to_head();
+
+        std::mem::swap(self, &mut new_head);
+
+        Ok(true)
+    }
+
+    /// The body of the keyframe which `keyframe_if_due` would write, if any, for callers which write it themselves
+    pub(crate) async fn keyframe_body_if_due(&self, slab: &SlabHandle) -> Result<Option<MemoBody>, RetrieveError> {
+        match self.entity_id() {
+            Some(EntityId { stype: EntityType::Record,
+                            .. }) => {},
+            _ => return Ok(None),
+        };
+
+        if !self.keyframe_due(slab, &slab.keyframe_policy()).await? {
+            return Ok(None);
+        }
+
+        self.materialize(slab).await
+    }
+
+    /// Whether the memos since the most recent materialized memo of each causal branch exceed either limit of the policy
+    async fn keyframe_due(&self, slab: &SlabHandle, policy: &KeyframePolicy) -> Result<bool, RetrieveError> {
+        if policy.max_edits.is_none() && policy.max_depth.is_none() {
+            return Ok(false);
+        }
+
+        // The parents of each memo since materialization, so far as they are likewise unmaterialized
+        let mut parents: HashMap<MemoId, Vec<MemoId>> = HashMap::new();
+        let mut queue: VecDeque<_> = self.to_vecdeque();
+
+        while let Some(memoref) = queue.pop_front() {
+            if parents.contains_key(&memoref.id) {
+                continue;
+            }
+
+            let memo = memoref.get_memo(slab.clone()).await?;
+            if memo.get_values().is_some_and(|(_, materialized)| materialized) {
+                continue;
+            }
+
+            let parent_head = memo.get_parent_head();
+            parents.insert(memo.id, parent_head.memo_ids());
+            queue.extend(parent_head.iter().cloned());
+
+            if policy.max_edits.is_some_and(|max| parents.len() > max) {
+                return Ok(true);
+            }
+        }
+
+        Ok(match policy.max_depth {
+               Some(max) => Self::longest_chain(&parents, &self.memo_ids()) > max,
+               None => false,
+           })
+    }
+
+    /// The number of memos in the longest chain from any of the given memos back through their parents
+    fn longest_chain(parents: &HashMap<MemoId, Vec<MemoId>>, from: &[MemoId]) -> usize {
+        let mut lengths: HashMap<MemoId, usize> = HashMap::new();
+        let mut stack: Vec<(MemoId, bool)> = from.iter().map(|memo_id| (*memo_id, false)).collect();
+
+        while let Some((memo_id, expanded)) = stack.pop() {
+            let memo_parents = match parents.get(&memo_id) {
+                Some(memo_parents) => memo_parents,
+                None => continue,
+            };
+
+            if expanded {
+                let longest = memo_parents.iter().filter_map(|p| lengths.get(p)).max().copied().unwrap_or(0);
+                lengths.insert(memo_id, longest + 1);
+            } else if !lengths.contains_key(&memo_id) {
+                stack.push((memo_id, true));
+                stack.extend(memo_parents.iter().map(|p| (*p, false)));
+            }
+        }
+
+        from.iter().filter_map(|memo_id| lengths.get(memo_id)).max().copied().unwrap_or(0)
+    }
+
+    /// A fully materialized memo body which presents the same values, relations, edges and CRDT fields as this head. None
+    /// if the head can't be summarized by one, because the entity is deleted, or because some key has differing
+    /// concurrent values which must be retained as such.
+    pub async fn materialize(&self, slab: &SlabHandle) -> Result<Option<MemoBody>, RetrieveError> {
+        let stype = match self.entity_id() {
+            Some(entity_id) => entity_id.stype,
+            None => return Ok(None),
+        };
+
+        if self.is_deleted(slab).await? {
+            return Ok(None);
+        }
+
+        let mut v = HashMap::new();
+        for key in self.get_all_keys(slab).await? {
+            let values = self.get_concurrent_values(slab, &key).await?;
+            match values.split_first() {
+                None => {},
+                // Such as where keyframes of concurrent branches present the same value
+                Some((first, rest)) if rest.iter().all(|other| other.value == first.value) => {
+                    v.insert(key, first.value.clone());
+                },
+                Some(_) => return Ok(None),
+            }
+        }
+
+        let r = RelationSet(self.get_all_relations(slab)
+                                .await?
+                                .into_iter()
+                                .map(|(slot_id, entity_id)| (slot_id, Some(entity_id)))
+                                .collect());
+        let mut e = EdgeSet::empty();
+        for (slot_id, heads) in self.get_all_concurrent_edges(slab).await? {
+            match heads.split_first() {
+                None => {},
+                Some((first, rest)) if rest.iter().all(|other| other == first) => {
+                    e.insert(slot_id, first.clone());
+                },
+                Some(_) => return Ok(None),
+            }
+        }
+
+        let o = self.get_all_operations(slab).await?;
+
+        Ok(Some(MemoBody::FullyMaterialized { v, r, e, o, t: stype }))
+    }
+}
@@ -1,10 +1,26 @@
+mod diff;
+mod keyframe;
 pub mod serde;
 
+pub use self::diff::{
+    Change,
+    ChangeTarget,
+    Side,
+};
+
 use crate::{
+    crdt::{
+        CrdtOp,
+        FieldOperations,
+    },
     error::{
         RetrieveError,
         WriteError,
     },
+    merge::{
+        ConcurrentValue,
+        MergePolicy,
+    },
     slab::{
         EdgeLink,
         EdgeSet,
@@ -21,11 +37,14 @@ use crate::{
         SlotId,
         MAX_SLOTS,
     },
+    value::Value,
 };
 
 use std::{
     collections::{
+        BTreeMap,
         HashMap,
+        HashSet,
         VecDeque,
     },
     fmt,
@@ -82,14 +101,21 @@ pub struct HeadWithProvenance {
 /// Head takes &SlabHandle on all calls, because it is an agent of storage and referentiality, NOT an enforcer of
 /// consistency
 impl Head {
-    pub fn new_index(slab: &SlabHandle, values: HashMap<String, String>) -> Head {
+    pub fn new_index(slab: &SlabHandle, values: HashMap<String, Value>) -> Head {
         let id = slab.generate_entity_id(EntityType::IndexNode);
 
+        Self::new_index_with_id(slab, id, values)
+    }
+
+    /// As with `new_index`, but for an index node whose id was agreed upon in advance rather than issued by the slab.
+    /// Every slab which creates the node with the same values creates the same memo.
+    pub fn new_index_with_id(slab: &SlabHandle, id: EntityId, values: HashMap<String, Value>) -> Head {
         slab.new_memo(Some(id),
                       Head::Null,
                       MemoBody::FullyMaterialized { v: values,
                                                     r: RelationSet::empty(),
                                                     e: EdgeSet::empty(),
+                                                    o: HashMap::new(),
                                                     t: EntityType::IndexNode, })
             .to_head()
     }
@@ -384,83 +410,331 @@ impl Head {
         Ok(true)
     }
 
-    /// Notify whomever needs to know that a new entity has been created
-    #[tracing::instrument]
-    pub async fn get_value(&mut self, slab: &SlabHandle, key: &str) -> Result<Option<String>, RetrieveError> {
-        // TODO: consider creating a consolidated projection routine for most/all uses
-        let mut memostream = self.causal_memo_stream(slab.clone()).boxed();
-        while let Some(memo) = memostream.next().await {
-            // println!("# \t\\ Considering Memo {}", memo.id );
-            if let Some((values, materialized)) = memo?.get_values() {
-                if let Some(v) = values.get(key) {
-                    return Ok(Some(v.clone()));
-                } else if materialized {
-                    return Ok(None); // end of the line here
+    /// The value presented for the key by the given merge policy. Where the policy presents several concurrent values,
+    /// this is the first of them.
+    pub async fn get_value(&self, slab: &SlabHandle, policy: &dyn MergePolicy, key: &str)
+                           -> Result<Option<Value>, RetrieveError> {
+        Ok(self.get_merged_values(slab, policy, key).await?.into_iter().next())
+    }
+
+    /// Every value presented for the key by the given merge policy
+    pub async fn get_merged_values(&self, slab: &SlabHandle, policy: &dyn MergePolicy, key: &str)
+                                   -> Result<Vec<Value>, RetrieveError> {
+        let values = self.get_concurrent_values(slab, key).await?;
+        if values.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        Ok(policy.merge(key, values))
+    }
+
+    /// Every key which has been written or removed along any causal branch since that branch was last materialized
+    pub async fn get_all_keys(&self, slab: &SlabHandle) -> Result<Vec<String>, RetrieveError> {
+        let mut queue = self.to_vecdeque();
+        let mut visited = HashSet::new();
+        let mut keys = HashSet::new();
+
+        // Each branch is followed back as far as its most recent materialization
+        while let Some(memoref) = queue.pop_front() {
+            if !visited.insert(memoref.id) {
+                continue;
+            }
+
+            let memo = memoref.get_memo(slab.clone()).await?;
+            if memo.is_tombstone() {
+                return Ok(Vec::new());
+            }
+
+            match memo.get_values() {
+                Some((values, materialized)) => {
+                    keys.extend(values.into_keys());
+                    if !materialized {
+                        queue.extend(memo.get_parent_head().iter().cloned());
+                    }
+                },
+                None => queue.extend(memo.get_parent_head().iter().cloned()),
+            }
+        }
+
+        let mut keys: Vec<String> = keys.into_iter().collect();
+        keys.sort();
+        Ok(keys)
+    }
+
+    /// Every occupied relation. As with `get_relation`, the most recent write to each slot prevails.
+    pub async fn get_all_relations(&self, slab: &SlabHandle) -> Result<HashMap<SlotId, EntityId>, RetrieveError> {
+        let mut queue = self.to_vecdeque();
+        let mut visited = HashSet::new();
+        let mut slot_ids = HashSet::new();
+
+        // Each branch is followed back as far as its most recent materialization
+        while let Some(memoref) = queue.pop_front() {
+            if !visited.insert(memoref.id) {
+                continue;
+            }
+
+            let memo = memoref.get_memo(slab.clone()).await?;
+            if memo.is_tombstone() {
+                return Ok(HashMap::new());
+            }
+
+            match memo.get_relations() {
+                Some((relationset, materialized)) => {
+                    slot_ids.extend(relationset.0.keys().cloned());
+                    if !materialized {
+                        queue.extend(memo.get_parent_head().iter().cloned());
+                    }
+                },
+                None => queue.extend(memo.get_parent_head().iter().cloned()),
+            }
+        }
+
+        let mut relations = HashMap::new();
+        for slot_id in slot_ids {
+            if let Some(entity_id) = self.get_relation(slab, slot_id).await? {
+                relations.insert(slot_id, entity_id);
+            }
+        }
+
+        Ok(relations)
+    }
+
+    /// Whether the entity has been deleted. A deletion prevails over writes made concurrently with it, so this is the
+    /// case if any causal branch reaches a tombstone before it reaches a fully materialized memo.
+    pub async fn is_deleted(&self, slab: &SlabHandle) -> Result<bool, RetrieveError> {
+        let mut queue = self.to_vecdeque();
+        let mut visited = HashSet::new();
+
+        while let Some(memoref) = queue.pop_front() {
+            if !visited.insert(memoref.id) {
+                continue;
+            }
+
+            let memo = memoref.get_memo(slab.clone()).await?;
+            match memo.body {
+                MemoBody::Tombstone(_) => return Ok(true),
+                MemoBody::FullyMaterialized { .. } => {},
+                _ => queue.extend(memo.get_parent_head().iter().cloned()),
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// The values of the most recent writes to the key, one per concurrent write.
+    ///
+    /// Each causal branch is followed back until it reaches a memo which writes the key, or one which is fully
+    /// materialized. Any write which some other such memo descends has been superseded, and is excluded. Should any
+    /// branch reach a tombstone, the entity is deleted, and there are no values at all.
+    pub async fn get_concurrent_values(&self, slab: &SlabHandle, key: &str) -> Result<Vec<ConcurrentValue>, RetrieveError> {
+        let memos = match self.latest_on_each_branch(slab, |memo| {
+                                  match memo.get_values() {
+                                      Some((values, materialized)) => materialized || values.contains_key(key),
+                                      None => false,
+                                  }
+                              })
+                              .await?
+        {
+            Some(memos) => memos,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut concurrent = Vec::new();
+        for memo in memos {
+            if let Some((mut values, _)) = memo.get_values() {
+                if let Some(value) = values.remove(key).flatten() {
+                    concurrent.push(ConcurrentValue { memo_id: memo.id,
+                                                      value });
                 }
             }
         }
 
-        Err(RetrieveError::MemoLineageError)
+        Ok(concurrent)
     }
 
-    pub async fn get_relation(&mut self, slab: &SlabHandle, key: SlotId) -> Result<Option<EntityId>, RetrieveError> {
-        // println!("# Entity({}).get_relation({})",self.id,key);
+    /// The entity related by the given slot, if it's occupied.
+    ///
+    /// Relations are resolved in the same manner as `LastWriterWins` resolves values: of the most recent writes to the
+    /// slot on each causal branch, that of the greatest memo id prevails, such that every slab agrees on it.
+    pub async fn get_relation(&self, slab: &SlabHandle, key: SlotId) -> Result<Option<EntityId>, RetrieveError> {
+        let memos = match self.latest_on_each_branch(slab, |memo| {
+                                  match memo.get_relations() {
+                                      Some((relations, materialized)) => materialized || relations.contains_key(&key),
+                                      None => false,
+                                  }
+                              })
+                              .await?
+        {
+            Some(memos) => memos,
+            None => return Ok(None),
+        };
 
-        let mut memostream = self.causal_memo_stream(slab.clone());
-        while let Some(memo) = memostream.next().await {
-            let memo = memo?;
-            if let Some((relations, materialized)) = memo.get_relations() {
-                debug!("# \t\\ Considering Memo {}, Head: {:?}, Relations: {:?}",
-                       memo.id,
-                       memo.get_parent_head(),
-                       relations);
-                if let Some(maybe_entity_id) = relations.get(&key) {
-                    return match *maybe_entity_id {
-                        Some(entity_id) => Ok(Some(entity_id)),
-                        None => Ok(None),
-                    };
-                } else if materialized {
-                    debug!("\n# \t\\ Not Found (materialized)");
-                    return Ok(None);
+        let mut latest: Option<(MemoId, EntityId)> = None;
+        for memo in memos {
+            if let Some((relations, _)) = memo.get_relations() {
+                if let Some(&Some(entity_id)) = relations.get(&key) {
+                    if latest.is_none_or(|(memo_id, _)| memo.id > memo_id) {
+                        latest = Some((memo.id, entity_id));
+                    }
                 }
             }
         }
 
-        debug!("Not Found");
-        Err(RetrieveError::MemoLineageError)
+        Ok(latest.map(|(_, entity_id)| entity_id))
     }
 
+    /// Follow each causal branch back until it reaches a memo which `ends_branch` accepts, returning those memos which
+    /// are not descended by any other of them. Should any branch reach a tombstone, the entity is deleted, and there
+    /// are none at all.
+    async fn latest_on_each_branch<F>(&self, slab: &SlabHandle, ends_branch: F) -> Result<Option<Vec<Memo>>, RetrieveError>
+        where F: Fn(&Memo) -> bool
+    {
+        let mut queue = self.to_vecdeque();
+        let mut visited = HashSet::new();
+        let mut found: Vec<(MemoRef, Memo)> = Vec::new();
+
+        while let Some(memoref) = queue.pop_front() {
+            if !visited.insert(memoref.id) {
+                continue;
+            }
+
+            let memo = memoref.clone().get_memo(slab.clone()).await?;
+            if memo.is_tombstone() {
+                return Ok(None);
+            }
+
+            if ends_branch(&memo) {
+                found.push((memoref, memo));
+            } else {
+                queue.extend(memo.get_parent_head().iter().cloned());
+            }
+        }
+
+        let mut latest = Vec::new();
+        'found: for (i, (memoref, memo)) in found.iter().enumerate() {
+            for (j, (other, _)) in found.iter().enumerate() {
+                if i != j && other.descends(memoref, slab).await? {
+                    continue 'found;
+                }
+            }
+
+            latest.push(memo.clone());
+        }
+
+        Ok(Some(latest))
+    }
+
+    /// The head of the given edge, if it's occupied. Where the edge was written concurrently, the heads written by the
+    /// latest memo on each causal branch are merged, such that edits to the target which were made on different slabs
+    /// are all retained, while those writes which a branch has since superseded are not.
     pub async fn get_edge(&mut self, slab: &SlabHandle, key: SlotId) -> Result<Option<Head>, RetrieveError> {
-        let mut memostream = self.causal_memo_stream(slab.clone());
+        let mut merged: Option<Head> = None;
+        for head in self.get_concurrent_edges(slab, key).await? {
+            merged = Some(match merged {
+                              Some(merged) => merged.apply(&head, slab).await?.0,
+                              None => head,
+                          });
+        }
 
-        while let Some(memo) = memostream.next().await {
-            let memo = memo?;
-            if let Some((edges, materialized)) = memo.get_edges() {
-                debug!("# \t\\ Considering Memo {}, Head: {:?}, Relations: {:?}",
+        Ok(merged)
+    }
+
+    /// The heads which the most recent write to the given edge on each causal branch left it occupied by. See
+    /// `get_concurrent_values`.
+    pub async fn get_concurrent_edges(&self, slab: &SlabHandle, key: SlotId) -> Result<Vec<Head>, RetrieveError> {
+        let memos = match self.latest_on_each_branch(slab, |memo| {
+                                  match memo.get_edges() {
+                                      Some((edges, materialized)) => materialized || edges.contains_key(&key),
+                                      None => false,
+                                  }
+                              })
+                              .await?
+        {
+            Some(memos) => memos,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut concurrent = Vec::new();
+        for memo in memos {
+            if let Some((edges, _)) = memo.get_edges() {
+                debug!("# \t\\ Considering Memo {}, Head: {:?}, Edges: {:?}",
                        memo.id,
                        memo.get_parent_head(),
                        edges);
 
-                if let Some(head) = edges.get(&key) {
-                    // TODO POSTMERGE this is likely buggy - shouldn't we be looking at all of the memorefs in the head
-                    // in case of concurrencies?
-
-                    return Ok(Some(head.clone()));
-                } else if materialized {
-                    debug!("\n# \t\\ Not Found (materialized)");
-                    return Ok(None);
+                if let Some(head) = edges.get(&key).filter(|head| head.is_some()) {
+                    concurrent.push(head.clone());
                 }
             }
         }
 
-        debug!("Not Found");
-        Err(RetrieveError::MemoLineageError)
+        Ok(concurrent)
     }
 
-    pub async fn set_value(&mut self, slab: &SlabHandle, key: &str, value: &str) -> Result<(), WriteError> {
+    /// Every occupied edge, with the heads which occupy it on each causal branch. See `get_concurrent_edges`.
+    pub async fn get_all_concurrent_edges(&self, slab: &SlabHandle) -> Result<HashMap<SlotId, Vec<Head>>, RetrieveError> {
+        let mut queue = self.to_vecdeque();
+        let mut visited = HashSet::new();
+        let mut slot_ids = HashSet::new();
+
+        // Each branch is followed back as far as its most recent materialization
+        while let Some(memoref) = queue.pop_front() {
+            if !visited.insert(memoref.id) {
+                continue;
+            }
+
+            let memo = memoref.get_memo(slab.clone()).await?;
+            if memo.is_tombstone() {
+                return Ok(HashMap::new());
+            }
+
+            match memo.get_edges() {
+                Some((edgeset, materialized)) => {
+                    slot_ids.extend(edgeset.0.keys().cloned());
+                    if !materialized {
+                        queue.extend(memo.get_parent_head().iter().cloned());
+                    }
+                },
+                None => queue.extend(memo.get_parent_head().iter().cloned()),
+            }
+        }
+
+        let mut edges = HashMap::new();
+        for slot_id in slot_ids {
+            let heads = self.get_concurrent_edges(slab, slot_id).await?;
+            if !heads.is_empty() {
+                edges.insert(slot_id, heads);
+            }
+        }
+
+        Ok(edges)
+    }
+
+    pub async fn set_value(&mut self, slab: &SlabHandle, key: &str, value: Value) -> Result<(), WriteError> {
+        let mut vals = HashMap::new();
+        vals.insert(key.to_string(), Some(value));
+
+        self.edit(slab, vals)
+    }
+
+    /// Unset the key, such that it has no value until it's set again
+    pub async fn remove_value(&mut self, slab: &SlabHandle, key: &str) -> Result<(), WriteError> {
         let mut vals = HashMap::new();
-        vals.insert(key.to_string(), value.to_string());
+        vals.insert(key.to_string(), None);
+
+        self.edit(slab, vals)
+    }
+
+    /// Set or remove several keys by way of a single memo. Nothing is written if there are none.
+    pub async fn set_values(&mut self, slab: &SlabHandle, vals: HashMap<String, Option<Value>>) -> Result<(), WriteError> {
+        if vals.is_empty() {
+            return Ok(());
+        }
 
+        self.edit(slab, vals)
+    }
+
+    fn edit(&mut self, slab: &SlabHandle, vals: HashMap<String, Option<Value>>) -> Result<(), WriteError> {
         let entity_id = self.entity_id();
 
         // TODO - do this in a single swap? (fairly certain that requires unsafe)
@@ -477,13 +751,74 @@ impl Head {
         Ok(())
     }
 
+    /// Every operation upon the key in the causal history of this head, along with the id of the memo which carries it.
+    /// See `get_all_operations`.
+    pub async fn get_operations(&self, slab: &SlabHandle, key: &str) -> Result<Vec<(MemoId, CrdtOp)>, RetrieveError> {
+        Ok(self.get_all_operations(slab).await?.remove(key).unwrap_or_default())
+    }
+
+    /// Every operation upon CRDT fields in the causal history of this head, by key, along with the id of the memo which
+    /// carries it. Unlike values, operations are never superseded, but materialized memos carry those before them, so
+    /// each causal branch is followed back only as far as its most recent materialization. Should any branch reach a
+    /// tombstone, the entity is deleted, and there are none at all.
+    pub async fn get_all_operations(&self, slab: &SlabHandle) -> Result<FieldOperations, RetrieveError> {
+        let mut queue = self.to_vecdeque();
+        let mut visited = HashSet::new();
+        // Keyed by memo id, as materialized memos of concurrent branches carry the operations they have in common
+        let mut operations: HashMap<String, BTreeMap<MemoId, CrdtOp>> = HashMap::new();
+
+        while let Some(memoref) = queue.pop_front() {
+            if !visited.insert(memoref.id) {
+                continue;
+            }
+
+            let memo = memoref.get_memo(slab.clone()).await?;
+            if memo.is_tombstone() {
+                return Ok(HashMap::new());
+            }
+
+            match memo.get_operations() {
+                Some((ops, materialized)) => {
+                    for (key, ops) in ops {
+                        operations.entry(key).or_default().extend(ops);
+                    }
+                    if !materialized {
+                        queue.extend(memo.get_parent_head().iter().cloned());
+                    }
+                },
+                None => queue.extend(memo.get_parent_head().iter().cloned()),
+            }
+        }
+
+        Ok(operations.into_iter().map(|(key, ops)| (key, ops.into_iter().collect())).collect())
+    }
+
+    pub fn apply_operation(&mut self, slab: &SlabHandle, key: &str, op: CrdtOp) {
+        let mut ops = HashMap::new();
+        ops.insert(key.to_string(), op);
+
+        let entity_id = self.entity_id();
+
+        let mut parents = Head::Null;
+        std::mem::swap(self, &mut parents);
+
+        let mut new_head = slab.new_memo(entity_id, parents, MemoBody::Operation(ops)).to_head();
+
+        std::mem::swap(self, &mut new_head);
+    }
+
     pub async fn set_relation(&mut self, slab: &SlabHandle, key: SlotId, relation: &Self) -> Result<(), WriteError> {
         // println!("# Entity({}).set_relation({}, {})", &self.id, key, relation.id);
-        let mut relationset = RelationSet::empty();
-
         let entity_id = relation.entity_id().ok_or(WriteError::BadTarget)?;
 
-        relationset.insert(key, entity_id);
+        self.set_relations(slab, RelationSet::single(key, entity_id)).await
+    }
+
+    /// Set several relations by way of a single memo. Nothing is written if there are none.
+    pub async fn set_relations(&mut self, slab: &SlabHandle, relationset: RelationSet) -> Result<(), WriteError> {
+        if relationset.is_empty() {
+            return Ok(());
+        }
 
         let entity_id = self.entity_id();
 
@@ -501,14 +836,34 @@ impl Head {
         Ok(())
     }
 
+    /// Supersede this head with a new memo of the given body, without sending it to anyone, returning the memo such that
+    /// the caller may announce it once it's ready to. See `SlabHandle::announce_memo`.
+    pub(crate) fn write_unannounced(&mut self, slab: &SlabHandle, body: MemoBody) -> MemoRef {
+        let entity_id = self.entity_id();
+
+        let mut parents = Head::Null;
+        std::mem::swap(self, &mut parents);
+
+        let memoref = slab.new_memo_unannounced(entity_id, parents, body);
+        *self = memoref.to_head();
+
+        memoref
+    }
+
     pub fn set_edge(&mut self, slab: &SlabHandle, key: SlotId, target: Head) {
         debug!("# Entity({:?}).set_edge({}, {:?})",
                &self.entity_id(),
                key,
                target.entity_id());
 
-        let mut edgeset = EdgeSet::empty();
-        edgeset.insert(key, target);
+        self.set_edges(slab, EdgeSet::single(key, target));
+    }
+
+    /// Set several edges by way of a single memo. Nothing is written if there are none.
+    pub fn set_edges(&mut self, slab: &SlabHandle, edgeset: EdgeSet) {
+        if edgeset.is_empty() {
+            return;
+        }
 
         let entity_id = self.entity_id();
 
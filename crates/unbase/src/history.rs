@@ -0,0 +1,126 @@
+//! The history of an entity, as recorded by its memos.
+//!
+//! Memos are immutable, so the history of an entity is simply the causal history of its head, most recent first. Each
+//! memo in it marks a state the entity passed through, which may be read back by way of `Entity::at`.
+
+use crate::{
+    error::RetrieveError,
+    head::{
+        CausalMemoStream,
+        Head,
+    },
+    slab::{
+        Memo,
+        MemoId,
+        SlabHandle,
+        SlabId,
+        SlotId,
+    },
+};
+
+use futures::{
+    task::{
+        Context,
+        Poll,
+    },
+    Stream,
+    StreamExt,
+};
+use std::{
+    collections::HashSet,
+    pin::Pin,
+};
+
+/// A change made to an entity by one of its memos
+#[derive(Clone, Debug)]
+pub struct HistoryEntry {
+    pub memo_id:        MemoId,
+    /// The slab which made the change
+    pub origin_slab_id: SlabId,
+    /// The keys of the values and CRDT fields which were written or removed, in sorted order
+    pub fields:         Vec<String>,
+    pub relations:      Vec<SlotId>,
+    pub edges:          Vec<SlotId>,
+    /// Whether the memo supersedes everything before it, in which case the change lists everything the entity had
+    pub materialized:   bool,
+    pub deleted:        bool,
+    /// The head of the entity as of the change, from which its state at the time may be projected
+    pub head:           Head,
+}
+
+impl HistoryEntry {
+    fn from_memo(memo: &Memo, head: Head) -> HistoryEntry {
+        let mut fields = memo.get_changed_keys();
+        fields.sort();
+
+        let mut materialized = memo.get_values().is_some_and(|(_, m)| m);
+
+        let mut relations: Vec<SlotId> = match memo.get_relations() {
+            Some((relationset, m)) => {
+                materialized |= m;
+                relationset.0.into_keys().collect()
+            },
+            None => Vec::new(),
+        };
+        relations.sort_unstable();
+
+        let mut edges: Vec<SlotId> = match memo.get_edges() {
+            Some((edgeset, _)) => edgeset.0.into_keys().collect(),
+            None => Vec::new(),
+        };
+        edges.sort_unstable();
+
+        HistoryEntry { memo_id: memo.id,
+                       origin_slab_id: memo.origin_slab_id,
+                       fields,
+                       relations,
+                       edges,
+                       materialized,
+                       deleted: memo.is_tombstone(),
+                       head }
+    }
+}
+
+/// The changes made to an entity, most recent first. Concurrent changes are interleaved, and each memo is reported once,
+/// however many paths through the history lead to it.
+pub struct History {
+    memos: CausalMemoStream,
+    seen:  HashSet<MemoId>,
+    slab:  SlabHandle,
+}
+
+impl History {
+    pub(crate) fn new(head: &Head, slab: &SlabHandle) -> History {
+        History { memos: head.causal_memo_stream(slab.clone()),
+                  seen:  HashSet::new(),
+                  slab:  slab.clone(), }
+    }
+}
+
+impl Stream for History {
+    type Item = Result<HistoryEntry, RetrieveError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            match this.memos.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(memo))) => {
+                    if !this.seen.insert(memo.id) {
+                        continue;
+                    }
+
+                    let head = match this.slab.get_memoref(memo.id) {
+                        Some(memoref) => memoref.to_head(),
+                        None => return Poll::Ready(Some(Err(RetrieveError::NotFound))),
+                    };
+
+                    return Poll::Ready(Some(Ok(HistoryEntry::from_memo(&memo, head))));
+                },
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
@@ -0,0 +1,671 @@
+use crate::{
+    context::Context,
+    entity::Entity,
+    error::{
+        RetrieveError,
+        WriteError,
+    },
+    head::Head,
+    slab::{
+        EdgeSet,
+        EntityId,
+        EntityType,
+        MemoBody,
+        MemoId,
+        MemoRef,
+        RelationSet,
+        SlotId,
+    },
+    value::Value,
+};
+
+use super::Index;
+
+use async_trait::async_trait;
+use futures::stream::{
+    self,
+    BoxStream,
+    StreamExt,
+    TryStreamExt,
+};
+use std::{
+    cmp::Ordering,
+    collections::{
+        hash_map::Entry,
+        BTreeMap,
+        HashMap,
+        HashSet,
+    },
+    fmt,
+    mem,
+    ops::{
+        Bound,
+        RangeBounds,
+    },
+};
+
+use tracing::debug;
+
+/// Maximum number of entries in a node before it is split. Must be less than half of `MAX_SLOTS`, as the versions of a
+/// node written concurrently are merged.
+const MAX_ENTRIES: usize = 64;
+
+/// An ordered index over string keys.
+///
+/// This is a B+tree whose nodes are IndexNode entities. Each node is rewritten in its entirety whenever it changes,
+/// with entry `i` stored as value `key.{i}` and edge `i`. The edges of leaf nodes point at the indexed heads, and
+/// those of branch nodes point at child nodes, keyed by the lowest key that child may hold. Branch nodes also store
+/// the key below which the keys of each child lie as `hi.{i}`, which is absent where the child is unbounded above.
+///
+/// Slabs may write a node concurrently, leaving it with several versions, which are merged as it is loaded. A leaf
+/// holds the entries of all of its versions, with the version of greatest memo id deciding any key they disagree on.
+/// A branch holds the children of all of its versions, each reaching as far as any version has it reach. Because an
+/// insert rewrites every node along its path, the branches above a leaf always reach as far as the keys written to
+/// it. Where a node is split concurrently with a write to it, its range thus overlaps that of its new sibling, so
+/// both lookups and scans visit every child whose range holds a key, and prefer the entry of the node with the
+/// greatest lowest key where more than one holds it. The root, which retains its identity when it is split, may also
+/// merge a leaf version into a branch, whose entries it then holds loose until it is next written.
+#[derive(Clone)]
+pub struct IndexBTree {
+    root: Head,
+}
+
+/// A node, as merged from its versions. A leaf holds records, and a branch holds children, along with any records
+/// which it holds loose.
+struct Node {
+    head:     Head,
+    records:  Vec<(String, Head)>,
+    children: Vec<Child>,
+}
+
+struct Child {
+    span: Span,
+    head: Head,
+}
+
+/// The keys a node may hold: those from `lo`, and below `hi` where it is bounded
+#[derive(Clone)]
+struct Span {
+    lo: String,
+    hi: Option<String>,
+}
+
+/// A fully materialized version of a node
+struct Version {
+    id:     MemoId,
+    values: HashMap<String, Value>,
+    edges:  EdgeSet,
+}
+
+impl IndexBTree {
+    pub async fn new(context: &Context) -> Result<IndexBTree, WriteError> {
+        let root = Node::new(Vec::new(), Vec::new()).write(context).await?;
+
+        Ok(IndexBTree { root })
+    }
+
+    pub fn new_from_head(head: Head) -> IndexBTree {
+        IndexBTree { root: head }
+    }
+
+    pub fn root_head(&self) -> &Head {
+        &self.root
+    }
+
+    pub fn get_root_entity_id(&self) -> EntityId {
+        self.root.entity_id().unwrap()
+    }
+
+    pub async fn insert(&mut self, context: &Context, key: &str, target: Head) -> Result<(), WriteError> {
+        debug!("IndexBTree.insert({}, {:?})", key, target);
+
+        let mut path: Vec<(Node, Span, usize)> = Vec::new();
+        let mut span = Span::whole();
+        let mut node = Node::load(context, self.root.clone()).await?;
+
+        while !node.is_leaf() {
+            let i = node.child_for(key);
+            let child = &node.children[i];
+            let (head, child_span) = (child.head.clone(), child.span.clone());
+
+            path.push((node, mem::replace(&mut span, child_span), i));
+            node = Node::load(context, head).await?;
+        }
+
+        match node.records.binary_search_by(|(k, _)| k.as_str().cmp(key)) {
+            Ok(i) => node.records[i].1 = target,
+            Err(i) => node.records.insert(i, (key.to_string(), target)),
+        }
+
+        // Write the leaf, and then every node above it, splitting as necessary. Parents are rewritten even where they
+        // don't gain a child, such that wherever this is merged with a concurrent split of the leaf, the range of the
+        // leaf still reaches the key.
+        loop {
+            node.settle(context, &span).await?;
+
+            match (path.pop(), node.split()) {
+                (None, None) => {
+                    self.root = node.write(context).await?;
+                    return Ok(());
+                },
+                (Some((mut parent, parent_span, i)), None) => {
+                    parent.children[i].head = node.write(context).await?;
+
+                    node = parent;
+                    span = parent_span;
+                },
+                (Some((mut parent, parent_span, i)), Some((right, right_lo, left_hi))) => {
+                    parent.children[i].head = node.write(context).await?;
+
+                    let right_hi = mem::replace(&mut parent.children[i].span.hi, left_hi);
+                    let right_head = right.write(context).await?;
+                    parent.adopt(Child { span: Span { lo: right_lo,
+                                                      hi: right_hi, },
+                                         head: right_head, });
+
+                    node = parent;
+                    span = parent_span;
+                },
+                (None, Some((right, right_lo, left_hi))) => {
+                    // The root retains its identity, so it becomes a branch over two new nodes
+                    let left = Node::new(mem::take(&mut node.records), mem::take(&mut node.children));
+
+                    node.children = vec![Child { span: Span { lo: span.lo.clone(),
+                                                              hi: left_hi, },
+                                                 head: left.write(context).await?, },
+                                         Child { span: Span { lo: right_lo,
+                                                              hi: span.hi.clone(), },
+                                                 head: right.write(context).await?, }];
+
+                    self.root = node.write(context).await?;
+                    return Ok(());
+                },
+            }
+        }
+    }
+
+    pub async fn get(&self, context: &Context, key: &str) -> Result<Option<Head>, RetrieveError> {
+        let mut walk = Walk::new(context,
+                                 &self.root,
+                                 Bound::Included(key.to_string()),
+                                 End::Bound(Bound::Included(key.to_string())));
+
+        Ok(walk.next().await?.map(|(_, head)| head))
+    }
+
+    /// Entities whose keys fall within the given range, in key order.
+    ///
+    /// Nodes are loaded only as the stream is polled, such that a consumer which stops early doesn't pay for the rest of
+    /// the range.
+    pub fn range<'a, R>(&self, context: &Context, range: R) -> BoxStream<'static, Result<Entity, RetrieveError>>
+        where R: RangeBounds<&'a str>
+    {
+        let start = range.start_bound().map(|start| start.to_string());
+        let end = range.end_bound().map(|end| end.to_string());
+
+        self.scan(context, start, End::Bound(end))
+    }
+
+    /// Entities whose keys begin with the given prefix, in key order
+    pub fn prefix(&self, context: &Context, prefix: &str) -> BoxStream<'static, Result<Entity, RetrieveError>> {
+        self.scan(context, Bound::Included(prefix.to_string()), End::Prefix(prefix.to_string()))
+    }
+
+    /// All entities in the index, in key order
+    pub fn iter(&self, context: &Context) -> BoxStream<'static, Result<Entity, RetrieveError>> {
+        self.range(context, ..)
+    }
+
+    fn scan(&self, context: &Context, start: Bound<String>, end: End) -> BoxStream<'static, Result<Entity, RetrieveError>> {
+        let walk = Walk::new(context, &self.root, start, end);
+        let context = context.clone();
+
+        stream::try_unfold(walk, |mut walk| {
+            async move {
+                match walk.next().await? {
+                    Some((_, head)) => Ok(Some((head, walk))),
+                    None => Ok(None),
+                }
+            }
+        }).try_filter_map(move |head| {
+              let context = context.clone();
+              async move { context.get_entity_from_head(head).await }
+          })
+          .boxed()
+    }
+}
+
+#[async_trait]
+impl Index for IndexBTree {
+    type Key = String;
+
+    async fn insert(&mut self, context: &Context, key: String, head: Head) -> Result<(), WriteError> {
+        IndexBTree::insert(self, context, &key, head).await
+    }
+
+    async fn get(&self, context: &Context, key: String) -> Result<Option<Head>, RetrieveError> {
+        IndexBTree::get(self, context, &key).await
+    }
+}
+
+impl fmt::Debug for IndexBTree {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("IndexBTree").finish()
+    }
+}
+
+/// The progress of a walk through the tree in key order. As the ranges of nodes may overlap, the entries found are
+/// held until no node yet to be visited could hold a lower key.
+struct Walk {
+    context: Context,
+    start:   Bound<String>,
+    end:     End,
+    /// Nodes yet to be visited, by their lowest key and the order in which they were queued
+    pending: BTreeMap<(String, usize), Head>,
+    queued:  usize,
+    found:   BTreeMap<String, Found>,
+}
+
+/// An entry found by a walk, along with the lowest key and the id of the node it was found in
+struct Found {
+    source: (String, Option<EntityId>),
+    head:   Head,
+}
+
+/// Where a walk ends
+enum End {
+    Bound(Bound<String>),
+    Prefix(String),
+}
+
+impl Walk {
+    fn new(context: &Context, root: &Head, start: Bound<String>, end: End) -> Walk {
+        let mut pending = BTreeMap::new();
+        pending.insert((String::new(), 0), root.clone());
+
+        Walk { context: context.clone(),
+               start,
+               end,
+               pending,
+               queued: 1,
+               found: BTreeMap::new() }
+    }
+
+    /// The next entry within the bounds of the walk, in key order
+    async fn next(&mut self) -> Result<Option<(String, Head)>, RetrieveError> {
+        loop {
+            if let Some(entry) = self.found.first_entry() {
+                if self.end.is_past(entry.key()) {
+                    return Ok(None);
+                }
+                if self.pending.keys().next().is_none_or(|(lo, _)| entry.key() < lo) {
+                    let (key, found) = entry.remove_entry();
+                    return Ok(Some((key, found.head)));
+                }
+            }
+
+            let ((lo, _), head) = match self.pending.pop_first() {
+                Some(next) => next,
+                None => return Ok(None),
+            };
+
+            let node = Node::load(&self.context, head).await?;
+            let source = (lo, node.head.entity_id());
+
+            for (key, head) in node.records {
+                if self.is_before_start(&key) || self.end.is_past(&key) {
+                    continue;
+                }
+                if self.found.get(&key).is_none_or(|found| found.source < source) {
+                    self.found.insert(key, Found { source: source.clone(),
+                                                   head });
+                }
+            }
+
+            for child in node.children {
+                if self.reaches(&child.span) {
+                    self.pending.insert((child.span.lo, self.queued), child.head);
+                    self.queued += 1;
+                }
+            }
+        }
+    }
+
+    fn is_before_start(&self, key: &str) -> bool {
+        match self.start {
+            Bound::Included(ref start) => key < start.as_str(),
+            Bound::Excluded(ref start) => key <= start.as_str(),
+            Bound::Unbounded => false,
+        }
+    }
+
+    fn reaches(&self, span: &Span) -> bool {
+        let reaches_start = match (&self.start, &span.hi) {
+            (Bound::Included(start), Some(hi)) | (Bound::Excluded(start), Some(hi)) => start < hi,
+            _ => true,
+        };
+
+        reaches_start && !self.end.is_past(&span.lo)
+    }
+}
+
+impl End {
+    /// Whether the given key lies past the end. This is monotonic with respect to key order.
+    fn is_past(&self, key: &str) -> bool {
+        match self {
+            End::Bound(Bound::Included(end)) => key > end.as_str(),
+            End::Bound(Bound::Excluded(end)) => key >= end.as_str(),
+            End::Bound(Bound::Unbounded) => false,
+            End::Prefix(prefix) => key > prefix.as_str() && !key.starts_with(prefix.as_str()),
+        }
+    }
+}
+
+impl Node {
+    fn new(records: Vec<(String, Head)>, children: Vec<Child>) -> Node {
+        Node { head: Head::Null,
+               records,
+               children }
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    async fn load(context: &Context, mut head: Head) -> Result<Node, RetrieveError> {
+        context.mut_update_index_head_for_consistency(&mut head).await?;
+
+        let mut records: HashMap<String, (MemoId, Head)> = HashMap::new();
+        let mut children: HashMap<EntityId, Child> = HashMap::new();
+
+        for version in Node::versions(context, &head).await? {
+            let branch = version.values.get("type") == Some(&Value::from("branch"));
+            let len = match version.values.get("len") {
+                Some(Value::Int(len)) => *len as usize,
+                Some(_) => return Err(RetrieveError::NotFound),
+                None => 0,
+            };
+
+            for i in 0..len {
+                let (key, target) = match (version.values.get(&format!("key.{}", i)), version.edges.get(&(i as SlotId))) {
+                    (Some(Value::String(key)), Some(target)) => (key, target),
+                    _ => return Err(RetrieveError::NotFound),
+                };
+
+                if !branch {
+                    if records.get(key).is_none_or(|(id, _)| version.id > *id) {
+                        records.insert(key.clone(), (version.id, target.clone()));
+                    }
+                    continue;
+                }
+
+                let hi = match version.values.get(&format!("hi.{}", i)) {
+                    Some(Value::String(hi)) => Some(hi.clone()),
+                    _ => None,
+                };
+
+                // The lowest key of a node never changes, but how far it reaches may differ between versions
+                match children.entry(target.entity_id().ok_or(RetrieveError::NotFound)?) {
+                    Entry::Occupied(mut entry) => {
+                        let span = &mut entry.get_mut().span;
+                        span.hi = farther(span.hi.take(), hi);
+                    },
+                    Entry::Vacant(entry) => {
+                        entry.insert(Child { span: Span { lo: key.clone(),
+                                                          hi },
+                                             head: target.clone() });
+                    },
+                }
+            }
+        }
+
+        let mut records: Vec<(String, Head)> = records.into_iter().map(|(key, (_, target))| (key, target)).collect();
+        records.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut children: Vec<Child> = children.into_values().collect();
+        children.sort_by(Child::order);
+
+        Ok(Node { head,
+                  records,
+                  children })
+    }
+
+    /// The versions of the node at the given head: the nearest fully materialized memo along each of its branches, less
+    /// any which another descends. Compaction may write edge memos upon a node, but these only bring its edges up to
+    /// date with the context, which loading a child through the context does anyway.
+    async fn versions(context: &Context, head: &Head) -> Result<Vec<Version>, RetrieveError> {
+        let mut found: Vec<(MemoRef, Version)> = Vec::new();
+        let mut visited = HashSet::new();
+        let mut queue = head.to_vecdeque();
+
+        while let Some(memoref) = queue.pop_front() {
+            if !visited.insert(memoref.id) {
+                continue;
+            }
+
+            let memo = memoref.clone().get_memo(context.slab.clone()).await?;
+            match memo.body {
+                MemoBody::FullyMaterialized { ref v, ref e, .. } => {
+                    found.push((memoref,
+                                Version { id:     memo.id,
+                                          values: v.clone(),
+                                          edges:  e.clone(), }))
+                },
+                MemoBody::Edge(_) => queue.extend(memo.get_parent_head().iter().cloned()),
+                _ => return Err(RetrieveError::NotFound),
+            }
+        }
+
+        let mut superseded = vec![false; found.len()];
+        for (i, (memoref, _)) in found.iter().enumerate() {
+            for (j, (other, _)) in found.iter().enumerate() {
+                if i != j && other.descends(memoref, &context.slab).await? {
+                    superseded[i] = true;
+                    break;
+                }
+            }
+        }
+
+        Ok(found.into_iter()
+                .zip(superseded)
+                .filter(|(_, superseded)| !superseded)
+                .map(|((_, version), _)| version)
+                .collect())
+    }
+
+    /// Materialize the node in its entirety as a new memo descending from its present head
+    async fn write(&self, context: &Context) -> Result<Head, WriteError> {
+        debug_assert!(self.is_leaf() || self.records.is_empty(), "loose records must be settled before writing");
+
+        let entity_id = match self.head.entity_id() {
+            Some(entity_id) => entity_id,
+            None => context.slab.generate_entity_id(EntityType::IndexNode),
+        };
+
+        let mut values = HashMap::new();
+        let mut edges = EdgeSet::empty();
+
+        if self.is_leaf() {
+            values.insert("type".to_string(), Value::from("leaf"));
+            values.insert("len".to_string(), Value::Int(self.records.len() as i64));
+
+            for (i, (key, target)) in self.records.iter().enumerate() {
+                values.insert(format!("key.{}", i), Value::from(key));
+                edges.insert(i as SlotId, target.clone());
+            }
+        } else {
+            values.insert("type".to_string(), Value::from("branch"));
+            values.insert("len".to_string(), Value::Int(self.children.len() as i64));
+
+            for (i, child) in self.children.iter().enumerate() {
+                values.insert(format!("key.{}", i), Value::from(&child.span.lo));
+                if let Some(ref hi) = child.span.hi {
+                    values.insert(format!("hi.{}", i), Value::from(hi));
+                }
+                edges.insert(i as SlotId, child.head.clone());
+            }
+        }
+
+        let head = context.slab
+                          .new_memo(Some(entity_id),
+                                    self.head.clone(),
+                                    MemoBody::FullyMaterialized { v: values,
+                                                                  r: RelationSet::empty(),
+                                                                  e: edges,
+                                                                  o: HashMap::new(),
+                                                                  t: EntityType::IndexNode, })
+                          .to_head();
+
+        context.apply_head(&head).await
+    }
+
+    /// Move any records held loose by a branch into a new leaf spanning the whole of the branch
+    async fn settle(&mut self, context: &Context, span: &Span) -> Result<(), WriteError> {
+        if self.is_leaf() || self.records.is_empty() {
+            return Ok(());
+        }
+
+        let leaf = Node::new(mem::take(&mut self.records), Vec::new());
+        let head = leaf.write(context).await?;
+        self.adopt(Child { span: span.clone(),
+                           head });
+
+        Ok(())
+    }
+
+    /// Split off the upper half of an overfull node, returning it along with its lowest key, and the key below which
+    /// those of the lower half lie
+    fn split(&mut self) -> Option<(Node, String, Option<String>)> {
+        if self.is_leaf() {
+            if self.records.len() <= MAX_ENTRIES {
+                return None;
+            }
+
+            let records = self.records.split_off(self.records.len() / 2);
+            let lo = records[0].0.clone();
+
+            Some((Node::new(records, Vec::new()), lo.clone(), Some(lo)))
+        } else {
+            if self.children.len() <= MAX_ENTRIES {
+                return None;
+            }
+
+            let children = self.children.split_off(self.children.len() / 2);
+            let lo = children[0].span.lo.clone();
+
+            // Children of the lower half may reach past the upper half, where they were split concurrently with a write
+            let mut hi = Some(lo.clone());
+            for child in &self.children {
+                hi = farther(hi, child.span.hi.clone());
+            }
+
+            Some((Node::new(Vec::new(), children), lo, hi))
+        }
+    }
+
+    fn adopt(&mut self, child: Child) {
+        self.children.push(child);
+        self.children.sort_by(Child::order);
+    }
+
+    /// Index of the child to insert the given key into: that of the greatest lowest key among those which reach it
+    fn child_for(&self, key: &str) -> usize {
+        self.children
+            .iter()
+            .rposition(|child| child.span.holds(key))
+            .or_else(|| self.children.iter().rposition(|child| child.span.lo.as_str() <= key))
+            .unwrap_or(0)
+    }
+}
+
+impl Child {
+    fn order(&self, other: &Child) -> Ordering {
+        self.span
+            .lo
+            .cmp(&other.span.lo)
+            .then_with(|| self.head.entity_id().cmp(&other.head.entity_id()))
+    }
+}
+
+impl Span {
+    fn whole() -> Span {
+        Span { lo: String::new(),
+               hi: None, }
+    }
+
+    fn holds(&self, key: &str) -> bool {
+        self.lo.as_str() <= key && self.hi.as_ref().is_none_or(|hi| key < hi.as_str())
+    }
+}
+
+/// The farther of two upper bounds, where `None` is unbounded
+fn farther(a: Option<String>, b: Option<String>) -> Option<String> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        error::RetrieveError,
+        index::IndexBTree,
+        Entity,
+        Network,
+        Slab,
+    };
+
+    use futures::{
+        stream::BoxStream,
+        TryStreamExt,
+    };
+
+    async fn names(mut stream: BoxStream<'static, Result<Entity, RetrieveError>>) -> Vec<String> {
+        let mut names = Vec::new();
+        while let Some(mut entity) = stream.try_next().await.unwrap() {
+            names.push(entity.get_value("name").await.unwrap().unwrap());
+        }
+        names
+    }
+
+    #[unbase_test_util::async_test]
+    async fn btree_ordered_scans() {
+        let net = Network::create_new_system();
+        let slab = Slab::new(&net);
+        let context = slab.create_context();
+
+        let mut index = IndexBTree::new(&context).await.unwrap();
+
+        // Inserted out of order, and enough of them to split the root more than once
+        let mut expected = Vec::new();
+        for i in (0..200).map(|i| (i * 7) % 200) {
+            let name = format!("item{:03}", i);
+            let record = Entity::new_with_single_kv(&context, "name", &name).await.unwrap();
+            index.insert(&context, &name, record.head.clone()).await.unwrap();
+            expected.push(name);
+        }
+        expected.sort();
+
+        assert_eq!(names(index.iter(&context)).await, expected);
+
+        let range = names(index.range(&context, "item050".."item060")).await;
+        assert_eq!(range, expected[50..60].to_vec());
+
+        let range = names(index.range(&context, "item195"..)).await;
+        assert_eq!(range, expected[195..].to_vec());
+
+        let prefix = names(index.prefix(&context, "item12")).await;
+        assert_eq!(prefix, expected[120..130].to_vec());
+
+        assert!(index.get(&context, "item100").await.unwrap().is_some());
+        assert!(index.get(&context, "item200").await.unwrap().is_none());
+
+        // Replacing a key leaves a single entry for it
+        let record = Entity::new_with_single_kv(&context, "name", "replacement").await.unwrap();
+        index.insert(&context, "item100", record.head.clone()).await.unwrap();
+
+        let replaced = names(index.prefix(&context, "item100")).await;
+        assert_eq!(replaced, vec!["replacement".to_string()]);
+    }
+}
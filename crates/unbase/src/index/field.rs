@@ -0,0 +1,316 @@
+use crate::{
+    context::Context,
+    error::{
+        RetrieveError,
+        WriteError,
+    },
+    head::Head,
+    slab::{
+        EntityId,
+        EntityType,
+    },
+    value::Value,
+};
+
+use super::fixed::{
+    IndexFixed,
+    ROOT_INDEX_DEPTH,
+};
+
+use futures::{
+    stream::{
+        self,
+        BoxStream,
+    },
+    StreamExt,
+    TryStreamExt,
+};
+use sha2::{
+    Digest,
+    Sha256,
+};
+use std::collections::HashMap;
+
+/// Number of tiers required for the value index to span the full key space of `value_key`
+const VALUE_INDEX_DEPTH: u8 = 8;
+
+/// A secondary index over the values of a single field.
+///
+/// Values are hashed to select a posting index, which is in turn keyed by entity, such that all entities sharing a
+/// value may be found with a pair of probes. Entities are moved between postings as their values change, and withdrawn
+/// when they're deleted. Edits made concurrently on different slabs may leave a posting stale until the entity is next
+/// written, so candidates are re-checked against the current state of the entity at lookup time.
+///
+/// The index is made of index nodes like any other, and is thus persisted and shared by every context in the system.
+/// The ids of its root, and of the root of each posting, are derived from the field and value rather than issued by a
+/// slab, such that contexts which create them concurrently converge upon the same entities. Declared fields are listed
+/// in a catalog, which is filed in the root index along with the root of each field index, under a slab id of zero,
+/// which is never issued.
+#[derive(Clone)]
+pub struct FieldIndex {
+    field:  String,
+    values: IndexFixed,
+}
+
+impl FieldIndex {
+    /// Declare an index on the given field, unless it has been already. Entities already in the root index are not
+    /// filed by this.
+    pub async fn declare(context: &Context, field: &str) -> Result<FieldIndex, WriteError> {
+        if let Some(index) = Self::open(context, field).await? {
+            return Ok(index);
+        }
+
+        let mut values = HashMap::new();
+        values.insert("field".to_string(), Value::from(field));
+        let root = Head::new_index_with_id(&context.slab, field_root_id(field), values);
+        context.apply_head(&root).await?;
+
+        let mut root_index = context.root_index().await?;
+        root_index.insert(context, field_root_id(field).index_key(), root.clone()).await?;
+
+        let mut catalog = match catalog(context).await? {
+            Some(catalog) => catalog,
+            None => Head::new_index_with_id(&context.slab, catalog_id(), HashMap::new()),
+        };
+        catalog.set_value(&context.slab, field, Value::Bool(true)).await?;
+        context.apply_head(&catalog).await?;
+        root_index.insert(context, catalog_id().index_key(), catalog).await?;
+
+        Ok(FieldIndex { field:  field.to_string(),
+                        values: IndexFixed::new_from_head(VALUE_INDEX_DEPTH, root), })
+    }
+
+    /// The index on the given field, if one has been declared
+    pub async fn open(context: &Context, field: &str) -> Result<Option<FieldIndex>, RetrieveError> {
+        let root_index = context.root_index().await?;
+
+        Ok(root_index.get(context, field_root_id(field).index_key())
+                     .await?
+                     .map(|root| {
+                         FieldIndex { field:  field.to_string(),
+                                      values: IndexFixed::new_from_head(VALUE_INDEX_DEPTH, root), }
+                     }))
+    }
+
+    /// Every index which has been declared
+    pub async fn declared(context: &Context) -> Result<Vec<FieldIndex>, RetrieveError> {
+        let catalog = match catalog(context).await? {
+            Some(catalog) => catalog,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut indices = Vec::new();
+        for field in catalog.get_all_keys(&context.slab).await? {
+            if context.get_head_value(&catalog, &field).await? == Some(Value::Bool(true)) {
+                indices.extend(Self::open(context, &field).await?);
+            }
+        }
+
+        Ok(indices)
+    }
+
+    pub fn field(&self) -> &str {
+        &self.field
+    }
+
+    /// File the entity under its value for this field as of `current`, having been filed under its value as of
+    /// `previous`, if any.
+    pub async fn update(&mut self, context: &Context, entity_id: EntityId, previous: Option<&Head>, current: &Head)
+                        -> Result<(), WriteError> {
+        let previous_value = match previous {
+            Some(previous) => context.get_head_value(previous, &self.field).await?,
+            None => None,
+        };
+        let current_value = context.get_head_value(current, &self.field).await?;
+
+        if previous_value == current_value {
+            return Ok(());
+        }
+
+        if let Some(value) = previous_value {
+            self.withdraw(context, entity_id, &value.to_string()).await?;
+        }
+
+        if let Some(value) = current_value {
+            let value = value.to_string();
+            let key = value_key(&value);
+
+            // The entity head itself is brought up to date through the root index, so there's nothing to do unless
+            // this entity is new to the posting index
+            let mut postings = match self.values.get(context, key).await? {
+                Some(postings_root) => IndexFixed::new_from_head(ROOT_INDEX_DEPTH, postings_root),
+                None => {
+                    let mut values = HashMap::new();
+                    values.insert("field".to_string(), Value::from(self.field.as_str()));
+                    values.insert("value".to_string(), Value::from(value.as_str()));
+
+                    let root = Head::new_index_with_id(&context.slab, postings_root_id(&self.field, &value), values);
+                    context.apply_head(&root).await?;
+                    self.values.insert(context, key, root.clone()).await?;
+
+                    IndexFixed::new_from_head(ROOT_INDEX_DEPTH, root)
+                },
+            };
+
+            if postings.get(context, entity_id.index_key()).await?.is_none() {
+                postings.insert(context, entity_id.index_key(), current.clone()).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Withdraw the entity from the posting for its value for this field as of `previous`, if it has one
+    pub async fn remove(&mut self, context: &Context, entity_id: EntityId, previous: &Head) -> Result<(), WriteError> {
+        match context.get_head_value(previous, &self.field).await? {
+            Some(value) => self.withdraw(context, entity_id, &value.to_string()).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn withdraw(&mut self, context: &Context, entity_id: EntityId, value: &str) -> Result<(), WriteError> {
+        if let Some(postings_root) = self.values.get(context, value_key(value)).await? {
+            let mut postings = IndexFixed::new_from_head(ROOT_INDEX_DEPTH, postings_root);
+            postings.remove(context, entity_id.index_key()).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Find the first entity whose present value for this field is `value`
+    pub async fn find(&self, context: &Context, value: &str) -> Result<Option<Head>, RetrieveError> {
+        self.stream_found(context, value).await?.try_next().await
+    }
+
+    /// Find every entity whose present value for this field is `value`
+    pub async fn find_all(&self, context: &Context, value: &str) -> Result<Vec<Head>, RetrieveError> {
+        self.stream_found(context, value).await?.try_collect().await
+    }
+
+    /// As `find_all`, but each entity is looked up and checked only as the stream is polled
+    pub async fn stream_found(&self, context: &Context, value: &str)
+                              -> Result<BoxStream<'static, Result<Head, RetrieveError>>, RetrieveError> {
+        let postings_root = match self.values.get(context, value_key(value)).await? {
+            Some(head) => head,
+            None => return Ok(stream::empty().boxed()),
+        };
+
+        let postings = IndexFixed::new_from_head(ROOT_INDEX_DEPTH, postings_root);
+
+        let context = context.clone();
+        let field = self.field.clone();
+        let value = value.to_string();
+
+        Ok(postings.stream_leaves(&context)
+                   .try_filter_map(move |mut head| {
+                       let context = context.clone();
+                       let field = field.clone();
+                       let value = value.clone();
+
+                       async move {
+                           context.mut_update_record_head_for_consistency(&mut head).await?;
+
+                           match context.get_head_value(&head, &field).await? {
+                               Some(v) if v.to_string() == value => Ok(Some(head)),
+                               _ => Ok(None),
+                           }
+                       }
+                   })
+                   .boxed())
+    }
+}
+
+/// The catalog of declared fields, if any have been
+async fn catalog(context: &Context) -> Result<Option<Head>, RetrieveError> {
+    let root_index = context.root_index().await?;
+
+    match root_index.get(context, catalog_id().index_key()).await? {
+        Some(mut catalog) => {
+            context.mut_update_index_head_for_consistency(&mut catalog).await?;
+            Ok(Some(catalog))
+        },
+        None => Ok(None),
+    }
+}
+
+fn catalog_id() -> EntityId {
+    reserved_id(&["catalog"])
+}
+
+fn field_root_id(field: &str) -> EntityId {
+    reserved_id(&["field", field])
+}
+
+fn postings_root_id(field: &str, value: &str) -> EntityId {
+    reserved_id(&["postings", field, value])
+}
+
+/// An index node id which no slab will issue, derived from the given parts
+fn reserved_id(parts: &[&str]) -> EntityId {
+    EntityId { slab_id: 0,
+               id:      hash_key(parts),
+               stype:   EntityType::IndexNode, }
+}
+
+fn value_key(value: &str) -> u64 {
+    hash_key(&[value])
+}
+
+fn hash_key(parts: &[&str]) -> u64 {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.input(part.as_bytes());
+        hasher.input([0u8]);
+    }
+
+    let digest = hasher.result();
+    let mut key = [0u8; 8];
+    key.copy_from_slice(&digest[0..8]);
+
+    u64::from_be_bytes(key)
+}
+
+#[cfg(test)]
+mod test {
+    use super::value_key;
+    use crate::{
+        index::IndexFixed,
+        Entity,
+        Network,
+        Slab,
+    };
+
+    #[unbase_test_util::async_test]
+    async fn field_index_lookup() {
+        let net = Network::create_new_system();
+        let slab = Slab::new(&net);
+        let context = slab.create_context();
+
+        // Filed at declaration time
+        let tiger = Entity::new_with_single_kv(&context, "beast", "Tiger").await.unwrap();
+        context.add_field_index("beast").await.unwrap();
+
+        // Filed by update_indices
+        let mut lion = Entity::new_with_single_kv(&context, "beast", "Lion").await.unwrap();
+        let _other = Entity::new_with_single_kv(&context, "sound", "Lion").await.unwrap();
+
+        let index = context.field_index("beast").await.unwrap().expect("field index");
+        let head = index.find(&context, "Tiger").await.unwrap().expect("Tiger");
+        assert_eq!(head.entity_id(), Some(tiger.id));
+        let head = index.find(&context, "Lion").await.unwrap().expect("Lion");
+        assert_eq!(head.entity_id(), Some(lion.id));
+
+        // The entity is withdrawn from the posting for its previous value
+        lion.set_value("beast", "Bear").await.unwrap();
+        assert!(index.find(&context, "Lion").await.unwrap().is_none());
+
+        let postings_root = index.values.get(&context, value_key("Lion")).await.unwrap().expect("Lion postings");
+        let postings = IndexFixed::new_from_head(super::ROOT_INDEX_DEPTH, postings_root);
+        assert!(postings.leaves(&context).await.unwrap().is_empty());
+
+        let mut bear = context.try_fetch_kv("beast", "Bear").await.unwrap().expect("Bear");
+        assert_eq!(bear.id, lion.id);
+        assert_eq!(bear.get_value("beast").await.unwrap(), Some("Bear".to_string()));
+        assert!(context.try_fetch_kv("beast", "Lion").await.unwrap().is_none());
+    }
+}
@@ -6,19 +6,44 @@ use crate::{
     },
     head::Head,
     slab::{
+        EdgeSet,
         EntityId,
+        EntityType,
+        MemoBody,
         SlotId,
         MAX_SLOTS,
     },
+    value::Value,
 };
 
+use super::Index;
+
+use async_trait::async_trait;
+use futures::{
+    future::BoxFuture,
+    stream::{
+        self,
+        BoxStream,
+    },
+    FutureExt,
+    StreamExt,
+    TryStreamExt,
+};
 use std::{
-    collections::HashMap,
+    collections::{
+        BTreeMap,
+        HashMap,
+        VecDeque,
+    },
     fmt,
 };
 
 use tracing::debug;
 
+/// Number of tiers required for the root index to span the 40 bit key space of `EntityId::index_key`
+pub const ROOT_INDEX_DEPTH: u8 = 5;
+
+#[derive(Clone)]
 pub struct IndexFixed {
     root:  Head,
     depth: u8,
@@ -28,7 +53,7 @@ impl IndexFixed {
     /// Index takes everything with context, because Index is an enforcer of consistency
     pub fn new(context: &Context, depth: u8) -> IndexFixed {
         let mut debug_info = HashMap::new();
-        debug_info.insert("tier".to_string(), "root".to_string());
+        debug_info.insert("tier".to_string(), Value::from("root"));
 
         Self { root: Head::new_index(&context.slab, debug_info),
                depth }
@@ -38,6 +63,10 @@ impl IndexFixed {
         Self { root: head, depth }
     }
 
+    pub fn root_head(&self) -> &Head {
+        &self.root
+    }
+
     pub fn get_root_entity_id(&self) -> EntityId {
         self.root.entity_id().unwrap()
     }
@@ -57,8 +86,8 @@ impl IndexFixed {
             // Could just assume we're dealing with whole bytes here, but I'd rather
             // allow for MAX_SLOTS <> 256. Values like 128, 512, 1024 may not be entirely ridiculous
             let exponent: u32 = (self.depth as u32 - 1) - tier as u32;
-            let x = MAX_SLOTS.pow(exponent as u32);
-            let y = ((key / (x as u64)) % MAX_SLOTS as u64) as SlotId;
+            let x = (MAX_SLOTS as u64).pow(exponent);
+            let y = ((key / x) % MAX_SLOTS as u64) as SlotId;
 
             // println!("Tier {}, {}, {}", tier, x, y );
 
@@ -69,6 +98,15 @@ impl IndexFixed {
                 // TODO- this MIGHT not be necessary, because context.apply_head might be doing the same thing.
                 context.mut_update_index_head_for_consistency(&mut node).await?;
 
+                // The target may have been written concurrently with the head we already have for it, in which case
+                // both are retained rather than one replacing the other
+                let target = match node.get_edge(&context.slab, y).await? {
+                    Some(existing) if existing.entity_id() == target.entity_id() => {
+                        existing.apply(&target, &context.slab).await?.0
+                    },
+                    _ => target,
+                };
+
                 node.set_edge(&context.slab, y as SlotId, target);
 
                 // Apply the updated head to the context
@@ -86,7 +124,7 @@ impl IndexFixed {
                     },
                     None => {
                         let mut debug_info = HashMap::new();
-                        debug_info.insert("tier".to_string(), tier.to_string());
+                        debug_info.insert("tier".to_string(), Value::Int(tier as i64));
 
                         let next_node = Head::new_index(&context.slab, debug_info);
 
@@ -107,17 +145,127 @@ impl IndexFixed {
         }
     }
 
+    /// Insert several entries such that the new head of the root node descends from all of them. Only that head is sent
+    /// to other slabs, whereas the nodes beneath it are retrieved from us on demand, so no context elsewhere can see any
+    /// of the entries without seeing the rest. Unlike `insert`, every node along the way is rewritten.
+    pub async fn insert_batch(&mut self, context: &Context, entries: Vec<(u64, Head)>) -> Result<(), WriteError> {
+        debug!("IndexFixed.insert_batch({} entries)", entries.len());
+
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        self.root = self.insert_below(context, self.root.clone(), 0, entries).await?;
+
+        Ok(())
+    }
+
+    /// Write the entries into the subtree of the given node, returning its new head
+    fn insert_below<'a>(&'a self, context: &'a Context, mut node: Head, tier: u8, entries: Vec<(u64, Head)>)
+                        -> BoxFuture<'a, Result<Head, WriteError>> {
+        async move {
+            context.mut_update_index_head_for_consistency(&mut node).await?;
+
+            let exponent: u32 = (self.depth as u32 - 1) - tier as u32;
+            let x = (MAX_SLOTS as u64).pow(exponent);
+
+            let mut by_slot: BTreeMap<SlotId, Vec<(u64, Head)>> = BTreeMap::new();
+            for (key, target) in entries {
+                let y = ((key / x) % MAX_SLOTS as u64) as SlotId;
+                by_slot.entry(y).or_default().push((key, target));
+            }
+
+            let mut edgeset = EdgeSet::empty();
+            for (y, entries) in by_slot {
+                let head = if exponent == 0 {
+                    // As with insert, a target written concurrently with the head we already have is merged with it
+                    let mut head = node.get_edge(&context.slab, y).await?;
+                    for (_, target) in entries {
+                        head = Some(match head {
+                                        Some(existing) if existing.entity_id() == target.entity_id() => {
+                                            existing.apply(&target, &context.slab).await?.0
+                                        },
+                                        _ => target,
+                                    });
+                    }
+
+                    head.expect("at least one entry per slot")
+                } else {
+                    let child = match node.get_edge(&context.slab, y).await? {
+                        Some(child) => child,
+                        None => {
+                            let mut debug_info = HashMap::new();
+                            debug_info.insert("tier".to_string(), Value::Int(tier as i64));
+
+                            let child = Head::new_index(&context.slab, debug_info);
+                            context.apply_head(&child).await?;
+                            child
+                        },
+                    };
+
+                    self.insert_below(context, child, tier + 1, entries).await?
+                };
+
+                edgeset.insert(y, head);
+            }
+
+            let entity_id = node.entity_id();
+            let body = MemoBody::Edge(edgeset);
+            let memoref = if tier == 0 {
+                context.slab.new_memo(entity_id, node, body)
+            } else {
+                context.slab.new_memo_unannounced(entity_id, node, body)
+            };
+            let node = memoref.to_head();
+
+            // Applied from the bottom up, such that each node supersedes the heads of its children in the stash
+            context.apply_head(&node).await?;
+
+            Ok(node)
+        }.boxed()
+    }
+
+    /// Vacate the slot for the given key, if it's occupied
+    pub async fn remove(&mut self, context: &Context, key: u64) -> Result<(), WriteError> {
+        debug!("IndexFixed.remove({})", key);
+
+        let mut node = self.root.clone();
+        let max = MAX_SLOTS as u64;
+
+        for tier in 0..self.depth {
+            let exponent = (self.depth - 1) - tier;
+            let x = max.pow(exponent as u32);
+            let y = ((key / x) % max) as SlotId;
+
+            context.mut_update_index_head_for_consistency(&mut node).await?;
+
+            if exponent == 0 {
+                if node.get_edge(&context.slab, y).await?.is_some() {
+                    node.set_edge(&context.slab, y, Head::Null);
+
+                    // Apply the updated head to the context
+                    context.apply_head(&node).await?;
+                }
+
+                return Ok(());
+            }
+
+            match node.get_edge(&context.slab, y).await? {
+                Some(n) => node = n,
+                None => return Ok(()),
+            }
+        }
+
+        panic!("Sanity error");
+    }
+
     /// Convenience method for the test suite
     #[doc(hidden)]
     #[cfg(test)]
     pub(crate) async fn test_get_entity_handle(&self, context: &Context, key: u64)
                                                -> Result<Option<crate::entity::Entity>, RetrieveError> {
         match self.get(context, key).await? {
-            Some(head) => {
-                let entity = context.get_entity_from_head(head).await?;
-
-                Ok(Some(entity))
-            },
+            Some(head) => context.get_entity_from_head(head).await,
             None => Ok(None),
         }
     }
@@ -133,7 +281,7 @@ impl IndexFixed {
         for tier in 0..self.depth {
             let exponent = (self.depth - 1) - tier;
             let x = max.pow(exponent as u32);
-            let y = ((key / (x as u64)) % max) as SlotId;
+            let y = ((key / x) % max) as SlotId;
             debug!("Tier {}, {}, {}", tier, x, y);
 
             if exponent == 0 {
@@ -158,6 +306,55 @@ impl IndexFixed {
         panic!("Sanity error");
     }
 
+    /// Collect the heads of every record in the index, in key order. Index nodes filed alongside the records, such as
+    /// those of the field indexes, are omitted.
+    pub async fn leaves(&self, context: &Context) -> Result<Vec<Head>, RetrieveError> {
+        self.stream_leaves(context).try_collect().await
+    }
+
+    /// As `leaves`, but each node is loaded only as the stream is polled, such that a consumer which stops early
+    /// doesn't pay for the rest of the index.
+    pub fn stream_leaves(&self, context: &Context) -> BoxStream<'static, Result<Head, RetrieveError>> {
+        let walk = LeafWalk { context: context.clone(),
+                              depth:   self.depth,
+                              stack:   vec![(self.root.clone(), 0)],
+                              ready:   VecDeque::new(), };
+
+        stream::try_unfold(walk, |mut walk| {
+            async move {
+                loop {
+                    if let Some(head) = walk.ready.pop_front() {
+                        return Ok(Some((head, walk)));
+                    }
+
+                    let (mut node, tier) = match walk.stack.pop() {
+                        Some(next) => next,
+                        None => return Ok(None),
+                    };
+
+                    walk.context.mut_update_index_head_for_consistency(&mut node).await?;
+
+                    if tier == walk.depth - 1 {
+                        for slot_id in 0..MAX_SLOTS {
+                            if let Some(head) = node.get_edge(&walk.context.slab, slot_id as SlotId).await? {
+                                if is_record(&head) {
+                                    walk.ready.push_back(head);
+                                }
+                            }
+                        }
+                    } else {
+                        // Pushed in reverse so that lower slots are popped first
+                        for slot_id in (0..MAX_SLOTS).rev() {
+                            if let Some(child) = node.get_edge(&walk.context.slab, slot_id as SlotId).await? {
+                                walk.stack.push((child, tier + 1));
+                            }
+                        }
+                    }
+                }
+            }
+        }).boxed()
+    }
+
     pub async fn scan_first_kv(&mut self, context: &Context, key: &str, value: &str) -> Result<Option<Head>, RetrieveError> {
         // TODO POSTMERGE - figure out how the hell to make this work with a closure
         //
@@ -198,7 +395,7 @@ impl IndexFixed {
                 // println!("LAST Non-leaf node   {}, {}, {}", node.id, tier, self.depth );
                 for slot_id in 0..MAX_SLOTS {
                     context.mut_update_index_head_for_consistency(&mut node).await?;
-                    if let Some(mut head) = node.get_edge(&context.slab, slot_id as SlotId).await? {
+                    if let Some(head) = node.get_edge(&context.slab, slot_id as SlotId).await?.filter(is_record) {
                         //                        TODO POSTMERGE - update this to take a closure
                         //                        if f(&mut head).await? {
                         //                            return Ok(Some(head))
@@ -206,8 +403,8 @@ impl IndexFixed {
 
                         context.mut_update_index_head_for_consistency(&mut node).await?;
 
-                        if let Some(v) = head.get_value(&context.slab, key).await? {
-                            if v == value {
+                        if let Some(v) = context.get_head_value(&head, key).await? {
+                            if v.to_string() == value {
                                 return Ok(Some(head));
                             }
                         }
@@ -228,6 +425,31 @@ impl IndexFixed {
     }
 }
 
+/// The progress of `stream_leaves` through the index: the nodes yet to be visited, and the leaves of the last one
+struct LeafWalk {
+    context: Context,
+    depth:   u8,
+    stack:   Vec<(Head, u8)>,
+    ready:   VecDeque<Head>,
+}
+
+fn is_record(head: &Head) -> bool {
+    matches!(head.entity_id(), Some(EntityId { stype: EntityType::Record, .. }))
+}
+
+#[async_trait]
+impl Index for IndexFixed {
+    type Key = u64;
+
+    async fn insert(&mut self, context: &Context, key: u64, head: Head) -> Result<(), WriteError> {
+        IndexFixed::insert(self, context, key, head).await
+    }
+
+    async fn get(&self, context: &Context, key: u64) -> Result<Option<Head>, RetrieveError> {
+        IndexFixed::get(self, context, key).await
+    }
+}
+
 impl fmt::Debug for IndexFixed {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         fmt.debug_struct("IndexFixed").finish()
@@ -239,6 +461,7 @@ mod test {
     use crate::{
         index::IndexFixed,
         util::simulator::Simulator,
+        value::Value,
         Entity,
         Network,
         Slab,
@@ -284,22 +507,20 @@ mod test {
 
         let maybe_head = index.scan_first_kv(&context_a, "record number", "12345").await.expect("Ok");
         assert!(maybe_head.is_some(), "Index scan for record 12345");
-        assert_eq!(maybe_head.unwrap()
-                             .get_value(&context_a.slab, "record number")
-                             .await
-                             .unwrap()
-                             .unwrap(),
-                   "12345",
+        assert_eq!(context_a.get_head_value(&maybe_head.unwrap(), "record number")
+                            .await
+                            .unwrap()
+                            .unwrap(),
+                   Value::from("12345"),
                    "Is correct record");
 
         let maybe_head = index.scan_first_kv(&context_a, "record number", "275").await.unwrap();
         assert!(maybe_head.is_some(), "Index scan for record 275");
-        assert_eq!(maybe_head.unwrap()
-                             .get_value(&context_a.slab, "record number")
-                             .await
-                             .unwrap()
-                             .unwrap(),
-                   "275",
+        assert_eq!(context_a.get_head_value(&maybe_head.unwrap(), "record number")
+                            .await
+                            .unwrap()
+                            .unwrap(),
+                   Value::from("275"),
                    "Is correct record");
     }
 }
@@ -1,8 +1,28 @@
+mod btree;
+mod field;
 mod fixed;
-pub use self::fixed::IndexFixed;
-use crate::head::Head;
+pub use self::btree::IndexBTree;
+pub use self::field::FieldIndex;
+pub use self::fixed::{
+    IndexFixed,
+    ROOT_INDEX_DEPTH,
+};
+use crate::{
+    context::Context,
+    error::{
+        RetrieveError,
+        WriteError,
+    },
+    head::Head,
+};
 
-trait Index {
-    fn insert(&self, key: u64, head: Head);
-    fn get(&self, key: u64) -> Option<Head>;
+use async_trait::async_trait;
+
+/// An index of entity heads, stored as IndexNode entities and traversed with the consistency of the given context
+#[async_trait]
+pub trait Index {
+    type Key: Send + 'static;
+
+    async fn insert(&mut self, context: &Context, key: Self::Key, head: Head) -> Result<(), WriteError>;
+    async fn get(&self, context: &Context, key: Self::Key) -> Result<Option<Head>, RetrieveError>;
 }
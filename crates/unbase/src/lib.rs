@@ -61,13 +61,18 @@ extern crate serde;
 extern crate serde_json;
 
 pub mod context;
+pub mod crdt;
 pub mod entity;
 pub mod error;
 pub mod head;
+pub mod history;
 pub mod index;
+pub mod merge;
 pub mod network;
+pub mod query;
 pub mod slab;
 pub mod util;
+pub mod value;
 
 pub use crate::{
     entity::Entity,
@@ -0,0 +1,85 @@
+//! Resolution of values written concurrently to the same field of an entity.
+//!
+//! Writes which are causally ordered never conflict, as the later one simply supersedes the earlier. But two slabs may
+//! each write a field without having seen the other's write, in which case both writes survive in the head of the
+//! entity, and a `MergePolicy` decides what the reader is presented with. Policies are selected per field or per
+//! entity type on the `Context`, falling back to `LastWriterWins`.
+
+use crate::{
+    slab::{
+        EntityType,
+        MemoId,
+    },
+    value::Value,
+};
+
+use std::{
+    collections::HashMap,
+    sync::Arc,
+};
+
+/// One of several values written concurrently to a field
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConcurrentValue {
+    /// The memo which wrote the value
+    pub memo_id: MemoId,
+    pub value:   Value,
+}
+
+pub trait MergePolicy: Send + Sync {
+    /// Choose the values to present for a field, given those written concurrently. `values` is never empty.
+    fn merge(&self, field: &str, values: Vec<ConcurrentValue>) -> Vec<Value>;
+}
+
+/// Present a single value, chosen arbitrarily but consistently, such that every slab agrees on it.
+/// The other writes are disregarded.
+pub struct LastWriterWins;
+
+impl MergePolicy for LastWriterWins {
+    fn merge(&self, _field: &str, values: Vec<ConcurrentValue>) -> Vec<Value> {
+        values.into_iter().max_by_key(|v| v.memo_id).map(|v| v.value).into_iter().collect()
+    }
+}
+
+/// Present every concurrent value, such that the conflict may be resolved by the application, typically by writing a
+/// new value. Values are ordered consistently on every slab.
+pub struct MultiValue;
+
+impl MergePolicy for MultiValue {
+    fn merge(&self, _field: &str, mut values: Vec<ConcurrentValue>) -> Vec<Value> {
+        values.sort_by_key(|v| v.memo_id);
+        values.into_iter().map(|v| v.value).collect()
+    }
+}
+
+/// Any suitable closure may serve as a policy
+impl<F> MergePolicy for F where F: Fn(&str, Vec<ConcurrentValue>) -> Vec<Value> + Send + Sync
+{
+    fn merge(&self, field: &str, values: Vec<ConcurrentValue>) -> Vec<Value> {
+        self(field, values)
+    }
+}
+
+/// The policies declared on a slab. Those declared for a field take precedence over those for an entity type.
+#[derive(Clone, Default)]
+pub(crate) struct MergePolicies {
+    by_field: HashMap<String, Arc<dyn MergePolicy>>,
+    by_type:  HashMap<EntityType, Arc<dyn MergePolicy>>,
+}
+
+impl MergePolicies {
+    pub fn set_for_field(&mut self, field: &str, policy: Arc<dyn MergePolicy>) {
+        self.by_field.insert(field.to_string(), policy);
+    }
+
+    pub fn set_for_type(&mut self, stype: EntityType, policy: Arc<dyn MergePolicy>) {
+        self.by_type.insert(stype, policy);
+    }
+
+    pub fn get(&self, stype: EntityType, field: &str) -> Arc<dyn MergePolicy> {
+        match self.by_field.get(field).or_else(|| self.by_type.get(&stype)) {
+            Some(policy) => policy.clone(),
+            None => Arc::new(LastWriterWins),
+        }
+    }
+}
@@ -52,10 +52,10 @@ impl Deref for Network {
 }
 
 pub struct NetworkInner {
-    next_slab_id:      RwLock<u32>,
     slabs:             RwLock<Vec<SlabHandle>>,
     transports:        RwLock<Vec<Box<dyn Transport + Send + Sync>>>,
     root_index_seed:   RwLock<Option<(Head, SlabRef)>>,
+    next_slab_id:      RwLock<Option<SlabId>>,
     create_new_system: bool,
 }
 
@@ -80,10 +80,10 @@ impl Network {
     }
 
     fn new_inner(create_new_system: bool) -> Network {
-        let net = Network(Arc::new(NetworkInner { next_slab_id: RwLock::new(0),
-                                                  slabs: RwLock::new(Vec::new()),
+        let net = Network(Arc::new(NetworkInner { slabs: RwLock::new(Vec::new()),
                                                   transports: RwLock::new(Vec::new()),
                                                   root_index_seed: RwLock::new(None),
+                                                  next_slab_id: RwLock::new(None),
                                                   create_new_system }));
 
         let localdirect = self::transport::LocalDirect::new();
@@ -92,11 +92,6 @@ impl Network {
         net
     }
 
-    // TODO: remove this when slab ids are randomly generated
-    pub fn hack_set_next_slab_id(&self, id: SlabId) {
-        *self.next_slab_id.write().unwrap() = id;
-    }
-
     pub fn weak(&self) -> WeakNetwork {
         WeakNetwork(Arc::downgrade(&self.0))
     }
@@ -115,12 +110,27 @@ impl Network {
         self.transports.write().unwrap().push(transport);
     }
 
-    pub fn generate_slab_id(&self) -> u32 {
-        let mut next_slab_id = self.next_slab_id.write().unwrap();
-        let id = *next_slab_id;
-        *next_slab_id += 1;
+    /// Issue slab ids sequentially from `id` rather than randomly, so that the test suite may make assertions about
+    /// the exact contents of the index. Not for use in production.
+    pub fn hack_set_next_slab_id(&self, id: SlabId) {
+        *self.next_slab_id.write().unwrap() = Some(id);
+    }
 
-        id
+    /// Slab ids are random so that slabs in separate processes may join the same system without coordination.
+    /// Zero is never issued, as it denotes an unknown destination slab on the wire.
+    pub fn generate_slab_id(&self) -> SlabId {
+        if let Some(ref mut next_slab_id) = *self.next_slab_id.write().unwrap() {
+            let id = *next_slab_id;
+            *next_slab_id += 1;
+            return id;
+        }
+
+        loop {
+            let id: SlabId = rand::random();
+            if id != 0 && self.get_slabhandle(id).is_none() {
+                return id;
+            }
+        }
     }
 
     pub fn get_slabhandle(&self, slab_id: SlabId) -> Option<SlabHandle> {
@@ -298,7 +308,7 @@ impl Network {
 impl fmt::Debug for Network {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         fmt.debug_struct("Network")
-           .field("next_slab_id", &self.next_slab_id.read().unwrap())
+           .field("slabs", &self.slabs.read().unwrap().iter().map(|s| s.my_ref.slab_id).collect::<Vec<SlabId>>())
            .finish()
     }
 }
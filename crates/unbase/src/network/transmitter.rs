@@ -24,7 +24,7 @@ impl<'a> TransmitterArgs<'a> {
     pub fn get_slab_id(&self) -> SlabId {
         match self {
             &TransmitterArgs::Local(ref s) => s.my_ref.slab_id.clone(),
-            &TransmitterArgs::Remote(ref id, _) => *id.clone(),
+            &TransmitterArgs::Remote(id, _) => *id,
         }
     }
 }
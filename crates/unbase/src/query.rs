@@ -1,28 +1,555 @@
-//use context::Context;
-//use entity::{EntityId,EntityField};
-//
-//pub struct Query{
-//    parts: Vec<QueryPart>,
-//    context: Context
-//}
-//pub struct QueryPart {
-//    fields: Vec<EntityField>,
-//    criteria: QueryPartCriteria
-//}
-//pub enum QueryPartCriteria {
-//    ByEntityId: { id: Vec<EntityId> }
-//    ByFieldComparison
-//}
-//
-//impl Query {
-//    pub fn new (context: &Context) -> Query {
-//        Query {
-//            parts:   Vec::new(),
-//            context: context
-//        }
-//    }
-//
-//    pub fn add_part {
-//
-//    }
-//}
+use crate::{
+    context::Context,
+    entity::Entity,
+    error::RetrieveError,
+    head::Head,
+    slab::{
+        EntityId,
+        EntityType,
+        MemoBody,
+        MemoId,
+        SlotId,
+    },
+    value::Value,
+};
+
+use futures::{
+    channel::mpsc,
+    future::{
+        self,
+        RemoteHandle,
+    },
+    stream::{
+        self,
+        BoxStream,
+        Stream,
+    },
+    task::{
+        Context as TaskContext,
+        Poll,
+    },
+    SinkExt,
+    StreamExt,
+    TryStreamExt,
+};
+use std::{
+    cmp::Ordering,
+    collections::{
+        hash_map::Entry,
+        HashMap,
+        HashSet,
+    },
+    pin::Pin,
+};
+
+use tracing::debug;
+
+/// A query against the entities visible to a context, built up from a sequence of parts which are applied in order.
+///
+/// ```
+/// # use unbase::{Network, Slab, Entity};
+/// # async fn run () {
+/// # let net = Network::create_new_system();
+/// # let slab = Slab::new(&net);
+/// # let context = slab.create_context();
+/// # Entity::new_with_single_kv(&context, "beast", "Tiger").await.unwrap();
+/// use futures::TryStreamExt;
+///
+/// let mut tigers = context.query()
+///                         .eq("beast", "Tiger")
+///                         .limit(10)
+///                         .execute()
+///                         .await
+///                         .expect("the query didn't fail");
+///
+/// while let Some(tiger) = tigers.try_next().await.expect("the entity was retrieved") {
+///     // ...
+/// }
+/// # }
+/// # async_std::task::block_on(run())
+/// ```
+#[derive(Clone)]
+pub struct Query {
+    context: Context,
+    parts:   Vec<QueryPart>,
+    limit:   Option<usize>,
+}
+
+#[derive(Clone)]
+pub enum QueryPart {
+    /// Retain only the entities with the given ids
+    ByEntityId(Vec<EntityId>),
+    /// Retain only the entities whose value for the field compares as specified
+    ByFieldComparison {
+        field:      String,
+        comparison: Comparison,
+        value:      Value,
+    },
+    /// Replace each entity with that which it relates to in the given slot, if any
+    Relation(SlotId),
+}
+
+/// Values are compared as described by `Value::compare`. Values which aren't comparable, such as a string and a
+/// number, are unequal, but neither less nor greater than one another.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn matches(self, ordering: Option<Ordering>) -> bool {
+        match self {
+            Comparison::Eq => ordering == Some(Ordering::Equal),
+            Comparison::Ne => ordering != Some(Ordering::Equal),
+            Comparison::Lt => ordering == Some(Ordering::Less),
+            Comparison::Le => matches!(ordering, Some(Ordering::Less) | Some(Ordering::Equal)),
+            Comparison::Gt => ordering == Some(Ordering::Greater),
+            Comparison::Ge => matches!(ordering, Some(Ordering::Greater) | Some(Ordering::Equal)),
+        }
+    }
+}
+
+impl Query {
+    pub fn new(context: &Context) -> Query {
+        Query { context: context.clone(),
+                parts:   Vec::new(),
+                limit:   None, }
+    }
+
+    pub(crate) fn in_context(mut self, context: &Context) -> Self {
+        self.context = context.clone();
+        self
+    }
+
+    pub fn add_part(mut self, part: QueryPart) -> Self {
+        self.parts.push(part);
+        self
+    }
+
+    pub fn by_id(self, entity_ids: Vec<EntityId>) -> Self {
+        self.add_part(QueryPart::ByEntityId(entity_ids))
+    }
+
+    pub fn filter<V>(self, field: &str, comparison: Comparison, value: V) -> Self
+        where V: Into<Value>
+    {
+        self.add_part(QueryPart::ByFieldComparison { field: field.to_string(),
+                                                     comparison,
+                                                     value: value.into() })
+    }
+
+    pub fn eq<V>(self, field: &str, value: V) -> Self
+        where V: Into<Value>
+    {
+        self.filter(field, Comparison::Eq, value)
+    }
+
+    pub fn relation(self, slot_id: SlotId) -> Self {
+        self.add_part(QueryPart::Relation(slot_id))
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Execute the query, yielding the matching entities in the order in which they were found. Candidates are
+    /// retrieved and evaluated only as the stream is polled, and no more once the limit is reached.
+    ///
+    /// The candidates are drawn from the first part where possible: entity ids are looked up directly, and equality on
+    /// a field with a secondary index is probed. Otherwise every record in the root index is considered.
+    pub async fn execute(self) -> Result<impl Stream<Item = Result<Entity, RetrieveError>> + Unpin, RetrieveError> {
+        let (candidates, applied) = self.candidates().await?;
+
+        let execution = Execution { candidates,
+                                    parts:     self.parts[applied..].to_vec(),
+                                    remaining: self.limit,
+                                    yielded:   HashSet::new(), };
+
+        Ok(stream::try_unfold(execution, |mut execution| {
+            async move {
+                if execution.remaining == Some(0) {
+                    return Ok(None);
+                }
+
+                while let Some(entity) = execution.candidates.try_next().await? {
+                    if let Some(entity) = Self::apply_parts(&execution.parts, entity).await? {
+                        if execution.yielded.insert(entity.id) {
+                            execution.remaining = execution.remaining.map(|remaining| remaining - 1);
+                            return Ok(Some((entity, execution)));
+                        }
+                    }
+                }
+
+                Ok(None)
+            }
+        }).boxed())
+    }
+
+    /// The entities to which the parts are to be applied, along with the number of leading parts which they're already
+    /// known to satisfy
+    async fn candidates(&self) -> Result<(BoxStream<'static, Result<Entity, RetrieveError>>, usize), RetrieveError> {
+        let context = &self.context;
+
+        // Equality on a field with a secondary index is probed
+        let field_index = match self.parts.first() {
+            Some(QueryPart::ByFieldComparison { field,
+                                                comparison: Comparison::Eq,
+                                                .. }) => context.field_index(field).await?,
+            _ => None,
+        };
+
+        Ok(match (self.parts.first(), field_index) {
+               (Some(QueryPart::ByEntityId(entity_ids)), _) => {
+                   let context = context.clone();
+                   let entities = stream::iter(entity_ids.clone()).then(move |entity_id| {
+                                                                       let context = context.clone();
+                                                                       async move { context.get_entity(entity_id).await }
+                                                                   });
+
+                   (entities.try_filter_map(|entity| future::ready(Ok(entity))).boxed(), 1)
+               },
+               (Some(QueryPart::ByFieldComparison { value, .. }), Some(field_index)) => {
+                   // Postings may be stale, so the comparison is retained
+                   let heads = field_index.stream_found(context, &value.to_string()).await?;
+
+                   (Self::entities_from_heads(context, heads), 0)
+               },
+               _ => {
+                   let heads = context.root_index().await?.stream_leaves(context);
+
+                   (Self::entities_from_heads(context, heads), 0)
+               },
+           })
+    }
+
+    /// Execute the query, and thereafter yield the differences to its result set as entities change. The first result
+    /// set is yielded as a series of `Added` events.
+    ///
+    /// Rather than executing the query afresh, each memo is considered only where it belongs to an entity from which a
+    /// result was reached, or to an entity which it may bring to match, such as by writing a field which is filtered
+    /// on. Only the entities from which results may be reached by way of the entity in question are then evaluated.
+    /// Where the query has a limit, further results are added only while there are fewer than that.
+    pub(crate) fn observe(self) -> QueryObserver {
+        let (mut tx, rx) = mpsc::channel(1000);
+        let (memo_tx, mut memo_rx) = mpsc::unbounded();
+
+        self.context.slab.observe_memos(memo_tx);
+
+        let task = crate::util::task::spawn_with_handle(async move {
+            let mut observed = ObservedResults::default();
+
+            let mut events = match self.evaluate_candidates(&mut observed).await {
+                Ok(events) => events,
+                Err(e) => {
+                    debug!("Query.observe - failed to execute query: {:?}", e);
+                    Vec::new()
+                },
+            };
+
+            loop {
+                for event in events {
+                    if tx.send(event).await.is_err() {
+                        // The observer was dropped
+                        return;
+                    }
+                }
+
+                let head = match memo_rx.next().await {
+                    Some(head) => head,
+                    None => return,
+                };
+
+                events = match head.entity_id() {
+                    Some(entity_id @ EntityId { stype: EntityType::Record,
+                                                .. }) => {
+                        match self.evaluate_changed(&mut observed, entity_id, head).await {
+                            Ok(events) => events,
+                            Err(e) => {
+                                debug!("Query.observe - failed to evaluate {}: {:?}", entity_id, e);
+                                Vec::new()
+                            },
+                        }
+                    },
+                    _ => Vec::new(),
+                };
+            }
+        });
+
+        QueryObserver { rx,
+                        _task: task }
+    }
+
+    async fn evaluate_candidates(&self, observed: &mut ObservedResults) -> Result<Vec<QueryEvent>, RetrieveError> {
+        let (mut candidates, _) = self.candidates().await?;
+
+        let mut touched = Touched::default();
+        while let Some(entity) = candidates.try_next().await? {
+            let source = entity.id;
+            observed.record(source, Self::apply_parts_traced(&self.parts, entity).await?, &mut touched);
+        }
+
+        self.observed_events(observed, touched).await
+    }
+
+    /// Evaluate those entities from which a result may be reached by way of the given entity, whose head has changed
+    async fn evaluate_changed(&self, observed: &mut ObservedResults, entity_id: EntityId, head: Head)
+                              -> Result<Vec<QueryEvent>, RetrieveError> {
+        let mut sources: Vec<EntityId> = observed.sources_via(entity_id);
+
+        if !sources.contains(&entity_id) && self.may_match(&head) {
+            sources.push(entity_id);
+        }
+
+        let mut touched = Touched::default();
+        for source in sources {
+            let entity = if source == entity_id {
+                self.context.get_entity_from_head(head.clone()).await?
+            } else {
+                self.context.get_entity(source).await?
+            };
+
+            let traversal = match entity {
+                Some(entity) => Self::apply_parts_traced(&self.parts, entity).await?,
+                None => Traversal { path:   vec![source],
+                                    result: None, },
+            };
+
+            observed.record(source, traversal, &mut touched);
+        }
+
+        self.observed_events(observed, touched).await
+    }
+
+    /// Whether the memos of the head may bring their entity to match the query, were it not already evaluated
+    fn may_match(&self, head: &Head) -> bool {
+        let has_relation = self.parts.iter().any(|part| matches!(part, QueryPart::Relation(_)));
+
+        head.iter().any(|memoref| {
+                       let memo = match memoref.get_memo_if_resident() {
+                           Some(memo) => memo,
+                           None => return true,
+                       };
+
+                       match memo.body {
+                           MemoBody::FullyMaterialized { .. } => true,
+                           MemoBody::Relation(_) => has_relation,
+                           _ => memo.get_changed_keys().iter().any(|key| self.filters_on(key)),
+                       }
+                   })
+    }
+
+    fn filters_on(&self, key: &str) -> bool {
+        self.parts.iter().any(|part| {
+                             match part {
+                                 QueryPart::ByFieldComparison { field, .. } => field == key,
+                                 _ => false,
+                             }
+                         })
+    }
+
+    /// The events arising from the evaluation of some entities, given the results previously and presently reached
+    /// from them. Where a result is reached from several entities, it's yielded as long as any of them reach it.
+    async fn observed_events(&self, observed: &mut ObservedResults, mut touched: Touched)
+                             -> Result<Vec<QueryEvent>, RetrieveError> {
+        let mut events = Vec::new();
+        let mut considered = HashSet::new();
+
+        for entity_id in touched.order {
+            if !considered.insert(entity_id) {
+                continue;
+            }
+
+            // Those results which were reached afresh are preferred, but any other entity may still reach it
+            let current = match touched.fresh.remove(&entity_id) {
+                Some(entity) => Some(entity),
+                None => {
+                    observed.traversals
+                            .values()
+                            .filter_map(|traversal| traversal.result.as_ref())
+                            .find(|result| result.id == entity_id)
+                            .cloned()
+                },
+            };
+
+            match (observed.yielded.get(&entity_id), current) {
+                (None, Some(entity)) => {
+                    if self.limit.is_none_or(|limit| observed.yielded.len() < limit) {
+                        observed.yielded.insert(entity.id, sorted_memo_ids(&entity));
+                        events.push(QueryEvent::Added(entity));
+                    }
+                },
+                (Some(_), None) => {
+                    observed.yielded.remove(&entity_id);
+                    events.push(QueryEvent::Removed(entity_id));
+                },
+                (Some(previous_memo_ids), Some(entity)) => {
+                    let memo_ids = sorted_memo_ids(&entity);
+                    if *previous_memo_ids != memo_ids {
+                        observed.yielded.insert(entity.id, memo_ids);
+                        events.push(QueryEvent::Changed(entity));
+                    }
+                },
+                (None, None) => {},
+            }
+        }
+
+        Ok(events)
+    }
+
+    fn entities_from_heads(context: &Context, heads: BoxStream<'static, Result<Head, RetrieveError>>)
+                           -> BoxStream<'static, Result<Entity, RetrieveError>> {
+        let context = context.clone();
+
+        heads.and_then(move |head| {
+                 let context = context.clone();
+                 async move { context.get_entity_from_head(head).await }
+             })
+             .try_filter_map(|entity| future::ready(Ok(entity)))
+             .boxed()
+    }
+
+    async fn apply_parts(parts: &[QueryPart], entity: Entity) -> Result<Option<Entity>, RetrieveError> {
+        Ok(Self::apply_parts_traced(parts, entity).await?.result)
+    }
+
+    /// Apply the parts to the entity, noting every entity along the way
+    async fn apply_parts_traced(parts: &[QueryPart], mut entity: Entity) -> Result<Traversal, RetrieveError> {
+        let mut path = vec![entity.id];
+
+        for part in parts {
+            match part {
+                QueryPart::ByEntityId(entity_ids) => {
+                    if !entity_ids.contains(&entity.id) {
+                        return Ok(Traversal { path, result: None });
+                    }
+                },
+                QueryPart::ByFieldComparison { field,
+                                               comparison,
+                                               value, } => {
+                    match entity.get_typed_value(field).await? {
+                        Some(v) if comparison.matches(v.compare(value)) => {},
+                        _ => return Ok(Traversal { path, result: None }),
+                    }
+                },
+                QueryPart::Relation(slot_id) => {
+                    entity = match entity.get_relation(*slot_id).await? {
+                        Some(related) => related,
+                        None => return Ok(Traversal { path, result: None }),
+                    };
+                    path.push(entity.id);
+                },
+            }
+        }
+
+        Ok(Traversal { path,
+                       result: Some(entity) })
+    }
+}
+
+/// The progress of an executed query through its candidates
+struct Execution {
+    candidates: BoxStream<'static, Result<Entity, RetrieveError>>,
+    parts:      Vec<QueryPart>,
+    remaining:  Option<usize>,
+    yielded:    HashSet<EntityId>,
+}
+
+/// The entities visited in applying the parts of a query to an entity, and the result, if it matched
+struct Traversal {
+    path:   Vec<EntityId>,
+    result: Option<Entity>,
+}
+
+/// The state of an observed query
+#[derive(Default)]
+struct ObservedResults {
+    /// The traversal from each entity which matched, or which was found not to match only by way of some other entity
+    traversals: HashMap<EntityId, Traversal>,
+    /// The entities whose traversals visit each entity
+    via:        HashMap<EntityId, HashSet<EntityId>>,
+    /// The memo ids of each result as last yielded
+    yielded:    HashMap<EntityId, Vec<MemoId>>,
+}
+
+impl ObservedResults {
+    /// Note the traversal from the given entity, which supersedes any previous one, along with the results reached
+    /// presently and previously. Entities which didn't match in their own right can only come to match by way of their
+    /// own memos, so their traversals needn't be retained.
+    fn record(&mut self, source: EntityId, traversal: Traversal, touched: &mut Touched) {
+        if let Some(ref result) = traversal.result {
+            touched.order.push(result.id);
+            touched.fresh.insert(result.id, result.clone());
+        }
+        if let Some(previous) = self.traversals.remove(&source) {
+            for entity_id in previous.path {
+                if let Entry::Occupied(mut e) = self.via.entry(entity_id) {
+                    e.get_mut().remove(&source);
+                    if e.get().is_empty() {
+                        e.remove();
+                    }
+                }
+            }
+
+            touched.order.extend(previous.result.map(|result| result.id));
+        }
+
+        if traversal.result.is_some() || traversal.path.len() > 1 {
+            for entity_id in traversal.path.iter() {
+                self.via.entry(*entity_id).or_default().insert(source);
+            }
+
+            self.traversals.insert(source, traversal);
+        }
+    }
+
+    fn sources_via(&self, entity_id: EntityId) -> Vec<EntityId> {
+        match self.via.get(&entity_id) {
+            Some(sources) => sources.iter().cloned().collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// The results reached by some entities, afresh or previously, in the order in which they were reached
+#[derive(Default)]
+struct Touched {
+    order: Vec<EntityId>,
+    fresh: HashMap<EntityId, Entity>,
+}
+
+fn sorted_memo_ids(entity: &Entity) -> Vec<MemoId> {
+    let mut memo_ids = entity.head.memo_ids();
+    memo_ids.sort();
+    memo_ids
+}
+
+/// A change to the result set of an observed query
+#[derive(Debug)]
+pub enum QueryEvent {
+    /// The entity has come to match the query
+    Added(Entity),
+    /// The entity no longer matches the query
+    Removed(EntityId),
+    /// The entity still matches the query, but has been edited
+    Changed(Entity),
+}
+
+/// The stream of changes to the result set of a query. The query is no longer observed once this is dropped.
+pub struct QueryObserver {
+    rx:    mpsc::Receiver<QueryEvent>,
+    _task: RemoteHandle<()>,
+}
+
+impl Stream for QueryObserver {
+    type Item = QueryEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut TaskContext) -> Poll<Option<QueryEvent>> {
+        self.get_mut().rx.poll_next_unpin(cx)
+    }
+}
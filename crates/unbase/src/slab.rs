@@ -18,38 +18,65 @@ pub use self::{
         SlabRef,
         SlabRefInner,
     },
+    subscriber::HeadReceiver,
 };
 
 use crate::{
     context::Context,
+    error::StorageError,
+    head::{
+        serde::HeadSeed,
+        Head,
+    },
     network::{
         Network,
         Transmitter,
         TransportAddress,
     },
-    slab::agent::SlabAgent,
+    slab::{
+        agent::{
+            SlabAgent,
+            MAINTENANCE_INTERVAL,
+        },
+        storage::{
+            Storage,
+            StoredSlab,
+            VolatileStorage,
+        },
+    },
+    util::serde::VecSeed,
 };
 
+#[cfg(not(target_arch = "wasm32"))]
+use crate::slab::storage::FileStorage;
+
+use futures::future::RemoteHandle;
+use serde::de::DeserializeSeed;
 use std::{
     ops::Deref,
     sync::{
         Arc,
         Mutex,
         RwLock,
+        Weak,
     },
+    time::Instant,
 };
+use timer::Delay;
 use tracing::info;
 
 pub(crate) mod agent;
 mod common_structs;
 mod handle;
 mod state;
+pub(crate) mod subscriber;
 
 mod memo;
 mod memoref;
 mod slabref;
+pub mod storage;
 
-pub type SlabId = u32;
+pub type SlabId = u64;
 
 #[derive(Clone)]
 pub struct Slab {
@@ -60,6 +87,7 @@ pub struct Slab {
     //    dispatch_channel: mpsc::Sender<MemoRef>,
     //    dispatcher: Arc<RemoteHandle<()>>,
     handle:           SlabHandle,
+    _maintainer:      Arc<RemoteHandle<()>>,
 }
 
 impl Deref for Slab {
@@ -71,10 +99,71 @@ impl Deref for Slab {
 }
 
 impl Slab {
+    /// Create a new Slab whose memos are held only in memory
     #[tracing::instrument]
     pub fn new(net: &Network) -> Slab {
-        let id = net.generate_slab_id();
+        Self::new_with_storage(net, Box::new(VolatileStorage::new()))
+    }
+
+    /// Create a new Slab which records its memos to the provided storage backend
+    #[tracing::instrument(skip(storage))]
+    pub fn new_with_storage(net: &Network, storage: Box<dyn Storage>) -> Slab {
+        let me = Self::assemble(net, net.generate_slab_id(), storage);
+
+        net.register_local_slab(me.handle());
+
+        net.conditionally_generate_root_index_seed(&me.handle);
+
+        me
+    }
+
+    /// Reopen the Slab which was previously stored in the data directory at the provided path, or create a new one
+    /// there if the directory is empty.
+    ///
+    /// The slab resumes with its previous id, counters, resident memos and known peers. If it had a root index seed,
+    /// it rejoins that system immediately rather than waiting for a peer to supply one, and announces itself to any
+    /// remote peers which it knew of.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tracing::instrument(skip(path))]
+    pub fn open<P: AsRef<std::path::Path>>(net: &Network, path: P) -> Result<Slab, StorageError> {
+        let storage = FileStorage::open(path)?;
+        let stored = storage.load()?;
+
+        let id = match stored.slab_id {
+            Some(id) => id,
+            None => net.generate_slab_id(),
+        };
+
+        let peers = stored.peers.clone();
+        let me = Self::assemble(net, id, Box::new(storage));
+        let root_index_seed = me.restore(stored)?;
+
+        net.register_local_slab(me.handle());
+
+        match root_index_seed {
+            Some(seed) => {
+                net.apply_root_index_seed(&me.agent.presence_for_origin(&me.my_ref), &seed, &me.my_ref);
+            },
+            None => {
+                net.conditionally_generate_root_index_seed(&me.handle);
+            },
+        }
 
+        // Local and simulated peers can't have survived the restart, but remote ones may have
+        for presence in peers.iter() {
+            if let Ok(peer_ref) = me.agent.slabref_from_presence(presence) {
+                let hello = me.new_memo(None,
+                                        Head::Null,
+                                        MemoBody::SlabPresence { p: me.agent.presence_for_origin(&peer_ref),
+                                                                 r: net.get_root_index_seed(&me.handle), });
+                peer_ref.send(&me.my_ref, &hello);
+            }
+        }
+
+        Ok(me)
+    }
+
+    fn assemble(net: &Network, id: SlabId, storage: Box<dyn Storage>) -> Slab {
         let my_ref_inner = SlabRefInner { slab_id:        id,
                                           owning_slab_id: id, // I own my own ref to me, obviously
                                           presence:       RwLock::new(vec![]), // this bit is just for show
@@ -85,30 +174,76 @@ impl Slab {
         // TODO: figure out how to reconcile this with the simulator
         // let (dispatch_tx_channel, dispatch_rx_channel) = mpsc::channel::<MemoRef>(10);
 
-        let agent = Arc::new(SlabAgent::new(net, my_ref.clone()));
+        let agent = Arc::new(SlabAgent::new(net, my_ref.clone(), storage));
 
         // let dispatcher: RemoteHandle<()> = crate::util::task::spawn_with_handle(
         //     Self::run_dispatcher( agent.clone(), dispatch_rx_channel )
         // );
 
+        let maintainer = crate::util::task::spawn_with_handle(Self::run_maintainer(Arc::downgrade(&agent)));
+
         let handle = SlabHandle { my_ref: my_ref.clone(),
                                   net:    net.clone(),
                                   // dispatch_channel: dispatch_tx_channel.clone(),
                                   agent:  agent.clone(), };
 
-        let me = Slab { id,
-                        // dispatch_channel: dispatch_tx_channel,
-                        // dispatcher: Arc::new(dispatcher),
-                        net: net.clone(),
-                        my_ref,
-                        handle,
-                        agent };
+        Slab { id,
+               // dispatch_channel: dispatch_tx_channel,
+               // dispatcher: Arc::new(dispatcher),
+               net: net.clone(),
+               my_ref,
+               handle,
+               _maintainer: Arc::new(maintainer),
+               agent }
+    }
 
-        net.register_local_slab(me.handle());
+    /// Periodically sweep away lapsed remote subscriptions and renew our own, replicate memos which are short of their
+    /// durability target, and collect garbage, until the slab is stopped
+    async fn run_maintainer(agent: Weak<SlabAgent>) {
+        loop {
+            Delay::new(MAINTENANCE_INTERVAL).await;
 
-        net.conditionally_generate_root_index_seed(&me.handle);
+            match agent.upgrade() {
+                Some(agent) if agent.is_running() => {
+                    agent.maintain_subscriptions(Instant::now());
+                    agent.replicate_memos();
+                    agent.collect_garbage();
+                },
+                _ => return,
+            }
+        }
+    }
 
-        me
+    /// Reconstitute the memos and peerlists which were retained by our storage, returning the stored root index seed
+    fn restore(&self, stored: StoredSlab) -> Result<Option<Head>, StorageError> {
+        for bytes in stored.memos.iter() {
+            let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+            memo_serde::StoredMemoSeed { dest_slab: &self.handle }.deserialize(&mut deserializer)
+                                                                   .map_err(|_| StorageError::Corrupt)?;
+        }
+
+        for (memo_id, entity_id, bytes) in stored.peerlists.iter() {
+            let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+            let peers = VecSeed(memoref_serde::MemoPeerSeed { dest_slab: &self.handle }).deserialize(&mut deserializer)
+                                                                                       .map_err(|_| StorageError::Corrupt)?;
+
+            self.agent.assert_memoref(*memo_id, *entity_id, MemoPeerList::new(peers), None);
+        }
+
+        let root_index_seed = match stored.root_index_seed {
+            Some(ref bytes) => {
+                let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+                let seed = HeadSeed { dest_slab:      &self.handle,
+                                      origin_slabref: &self.my_ref, }.deserialize(&mut deserializer)
+                                                                     .map_err(|_| StorageError::Corrupt)?;
+                Some(seed)
+            },
+            None => None,
+        };
+
+        self.agent.finish_restore(stored.counters);
+
+        Ok(root_index_seed)
     }
 
     // async fn run_dispatcher(agent: Arc<SlabAgent>, mut dispatch_rx_channel: mpsc::Receiver<MemoRef>) {
@@ -124,18 +259,6 @@ impl Slab {
     pub fn create_context(&self) -> Context {
         Context::new(self.handle())
     }
-
-    fn _memo_durability_score(&self, _memo: &Memo) -> u8 {
-        // TODO: devise durability_score algo
-        //       Should this number be inflated for memos we don't care about?
-        //       Or should that be a separate signal?
-
-        // Proposed factors:
-        // Estimated number of copies in the network (my count = count of first order peers + their counts weighted by:
-        // uptime?) Present diasporosity ( my diasporosity score = mean peer diasporosity scores weighted by
-        // what? )
-        0
-    }
 }
 
 impl Drop for Slab {
@@ -1,17 +1,31 @@
 use std::{
-    collections::hash_map::Entry,
+    collections::{
+        hash_map::Entry,
+        HashMap,
+        HashSet,
+        VecDeque,
+    },
     sync::{
         Arc,
         Mutex,
         RwLock,
     },
+    time::{
+        Duration,
+        Instant,
+    },
 };
 
-use tracing::debug;
+use tracing::{
+    debug,
+    error,
+};
 
 use crate::{
+    context::stash::WeakStash,
     error::StorageOpDeclined,
     head::Head,
+    merge::MergePolicy,
     network::{
         SlabRef,
         Transmitter,
@@ -19,10 +33,21 @@ use crate::{
         TransportAddress,
     },
     slab::{
-        state::SlabState,
+        memo::generate_memo_id,
+        state::{
+            HeldSubscription,
+            RemoteSubscription,
+            SlabState,
+        },
+        subscriber::HeadSender,
+        storage::{
+            Storage,
+            StoredCounters,
+        },
         EdgeSet,
         EntityId,
         EntityType,
+        KeyframePolicy,
         Memo,
         MemoBody,
         MemoId,
@@ -37,16 +62,28 @@ use crate::{
         SlabId,
         SlabPresence,
         SlabRefInner,
+        SubscriptionTarget,
+        DEFAULT_DURABILITY_TARGET,
+        MAX_SLOTS,
     },
     Network,
 };
 use futures::channel::mpsc;
 
+/// The lease of the subscriptions held with our peers on behalf of local observers, which are renewed for as long as
+/// the observers remain
+pub(crate) const OBSERVER_SUBSCRIPTION_LEASE: Duration = Duration::from_secs(30);
+
+/// How often the slab sweeps away lapsed remote subscriptions and renews those which it holds, pushes memos which fall
+/// short of their durability target to further peers, and collects garbage
+pub(crate) const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(1);
+
 pub struct SlabAgent {
-    pub id: SlabId,
-    state:  RwLock<SlabState>,
-    net:    Network,
-    my_ref: SlabRef,
+    pub id:  SlabId,
+    state:   RwLock<SlabState>,
+    net:     Network,
+    my_ref:  SlabRef,
+    storage: Box<dyn Storage>,
 }
 
 /// SlabAgent is the agent which holds the lock on SlabState.
@@ -54,18 +91,111 @@ pub struct SlabAgent {
 /// SlabAgent is not allowed to implement async functions because we might inadvertently hold the lock across yield
 /// points. All async functions must be offered by some other module.
 impl SlabAgent {
-    pub fn new(net: &Network, my_ref: SlabRef) -> Self {
+    pub fn new(net: &Network, my_ref: SlabRef, storage: Box<dyn Storage>) -> Self {
         let state = RwLock::new(SlabState::new());
 
+        if let Err(e) = storage.put_slab_id(my_ref.slab_id) {
+            error!("SlabAgent({}) failed to persist slab id: {:?}", my_ref.slab_id, e);
+        }
+
         SlabAgent { id: my_ref.slab_id,
                     state,
                     net: net.clone(),
-                    my_ref }
+                    my_ref,
+                    storage }
     }
 
     pub(crate) fn stop(&self) {
+        let (counters, peers) = {
+            let mut state = self.state.write().unwrap();
+            state.running = false;
+
+            let counters = Self::stored_counters(&state);
+
+            let peers: Vec<SlabPresence> = state.peer_refs
+                                                .iter()
+                                                .flat_map(|slabref| slabref.presence.read().unwrap().clone())
+                                                .collect();
+
+            (counters, peers)
+        };
+
+        let root_index_seed = self.net.get_root_index_seed_for_agent(self);
+
+        let result = self.storage
+                         .put_counters(&counters)
+                         .and_then(|_| self.storage.put_peers(&peers))
+                         .and_then(|_| self.storage.put_root_index_seed(&root_index_seed))
+                         .and_then(|_| self.storage.flush());
+
+        if let Err(e) = result {
+            error!("SlabAgent({}) failed to persist slab state: {:?}", self.id, e);
+        }
+    }
+
+    /// Make a memo which was read back from storage resident again, without treating it as having been received
+    pub(crate) fn restore_memo(&self, memo_id: MemoId, entity_id: Option<EntityId>, parents: Head, body: MemoBody,
+                               origin_slab_id: SlabId)
+                               -> MemoRef {
+        let memo = Memo::new(MemoInner { id: memo_id,
+                                         owning_slab_id: self.id,
+                                         origin_slab_id,
+                                         entity_id,
+                                         parents,
+                                         body });
+
+        self.assert_memoref(memo.id, memo.entity_id, MemoPeerList::new(Vec::new()), Some(memo)).0
+    }
+
+    /// Conclude the reconstitution of this slab from storage, once all memos and peerlists have been restored.
+    ///
+    /// The stored counters may be stale if we were not stopped cleanly, so we resume issuing entity ids after the
+    /// greatest of those and any entity id issued by this slab which we restored.
+    /// We also determine the latest head of each index node we hold, so that new contexts may pick up where the
+    /// previous ones left off.
+    pub(crate) fn finish_restore(&self, counters: Option<StoredCounters>) {
         let mut state = self.state.write().unwrap();
-        state.running = false;
+
+        let last_memo_id = counters.as_ref().map_or(0, |c| c.last_memo_id);
+        let mut last_entity_id = counters.as_ref().map_or(0, |c| c.last_entity_id);
+
+        let mut index_memos: HashMap<EntityId, Vec<MemoRef>> = HashMap::new();
+        let mut superseded: HashSet<MemoId> = HashSet::new();
+
+        for memoref in state.memorefs_by_id.values() {
+            if let Some(entity_id) = memoref.entity_id {
+                if entity_id.slab_id == self.id {
+                    last_entity_id = last_entity_id.max(entity_id.id);
+                }
+
+                if let Some(memo) = memoref.get_memo_if_resident() {
+                    if let EntityType::IndexNode = entity_id.stype {
+                        superseded.extend(memo.parents.memo_ids());
+                        index_memos.entry(entity_id).or_default().push(memoref.clone());
+                    }
+                }
+            }
+        }
+
+        state.counters.last_memo_id = state.counters.last_memo_id.max(last_memo_id);
+        state.counters.last_entity_id = state.counters.last_entity_id.max(last_entity_id);
+
+        state.restored_index_heads = index_memos.into_iter()
+                                                .map(|(entity_id, memorefs)| {
+                                                    Head::Entity { owning_slab_id: self.id,
+                                                                   entity_id,
+                                                                   head: memorefs.into_iter()
+                                                                                 .filter(|mr| !superseded.contains(&mr.id))
+                                                                                 .collect() }
+                                                })
+                                                .filter(|head| head.len() > 0)
+                                                .collect();
+    }
+
+    /// The latest heads of the index nodes which were restored from storage when this slab was opened
+    pub(crate) fn restored_index_heads(&self) -> Vec<Head> {
+        let state = self.state.read().unwrap();
+        state.restored_index_heads.clone()
     }
 
     pub(crate) fn is_running(&self) -> bool {
@@ -92,30 +222,132 @@ impl SlabAgent {
         state.counters.memos_redundantly_received as u64
     }
 
+    pub fn count_of_memos_collected(&self) -> u64 {
+        let state = self.state.read().unwrap();
+        state.counters.memos_collected
+    }
+
+    pub fn count_of_memos_retained_for_peering(&self) -> u64 {
+        let state = self.state.read().unwrap();
+        state.counters.memos_retained_for_peering
+    }
+
     #[allow(unused)]
     pub fn peer_slab_count(&self) -> usize {
         let state = self.state.read().unwrap();
         state.peer_refs.len() as usize
     }
 
+    pub fn keyframe_policy(&self) -> KeyframePolicy {
+        self.state.read().unwrap().keyframe_policy.clone()
+    }
+
+    pub fn set_keyframe_policy(&self, policy: KeyframePolicy) {
+        self.state.write().unwrap().keyframe_policy = policy;
+    }
+
+    /// The durability score which memos of the given entity type are replicated to
+    pub fn durability_target(&self, stype: EntityType) -> u8 {
+        let state = self.state.read().unwrap();
+        state.durability_targets.get(&stype).copied().unwrap_or(DEFAULT_DURABILITY_TARGET)
+    }
+
+    pub fn set_durability_target(&self, stype: EntityType, target: u8) {
+        self.state.write().unwrap().durability_targets.insert(stype, target);
+    }
+
+    pub fn merge_policy(&self, stype: EntityType, field: &str) -> Arc<dyn MergePolicy> {
+        self.state.read().unwrap().merge_policies.get(stype, field)
+    }
+
+    pub fn set_merge_policy_for_field(&self, field: &str, policy: Arc<dyn MergePolicy>) {
+        self.state.write().unwrap().merge_policies.set_for_field(field, policy);
+    }
+
+    pub fn set_merge_policy_for_type(&self, stype: EntityType, policy: Arc<dyn MergePolicy>) {
+        self.state.write().unwrap().merge_policies.set_for_type(stype, policy);
+    }
+
+    /// How well the given memo is preserved by other slabs, so far as we know. Zero for memos we haven't heard of.
+    pub fn memo_durability_score(&self, memo_id: MemoId) -> u8 {
+        match self.get_memoref(memo_id) {
+            Some(memoref) => memoref.peerlist.read().unwrap().durability_score(),
+            None => 0,
+        }
+    }
+
+    fn durability_target_for(&self, memoref: &MemoRef) -> u8 {
+        match memoref.entity_id {
+            Some(entity_id) => self.durability_target(entity_id.stype),
+            None => DEFAULT_DURABILITY_TARGET,
+        }
+    }
+
+    /// Whether the memo is durable enough elsewhere that we may drop our own copy. We ask for some margin above the target,
+    /// as our knowledge of the peers lags behind what they actually hold.
+    fn may_evict(&self, memoref: &MemoRef) -> bool {
+        let target = self.durability_target_for(memoref) as u16;
+        let score = memoref.peerlist.read().unwrap().durability_score() as u16;
+
+        score > 0 && score >= target + target / 2
+    }
+
     #[tracing::instrument]
     pub fn new_memo(&self, entity_id: Option<EntityId>, parents: Head, body: MemoBody) -> MemoRef {
-        let memo_id = {
-            let mut state = self.state.write().unwrap();
-            state.counters.last_memo_id += 1;
-            (self.id as u64).rotate_left(32) | state.counters.last_memo_id as u64
+        let memoref = self.create_memo(entity_id, parents, body);
+        self.consider_emit_memo(&memoref);
+
+        // Contexts apply their own edits, but other observers need to hear about them too, as do the other contexts
+        // of this slab, which share its index
+        Self::notify_index_subscribers(&mut self.state.write().unwrap(), &memoref);
+        self.notify_memo_subscribers(&memoref);
+        self.forward_to_remote_subscribers(&memoref, None);
+
+        memoref
+    }
+
+    /// Create a memo without sending it to anyone. Other slabs only learn of it by way of some later memo which references
+    /// it, and must then request it from us.
+    #[tracing::instrument]
+    pub fn new_memo_unannounced(&self, entity_id: Option<EntityId>, parents: Head, body: MemoBody) -> MemoRef {
+        let memoref = self.create_memo(entity_id, parents, body);
+        Self::notify_index_subscribers(&mut self.state.write().unwrap(), &memoref);
+        self.notify_memo_subscribers(&memoref);
+
+        memoref
+    }
+
+    /// Send a memo which was created by way of `new_memo_unannounced` to those it would have been sent to by `new_memo`
+    pub fn announce_memo(&self, memoref: &MemoRef) {
+        self.consider_emit_memo(memoref);
+        self.forward_to_remote_subscribers(memoref, None);
+    }
+
+    fn create_memo(&self, entity_id: Option<EntityId>, parents: Head, body: MemoBody) -> MemoRef {
+        // Entity memos are content addressed, so identical memos converge regardless of which slab created them.
+        // Other memos are only meaningful as events, so we make each of them distinct. So too are operations, which
+        // count once per write however alike they are, and whose memo ids tag the set items and text they add.
+        let nonce = match (entity_id, &body) {
+            (Some(_), MemoBody::Operation(_)) | (None, _) => {
+                let mut state = self.state.write().unwrap();
+                state.counters.last_memo_id += 1;
+                Some((self.id, state.counters.last_memo_id))
+            },
+            (Some(_), _) => None,
         };
 
+        let memo_id = generate_memo_id(entity_id, &parents, &body, nonce);
+
         debug!(%memo_id);
 
         let memo = Memo::new(MemoInner { id: memo_id,
                                          owning_slab_id: self.id,
+                                         origin_slab_id: self.id,
                                          entity_id,
                                          parents,
                                          body });
 
         let (memoref, _had_memoref) = self.assert_memoref(memo.id, memo.entity_id, MemoPeerList(Vec::new()), Some(memo));
-        self.consider_emit_memo(&memoref);
 
         memoref
     }
@@ -123,9 +355,10 @@ impl SlabAgent {
     pub fn generate_entity_id(&self, stype: EntityType) -> EntityId {
         let mut state = self.state.write().unwrap();
         state.counters.last_entity_id += 1;
-        let id = (self.id as u64).rotate_left(32) | state.counters.last_entity_id as u64;
 
-        EntityId { id, stype }
+        EntityId { slab_id: self.id,
+                   id:      state.counters.last_entity_id,
+                   stype }
     }
 
     #[tracing::instrument]
@@ -133,33 +366,89 @@ impl SlabAgent {
         // Emit memos for durability and notification purposes
         // At present, some memos like peering and slab presence are emitted manually.
         // TODO: This will almost certainly have to change once gossip/plumtree functionality is added
+        self.emit_toward_durability_target(memoref);
+    }
 
-        // TODO: test each memo for durability_score and emit accordingly
-        if let Some(memo) = memoref.get_memo_if_resident() {
-            let needs_peers = self.check_peering_target(&memo);
+    /// Send the memo to as many of our peers as it would take for its durability score to reach the target for its entity
+    /// type, were they all to hold it. The score itself only counts peers once they've confirmed as much by way of a
+    /// Peering memo. Returns the number of peers it was sent to.
+    fn emit_toward_durability_target(&self, memoref: &MemoRef) -> usize {
+        let memo = match memoref.get_memo_if_resident() {
+            Some(memo) => memo,
+            None => return 0,
+        };
 
-            debug!("memo is resident");
-            let state = self.state.read().unwrap();
-            for peer_ref in state.peer_refs
-                                 .iter()
-                                 .filter(|x| !memoref.is_peered_with_slabref(x))
-                                 .take(needs_peers as usize)
-            {
-                peer_ref.send(&self.my_ref, memoref);
+        // Memos which don't do peering would otherwise be routed in loops, as memoref.is_peered_with_slabref() obviously
+        // doesn't work for them. Something here should change when we switch to gossip/plumtree.
+        if !memo.does_peering() {
+            return 0;
+        }
+
+        debug!("memo is resident");
+        let target = self.durability_target_for(memoref);
+        let (mut planned, known) = {
+            let peerlist = memoref.peerlist.read().unwrap();
+            (peerlist.durability_score(), peerlist.0.clone())
+        };
+
+        let peer_refs = self.state.read().unwrap().peer_refs.clone();
+
+        let mut recipients = Vec::new();
+        for peer_ref in peer_refs {
+            if planned >= target {
+                break;
             }
+
+            // Peers which merely participate are sent the memo too, as they'd only count for more once they hold it
+            let weight = match known.iter().find(|peer| peer.slabref.slab_id == peer_ref.slab_id) {
+                Some(MemoPeer { status: MemoPeeringStatus::Resident,
+                                .. })
+                | Some(MemoPeer { status: MemoPeeringStatus::NonParticipating,
+                                  .. }) => continue,
+                Some(peer) => peer.durability_weight(),
+                None => 0,
+            };
+
+            planned = planned.saturating_add(peer_ref.anticipated_lifetime().durability_weight().saturating_sub(weight));
+            recipients.push(peer_ref);
+        }
+
+        for peer_ref in recipients.iter() {
+            peer_ref.send(&self.my_ref, memoref);
         }
+
+        recipients.len()
     }
 
-    fn check_peering_target(&self, memo: &Memo) -> u8 {
-        if memo.does_peering() {
-            5
-        } else {
-            // This is necessary to prevent memo routing loops for now, as
-            // memoref.is_peered_with_slabref() obviously doesn't work for non-peered memos
-            // something here should change when we switch to gossip/plumtree, but
-            // I'm not sufficiently clear on that at the time of this writing
-            0
+    /// Push the resident memos whose durability score is below the target for their entity type to further peers, as
+    /// when a target has been raised, or peers have gone away. Returns the number of memos which are short of their
+    /// target. Those which were pushed only count toward it once the recipients confirm that they hold them, and are
+    /// reported here until then.
+    pub fn replicate_memos(&self) -> usize {
+        let memorefs: Vec<MemoRef> = {
+            let state = self.state.read().unwrap();
+            state.memorefs_by_id
+                 .values()
+                 .filter(|memoref| memoref.entity_id.is_some() && memoref.is_resident())
+                 .cloned()
+                 .collect()
+        };
+
+        let mut short = 0;
+        for memoref in memorefs.iter() {
+            if memoref.peerlist.read().unwrap().durability_score() < self.durability_target_for(memoref) {
+                self.emit_toward_durability_target(memoref);
+                short += 1;
+            }
         }
+
+        short
+    }
+
+    /// The memoref for the given memo id, if this slab has heard of it
+    pub(crate) fn get_memoref(&self, memo_id: MemoId) -> Option<MemoRef> {
+        let state = self.state.read().unwrap();
+        state.memorefs_by_id.get(&memo_id).cloned()
     }
 
     pub fn memo_wait_channel(&self, memo_id: MemoId) -> futures::channel::oneshot::Receiver<Memo> {
@@ -179,9 +468,24 @@ impl SlabAgent {
         rx
     }
 
-    pub fn observe_index(&self, tx: mpsc::Sender<Head>) {
+    /// Index observers are served by a subscription to the root index and everything beneath it, where we know of it
+    pub(crate) fn observe_index(&self, tx: HeadSender) {
+        self.state.write().unwrap().index_subscriptions.push(tx);
+
+        if let Some(root_index_id) = self.net.get_root_index_seed_for_agent(self).entity_id() {
+            self.hold_observed_subscription(SubscriptionTarget::IndexSubtree(root_index_id));
+        }
+    }
+
+    pub(crate) fn register_stash(&self, stash: WeakStash) {
         let mut state = self.state.write().unwrap();
-        state.index_subscriptions.push(tx);
+        state.stashes.push(stash);
+    }
+
+    /// Subscribe to every entity memo, whether it was created locally or received from another slab
+    pub fn observe_memos(&self, tx: mpsc::UnboundedSender<Head>) {
+        let mut state = self.state.write().unwrap();
+        state.memo_subscriptions.push(tx);
     }
 
     #[tracing::instrument]
@@ -201,8 +505,16 @@ impl SlabAgent {
     }
 
     /// Perform necessary tasks given a newly arrived memo on this slab
+    ///
+    /// Because memo ids are content addressed, a memo which was already resident is an exact duplicate of one we've
+    /// handled before, and there is nothing further to do.
     #[tracing::instrument(skip(self), level = "trace")]
-    pub fn handle_memo_from_other_slab(&self, memo: &Memo, memoref: &MemoRef, origin_slabref: &SlabRef) {
+    pub fn handle_memo_from_other_slab(&self, memo: &Memo, memoref: &MemoRef, origin_slabref: &SlabRef, duplicate: bool) {
+        if duplicate {
+            debug!("SlabAgent({}) ignoring duplicate memo {}", self.id, memo.id);
+            return;
+        }
+
         tracing::info!("SlabAgent({})::handle_memo_from_other_slab({:?})", self.id, memo);
 
         match memo.body {
@@ -211,11 +523,7 @@ impl SlabAgent {
                                      r: ref root_index_seed, } => {
                 match root_index_seed {
                     &Head::Entity { .. } | &Head::Anonymous { .. } => {
-                        // HACK - this should be done inside the deserialize
-                        for memoref in root_index_seed.iter() {
-                            memoref.update_peer(origin_slabref, MemoPeeringStatus::Resident);
-                        }
-
+                        // The origin's own peering status for the seed memorefs was applied as they were deserialized
                         self.net.apply_root_index_seed(&presence, root_index_seed, &self.my_ref);
                     },
                     &Head::Null => {},
@@ -248,12 +556,9 @@ impl SlabAgent {
                 }
             },
             MemoBody::Peering(memo_id, entity_id, ref peerlist) => {
-                let (peered_memoref, _had_memo) = self.assert_memoref(memo_id, entity_id, peerlist.clone(), None);
-
-                // Don't peer with yourself
-                for peer in peerlist.iter().filter(|p| p.slabref.0.slab_id != self.id) {
-                    peered_memoref.update_peer(&peer.slabref, peer.status.clone());
-                }
+                // The sender's report of its own status is first hand, whereas what it tells us of other peers may be
+                // stale. Either way, the most recent report of each peer is applied.
+                self.assert_memoref(memo_id, entity_id, peerlist.clone(), None);
             },
             MemoBody::MemoRequest(ref desired_memo_ids, ref requesting_slabref) => {
                 if requesting_slabref.0.slab_id != self.id {
@@ -281,8 +586,7 @@ impl SlabAgent {
                                                                 MemoBody::Peering(
                                 *desired_memo_id,
                                 None,
-                                MemoPeerList::new(vec![MemoPeer { slabref: self.my_ref.clone(),
-                                                                  status:  MemoPeeringStatus::NonParticipating, }]),
+                                MemoPeerList::new(vec![MemoPeer::new(self.my_ref.clone(), MemoPeeringStatus::NonParticipating)]),
                             ),
                             );
                             requesting_slabref.send(&self.my_ref, &peering_memoref)
@@ -290,10 +594,241 @@ impl SlabAgent {
                     }
                 }
             },
+            MemoBody::Subscription { t, ref s, x } if s.slab_id != self.id => {
+                let mut state = self.state.write().unwrap();
+
+                if x == 0 {
+                    if let Entry::Occupied(mut e) = state.remote_subscriptions.entry(t.entity_id()) {
+                        e.get_mut().retain(|sub| sub.slabref.slab_id != s.slab_id);
+                        if e.get().is_empty() {
+                            e.remove();
+                        }
+                    }
+                } else {
+                    let subscription = RemoteSubscription { slabref: s.clone(),
+                                                            expires: Instant::now() + Duration::from_millis(x),
+                                                            subtree: matches!(t, SubscriptionTarget::IndexSubtree(_)), };
+
+                    Self::add_remote_subscription(&mut state, t.entity_id(), subscription);
+                }
+            },
             _ => {},
         }
     }
 
+    /// Ask our peers to forward memos for the target to us until the lease runs out. The subscription is renewed by
+    /// `maintain_subscriptions`, and extended to peers as we come to know of them, until it's cancelled with a zero
+    /// lease. Should we go away, it lapses on its own.
+    pub fn subscribe_remote(&self, target: SubscriptionTarget, lease: Duration) {
+        self.hold_subscription(target, lease, false);
+    }
+
+    /// Subscribe on behalf of a local observer, unless we already hold a subscription to the target
+    fn hold_observed_subscription(&self, target: SubscriptionTarget) {
+        if !self.state.read().unwrap().held_subscriptions.contains_key(&target) {
+            self.hold_subscription(target, OBSERVER_SUBSCRIPTION_LEASE, true);
+        }
+    }
+
+    fn hold_subscription(&self, target: SubscriptionTarget, lease: Duration, observed: bool) {
+        let previous = {
+            let state = self.state.read().unwrap();
+            state.held_subscriptions.get(&target).map(|held| (held.memoref.clone(), held.observed))
+        };
+
+        let parents = match previous {
+            Some((ref memoref, _)) => memoref.to_head(),
+            None => Head::Null,
+        };
+
+        let memoref = self.new_memo(None,
+                                    parents,
+                                    MemoBody::Subscription { t: target,
+                                                             s: self.my_ref.clone(),
+                                                             x: lease.as_millis() as u64, });
+
+        let peer_refs = {
+            let mut state = self.state.write().unwrap();
+
+            if lease == Duration::from_millis(0) {
+                state.held_subscriptions.remove(&target);
+            } else {
+                // An explicit subscription outlasts the observers which may also have wanted it
+                let observed = observed && previous.is_none_or(|(_, was_observed)| was_observed);
+
+                state.held_subscriptions.insert(target,
+                                                HeldSubscription { lease,
+                                                                   memoref: memoref.clone(),
+                                                                   renewed: Instant::now(),
+                                                                   observed });
+            }
+
+            state.peer_refs.clone()
+        };
+
+        for peer_ref in peer_refs {
+            peer_ref.send(&self.my_ref, &memoref);
+        }
+    }
+
+    /// Drop the remote subscriptions whose leases have run out as of `now`, and renew those which we hold, once half of
+    /// their lease has passed. Subscriptions held on behalf of observers which have since gone away are cancelled
+    /// instead, and any which are newly wanted by our observers are taken out. The slab calls this periodically.
+    pub fn maintain_subscriptions(&self, now: Instant) {
+        let root_index_id = self.net.get_root_index_seed_for_agent(self).entity_id();
+
+        let (renew, cancel, take) = {
+            let mut state = self.state.write().unwrap();
+
+            state.remote_subscriptions.retain(|_, subscriptions| {
+                                          subscriptions.retain(|sub| sub.expires > now);
+                                          !subscriptions.is_empty()
+                                      });
+
+            let mut wanted: HashSet<SubscriptionTarget> = HashSet::new();
+            for (entity_id, senders) in state.entity_subscriptions.iter() {
+                if senders.iter().any(|tx| !tx.is_closed()) {
+                    wanted.insert(SubscriptionTarget::Entity(*entity_id));
+                }
+            }
+            if let Some(root_index_id) = root_index_id {
+                if state.index_subscriptions.iter().any(|tx| !tx.is_closed()) {
+                    wanted.insert(SubscriptionTarget::IndexSubtree(root_index_id));
+                }
+            }
+
+            let mut renew = Vec::new();
+            let mut cancel = Vec::new();
+            for (target, held) in state.held_subscriptions.iter() {
+                if held.observed && !wanted.contains(target) {
+                    cancel.push(*target);
+                } else if now >= held.renewed + held.lease / 2 {
+                    renew.push((*target, held.lease, held.observed));
+                }
+            }
+
+            let take: Vec<SubscriptionTarget> =
+                wanted.into_iter().filter(|target| !state.held_subscriptions.contains_key(target)).collect();
+
+            (renew, cancel, take)
+        };
+
+        for target in cancel {
+            self.hold_subscription(target, Duration::from_millis(0), true);
+        }
+        for (target, lease, observed) in renew {
+            self.hold_subscription(target, lease, observed);
+        }
+        for target in take {
+            self.hold_observed_subscription(target);
+        }
+    }
+
+    /// Extend the subscriptions which we hold to a peer which we've only now come to know of
+    fn send_held_subscriptions(&self, peer_ref: &SlabRef) {
+        let memorefs: Vec<MemoRef> = {
+            let state = self.state.read().unwrap();
+            state.held_subscriptions.values().map(|held| held.memoref.clone()).collect()
+        };
+
+        for memoref in memorefs {
+            peer_ref.send(&self.my_ref, &memoref);
+        }
+    }
+
+    /// Subtree subscriptions cover the descendants we already know of, as well as those which are added later
+    fn add_remote_subscription(state: &mut SlabState, entity_id: EntityId, subscription: RemoteSubscription) {
+        let mut pending = vec![entity_id];
+
+        while let Some(entity_id) = pending.pop() {
+            let subscriptions = state.remote_subscriptions.entry(entity_id).or_default();
+
+            let extend = match subscriptions.iter_mut().find(|sub| sub.slabref.slab_id == subscription.slabref.slab_id) {
+                Some(existing) => {
+                    let extend = subscription.subtree && (!existing.subtree || subscription.expires > existing.expires);
+                    existing.expires = existing.expires.max(subscription.expires);
+                    existing.subtree |= subscription.subtree;
+                    extend
+                },
+                None => {
+                    subscriptions.push(subscription.clone());
+                    subscription.subtree
+                },
+            };
+
+            if extend {
+                let memo_ids = match state.memo_ids_by_entity.get(&entity_id) {
+                    Some(memo_ids) => memo_ids,
+                    None => continue,
+                };
+
+                for memo in memo_ids.iter()
+                                    .filter_map(|memo_id| state.memorefs_by_id.get(memo_id))
+                                    .filter_map(|memoref| memoref.get_memo_if_resident())
+                {
+                    if let Some((edges, _)) = memo.get_edges() {
+                        pending.extend(edges.0.values().filter_map(|head| head.entity_id()));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Send the memo to any remote slabs which have subscribed to its entity, other than the one it came from.
+    /// Subtree subscriptions are extended to the entities which the memo has edges to.
+    fn forward_to_remote_subscribers(&self, memoref: &MemoRef, origin_slabref: Option<&SlabRef>) {
+        let (entity_id, memo) = match (memoref.entity_id, memoref.get_memo_if_resident()) {
+            (Some(entity_id), Some(memo)) => (entity_id, memo),
+            _ => return,
+        };
+
+        let recipients: Vec<SlabRef> = {
+            let mut state = self.state.write().unwrap();
+            let now = Instant::now();
+
+            let subscriptions = match state.remote_subscriptions.get_mut(&entity_id) {
+                Some(subscriptions) => {
+                    subscriptions.retain(|sub| sub.expires > now);
+                    subscriptions.clone()
+                },
+                None => return,
+            };
+
+            if subscriptions.is_empty() {
+                state.remote_subscriptions.remove(&entity_id);
+                return;
+            }
+
+            if let Some((edges, _)) = memo.get_edges() {
+                for subscription in subscriptions.iter().filter(|sub| sub.subtree) {
+                    for child_entity_id in edges.0.values().filter_map(|head| head.entity_id()) {
+                        Self::add_remote_subscription(&mut state, child_entity_id, subscription.clone());
+                    }
+                }
+            }
+
+            subscriptions.into_iter()
+                         .map(|sub| sub.slabref)
+                         .filter(|slabref| origin_slabref.is_none_or(|origin| origin.slab_id != slabref.slab_id))
+                         .filter(|slabref| !memoref.is_peered_with_slabref(slabref))
+                         .collect()
+        };
+
+        for slabref in recipients {
+            slabref.send(&self.my_ref, memoref);
+        }
+    }
+
+    pub fn count_of_remote_subscriptions(&self) -> usize {
+        let now = Instant::now();
+        let state = self.state.read().unwrap();
+
+        state.remote_subscriptions
+             .values()
+             .map(|subscriptions| subscriptions.iter().filter(|sub| sub.expires > now).count())
+             .sum()
+    }
+
     // should this be a function of the slabref rather than the owning slab?
     pub fn presence_for_origin(&self, origin_slabref: &SlabRef) -> SlabPresence {
         // Get the address that the remote slab would recogize
@@ -344,17 +879,22 @@ impl SlabAgent {
         }
     }
 
-    pub(crate) fn observe_entity(&self, entity_id: EntityId, tx: mpsc::Sender<Head>) {
-        let mut state = self.state.write().unwrap();
+    /// Entity observers are served by a subscription to the entity with our peers as well
+    pub(crate) fn observe_entity(&self, entity_id: EntityId, tx: HeadSender) {
+        {
+            let mut state = self.state.write().unwrap();
 
-        match state.entity_subscriptions.entry(entity_id) {
-            Entry::Vacant(e) => {
-                e.insert(vec![tx]);
-            },
-            Entry::Occupied(mut e) => {
-                e.get_mut().push(tx);
-            },
-        };
+            match state.entity_subscriptions.entry(entity_id) {
+                Entry::Vacant(e) => {
+                    e.insert(vec![tx]);
+                },
+                Entry::Occupied(mut e) => {
+                    e.get_mut().push(tx);
+                },
+            };
+        }
+
+        self.hold_observed_subscription(SubscriptionTarget::Entity(entity_id));
     }
 
     #[tracing::instrument]
@@ -364,65 +904,44 @@ impl SlabAgent {
             None => return,
         };
 
-        // TODO POSTMERGE - come up with a memoref receiving strategy which allows backpressure when the queue is full
-        //        let mut futs = Vec::new();
-
         {
             let mut state = self.state.write().unwrap();
 
-            if let EntityType::IndexNode = entity_id.stype {
-                // TODO3 - update this to consider popularity of this node, and/or common points of reference with a
-                // given context selective hearing?
-
-                let senders = &mut state.index_subscriptions;
-                let len = senders.len();
-
-                // TODO POSTMERGE - alright, this approach isn't going to work.
-                //    fn send(&mut self, item: Item) -> Send<'_, Self, Item>
-                // it returns a Send future which contains &mut self
-                // so collecting these futures won't work unless we clone...
-                // and maybe not even then, because the clones won't live long enough for &mut self
+            // Subscribers which have gone away are dropped. Those which are merely slow have their heads coalesced by
+            // the channel, so there is never any need to wait on them.
+            Self::notify_index_subscribers(&mut state, &memoref);
 
-                for i in (0..len).rev() {
-                    let r = { senders[i].try_send(memoref.to_head()) };
+            if let Entry::Occupied(mut e) = state.entity_subscriptions.entry(entity_id) {
+                e.get_mut().retain(|tx| tx.send(memoref.to_head()).is_ok());
 
-                    match r {
-                        Ok(_) => {},
-                        Err(e) => {
-                            // the fact that SendError.kind is private is :facepalm:
-                            if e.is_disconnected() {
-                                senders.swap_remove(i);
-                            } else {
-                                panic!("one of the index_subscriptions queues is full, and I haven't implemented async sending \
-                                        yet")
-                            }
-                        },
-                    }
+                if e.get().is_empty() {
+                    e.remove();
                 }
             }
+        }
 
-            if let Some(ref mut senders) = state.entity_subscriptions.get_mut(&entity_id) {
-                let len = senders.len();
-                for i in (0..len).rev() {
-                    let r = { senders[i].try_send(memoref.to_head()) };
-
-                    match r {
-                        Ok(_) => {},
-                        Err(e) => {
-                            // the fact that SendError.kind is private is :facepalm:
-                            if e.is_disconnected() {
-                                senders.swap_remove(i);
-                            } else {
-                                panic!("one of the entity_subscriptions queues is full, and I haven't implemented async sending \
-                                        yet")
-                            }
-                        },
-                    }
-                }
-            }
+        self.notify_memo_subscribers(&memoref);
+    }
+
+    fn notify_index_subscribers(state: &mut SlabState, memoref: &MemoRef) {
+        if let Some(EntityId { stype: EntityType::IndexNode,
+                               .. }) = memoref.entity_id
+        {
+            // TODO3 - update this to consider popularity of this node, and/or common points of reference with a
+            // given context selective hearing?
+
+            state.index_subscriptions.retain(|tx| tx.send(memoref.to_head()).is_ok());
+        }
+    }
+
+    /// Memo subscribers act upon each memo in turn, so none may be dropped
+    fn notify_memo_subscribers(&self, memoref: &MemoRef) {
+        if memoref.entity_id.is_none() {
+            return;
         }
 
-        //        join_all(futs).await;
+        let mut state = self.state.write().unwrap();
+        state.memo_subscriptions.retain(|tx| tx.unbounded_send(memoref.to_head()).is_ok());
     }
 
     #[tracing::instrument]
@@ -502,14 +1021,16 @@ impl SlabAgent {
                                memo.entity_id,
                                self.localize_head(&memo.parents, from_slabref, false),
                                self.localize_memobody(&memo.body, from_slabref),
+                               memo.origin_slab_id,
                                from_slabref,
                                peerlist)
             .0
     }
 
     #[tracing::instrument(skip(self), level = "debug")]
+    #[allow(clippy::too_many_arguments)]
     pub fn reconstitute_memo(&self, memo_id: MemoId, entity_id: Option<EntityId>, parents: Head, body: MemoBody,
-                             origin_slabref: &SlabRef, peerlist: &MemoPeerList)
+                             origin_slab_id: SlabId, origin_slabref: &SlabRef, peerlist: &MemoPeerList)
                              -> (Memo, MemoRef, bool) {
         debug!("SlabAgent({})::reconstitute_memo({:?})", self.id, body);
 
@@ -518,16 +1039,22 @@ impl SlabAgent {
 
         let memo = Memo::new(MemoInner { id: memo_id,
                                          owning_slab_id: self.id,
+                                         origin_slab_id,
                                          entity_id,
                                          parents,
                                          body });
 
+        let duplicate = {
+            let state = self.state.read().unwrap();
+            state.memorefs_by_id.get(&memo_id).is_some_and(|mr| mr.is_resident())
+        };
+
         let (memoref, had_memoref) = self.assert_memoref(memo.id, memo.entity_id, peerlist.clone(), Some(memo.clone()));
 
         {
             let mut state = self.state.write().unwrap();
             state.counters.memos_received += 1;
-            if had_memoref {
+            if duplicate {
                 state.counters.memos_redundantly_received += 1;
             }
         }
@@ -540,7 +1067,13 @@ impl SlabAgent {
             // / block-tree NOTE: this might be a correct place to employ selective hearing. Highest
             // liklihood if the entity is in any of our contexts, otherwise
 
-            self.handle_memo_from_other_slab(memo, &memoref, &origin_slabref);
+            self.handle_memo_from_other_slab(memo, &memoref, &origin_slabref, duplicate);
+
+            if !duplicate {
+                self.forward_to_remote_subscribers(&memoref, Some(origin_slabref));
+            }
+
+            // Even for a duplicate, the sender evidently didn't know that we have it
             self.do_peering(&memoref, &origin_slabref);
         }
 
@@ -566,13 +1099,17 @@ impl SlabAgent {
             },
             &MemoBody::Edge(ref edgeset) => MemoBody::Edge(self.localize_edgeset(edgeset, from_slabref)),
             &MemoBody::Edit(ref hm) => MemoBody::Edit(hm.clone()),
+            &MemoBody::Operation(ref ops) => MemoBody::Operation(ops.clone()),
+            &MemoBody::Tombstone(t) => MemoBody::Tombstone(t),
             &MemoBody::FullyMaterialized { ref v,
                                            ref r,
                                            ref t,
-                                           ref e, } => {
+                                           ref e,
+                                           ref o, } => {
                 MemoBody::FullyMaterialized { v: v.clone(),
                                               r: r.clone(),
                                               e: self.localize_edgeset(e, from_slabref),
+                                              o: o.clone(),
                                               t: t.clone(), }
             },
             &MemoBody::PartiallyMaterialized { ref v,
@@ -591,6 +1128,11 @@ impl SlabAgent {
             &MemoBody::MemoRequest(ref memo_ids, ref slabref) => {
                 MemoBody::MemoRequest(memo_ids.clone(), self.localize_slabref(slabref))
             },
+            &MemoBody::Subscription { t, ref s, x } => {
+                MemoBody::Subscription { t,
+                                         s: self.localize_slabref(s),
+                                         x }
+            },
         }
     }
 
@@ -599,7 +1141,8 @@ impl SlabAgent {
                              .iter()
                              .map(|p| {
                                  MemoPeer { slabref: self.localize_slabref(&p.slabref),
-                                            status:  p.status.clone(), }
+                                            status:  p.status.clone(),
+                                            as_of:   p.as_of, }
                              })
                              .collect())
     }
@@ -622,6 +1165,7 @@ impl SlabAgent {
         let mut ptr = memoref.ptr.write().unwrap();
 
         if let MemoRefPtr::Remote = *ptr {
+            self.persist_memo(&memo);
             *ptr = MemoRefPtr::Resident(memo);
 
             // should this be using do_peering_for_memo?
@@ -633,8 +1177,7 @@ impl SlabAgent {
                               memoref.to_head(),
                               MemoBody::Peering(memoref.id,
                                                 memoref.entity_id,
-                                                MemoPeerList::new(vec![MemoPeer { slabref: self.my_ref.clone(),
-                                                                                  status:  MemoPeeringStatus::Resident, }])));
+                                                MemoPeerList::new(vec![MemoPeer::new(self.my_ref.clone(), MemoPeeringStatus::Resident)])));
 
             for peer in memoref.peerlist.read().unwrap().iter() {
                 peer.slabref.send(&self.my_ref, &peering_memoref);
@@ -671,13 +1214,16 @@ impl SlabAgent {
             }
         }
 
+        if let Err(e) = self.storage.remove_memo(memoref.id) {
+            error!("SlabAgent({}) failed to remove memo {} from storage: {:?}", self.id, memoref.id, e);
+        }
+
         let peering_memoref =
             self.new_memo(None,
                           memoref.to_head(),
                           MemoBody::Peering(memoref.id,
                                             memoref.entity_id,
-                                            MemoPeerList::new(vec![MemoPeer { slabref: self.my_ref.clone(),
-                                                                              status:  MemoPeeringStatus::Participating, }])));
+                                            MemoPeerList::new(vec![MemoPeer::new(self.my_ref.clone(), MemoPeeringStatus::Participating)])));
 
         // self.consider_emit_memo(&memoref);
 
@@ -692,8 +1238,16 @@ impl SlabAgent {
     pub fn assert_memoref(&self, memo_id: MemoId, entity_id: Option<EntityId>, peerlist: MemoPeerList, memo: Option<Memo>)
                           -> (MemoRef, bool) {
         let had_memoref;
-        let memoref = match self.state.write().unwrap().memorefs_by_id.entry(memo_id) {
+        let mut residentized = None;
+        let peered;
+
+        let mut state = self.state.write().unwrap();
+        let memoref = match state.memorefs_by_id.entry(memo_id) {
             Entry::Vacant(o) => {
+                // As with `MemoRef::apply_peers`, we're never a peer of our own memos
+                let peerlist = MemoPeerList(peerlist.0.into_iter().filter(|p| p.slabref.slab_id != self.id).collect());
+                peered = !peerlist.is_empty();
+
                 let mr = MemoRef(Arc::new(MemoRefInner { id: memo_id,
                                                          owning_slab_id: self.id,
                                                          entity_id,
@@ -701,6 +1255,7 @@ impl SlabAgent {
                                                          ptr: RwLock::new(match memo {
                                                                               Some(m) => {
                                                                                   assert!(self.id == m.owning_slab_id);
+                                                                                  residentized = Some(m.clone());
                                                                                   MemoRefPtr::Resident(m)
                                                                               },
                                                                               None => MemoRefPtr::Remote,
@@ -715,17 +1270,62 @@ impl SlabAgent {
                 if let Some(m) = memo {
                     let mut ptr = mr.ptr.write().unwrap();
                     if let MemoRefPtr::Remote = *ptr {
+                        residentized = Some(m.clone());
                         *ptr = MemoRefPtr::Resident(m)
                     }
                 }
-                mr.apply_peers(&peerlist);
+                peered = mr.apply_peers(&peerlist);
                 mr.clone()
             },
         };
 
+        if let (false, Some(entity_id)) = (had_memoref, entity_id) {
+            state.memo_ids_by_entity.entry(entity_id).or_default().insert(memo_id);
+        }
+        drop(state);
+
+        // Storage is updated only after the state lock has been released
+        if let Some(ref m) = residentized {
+            self.persist_memo(m);
+        }
+        if peered {
+            self.persist_peerlist(&memoref);
+        }
+
         (memoref, had_memoref)
     }
 
+    /// Durably record a memo which has become resident. Only entity memos are retained - Peering, MemoRequest and
+    /// SlabPresence memos are only meaningful at the time they are received.
+    ///
+    /// The counters are recorded along with each memo, so that they survive even if we aren't stopped cleanly.
+    fn persist_memo(&self, memo: &Memo) {
+        if memo.entity_id.is_none() {
+            return;
+        }
+
+        let counters = Self::stored_counters(&self.state.read().unwrap());
+
+        if let Err(e) = self.storage.put_memo(memo).and_then(|_| self.storage.put_counters(&counters)) {
+            error!("SlabAgent({}) failed to store memo {}: {:?}", self.id, memo.id, e);
+        }
+    }
+
+    fn stored_counters(state: &SlabState) -> StoredCounters {
+        StoredCounters { last_memo_id:   state.counters.last_memo_id,
+                         last_entity_id: state.counters.last_entity_id, }
+    }
+
+    fn persist_peerlist(&self, memoref: &MemoRef) {
+        if memoref.entity_id.is_none() {
+            return;
+        }
+
+        if let Err(e) = self.storage.put_peerlist(memoref) {
+            error!("SlabAgent({}) failed to store peerlist for memo {}: {:?}", self.id, memoref.id, e);
+        }
+    }
+
     #[tracing::instrument]
     pub fn assert_slabref(&self, slab_id: SlabId, presence: &[SlabPresence]) -> SlabRef {
         if slab_id == self.id {
@@ -791,6 +1391,8 @@ impl SlabAgent {
 
                 *slabref.0.tx.lock().expect("tx.lock()") = new_trans;
                 *slabref.0.return_address.write().expect("return_address write lock") = return_address;
+
+                self.send_held_subscriptions(&slabref);
             }
         }
 
@@ -819,6 +1421,145 @@ impl SlabAgent {
 
         Ok(())
     }
+
+    /// Drop those memos which no local head needs for projection, and which are durable enough elsewhere.
+    ///
+    /// The heads in the stashes of our contexts, and that of the root index, are followed back to the nearest
+    /// materialized memo of each causal branch, and through the edges along the way to the heads they point to. The
+    /// memos beyond are superseded, as materialized memos carry the CRDT operations before them. Superseded memos are
+    /// remotized where their durability score comfortably exceeds the target for their entity type, and forgotten
+    /// altogether once nothing refers to them. Memos of entities with local subscribers are retained regardless. Remote
+    /// subscribers are forwarded memos as they arrive, and aren't owed those they supersede. Memos which aren't of any
+    /// entity are forgotten once nothing refers to them. Returns the number of entity memos collected.
+    pub fn collect_garbage(&self) -> usize {
+        // Stashes are read before we take our own lock, lest someone holding one of them be waiting on us
+        let stashes = self.state.read().unwrap().stashes.clone();
+        let mut roots: Vec<Head> = stashes.iter().filter_map(|stash| stash.heads()).flatten().collect();
+        roots.push(self.net.get_root_index_seed_for_agent(self));
+
+        let subscribed: HashSet<EntityId> = {
+            let mut state = self.state.write().unwrap();
+            state.stashes.retain(|stash| stash.is_alive());
+            roots.extend(state.restored_index_heads.iter().cloned());
+
+            state.entity_subscriptions.keys().cloned().collect()
+        };
+
+        let mut live: HashSet<MemoId> = HashSet::new();
+        let mut superseded: HashMap<MemoId, MemoRef> = HashMap::new();
+
+        while let Some(head) = roots.pop() {
+            // Each memo is visited along with the slots which have been written by the memos descending it, whose edges
+            // therefore supersede its own. Revisiting a memo by way of another branch may narrow them.
+            let mut shadows: HashMap<MemoId, [bool; MAX_SLOTS]> = HashMap::new();
+            let mut queue: VecDeque<(MemoRef, [bool; MAX_SLOTS])> =
+                head.iter().map(|memoref| (memoref.clone(), [false; MAX_SLOTS])).collect();
+            let mut beyond: Vec<MemoRef> = Vec::new();
+
+            while let Some((memoref, mut shadow)) = queue.pop_front() {
+                if let Some(previous) = shadows.get(&memoref.id) {
+                    let mut narrowed = *previous;
+                    for (slot, shadowed) in narrowed.iter_mut().enumerate() {
+                        *shadowed &= shadow[slot];
+                    }
+
+                    if narrowed == *previous {
+                        continue;
+                    }
+                    shadow = narrowed;
+                }
+                shadows.insert(memoref.id, shadow);
+                live.insert(memoref.id);
+
+                let memo = match memoref.get_memo_if_resident() {
+                    Some(memo) => memo,
+                    None => continue,
+                };
+
+                if let Some((edges, _)) = memo.get_edges() {
+                    for (slot_id, edge_head) in edges.0 {
+                        if !shadow[slot_id as usize] {
+                            shadow[slot_id as usize] = true;
+                            if edge_head.is_some() {
+                                roots.push(edge_head);
+                            }
+                        }
+                    }
+                }
+
+                if memo.get_values().is_some_and(|(_, materialized)| materialized) {
+                    beyond.extend(memo.get_parent_head().iter().cloned());
+                } else {
+                    queue.extend(memo.get_parent_head().iter().map(|parent| (parent.clone(), shadow)));
+                }
+            }
+
+            let mut visited = HashSet::new();
+            while let Some(memoref) = beyond.pop() {
+                if live.contains(&memoref.id) || !visited.insert(memoref.id) {
+                    continue;
+                }
+
+                if let Some(memo) = memoref.get_memo_if_resident() {
+                    beyond.extend(memo.get_parent_head().iter().cloned());
+                }
+                superseded.insert(memoref.id, memoref);
+            }
+        }
+
+        let mut collected = Vec::new();
+        let mut retained = 0;
+        for (memo_id, memoref) in superseded {
+            if live.contains(&memo_id) || !memoref.is_resident() {
+                continue;
+            }
+            if memoref.entity_id.is_some_and(|entity_id| subscribed.contains(&entity_id)) {
+                continue;
+            }
+
+            if !self.may_evict(&memoref) {
+                retained += 1;
+                continue;
+            }
+
+            if self.remotize_memoref(&memoref).is_ok() {
+                collected.push(memo_id);
+            }
+        }
+
+        let mut guard = self.state.write().unwrap();
+        let state = &mut *guard;
+
+        // Other memos are events, which are handled as they arrive. Once nothing refers to one - be it the head of a
+        // later memo, a subscription we hold, a handler still at work on it, or somebody waiting for it to arrive - it
+        // would otherwise keep the memorefs it was about alive, including the notices of the remotizations above.
+        let waited = &state.memo_wait_channels;
+        state.memorefs_by_id.retain(|memo_id, memoref| {
+            memoref.entity_id.is_some() || Arc::strong_count(&memoref.0) > 1 || waited.contains_key(memo_id)
+        });
+
+        for memo_id in collected.iter() {
+            if let Entry::Occupied(entry) = state.memorefs_by_id.entry(*memo_id) {
+                // Our memoref is all that's left of it, and the memo may be requested anew should it be needed again
+                if Arc::strong_count(&entry.get().0) == 1 {
+                    let memoref = entry.remove();
+                    if let Some(entity_id) = memoref.entity_id {
+                        if let Entry::Occupied(mut e) = state.memo_ids_by_entity.entry(entity_id) {
+                            e.get_mut().remove(memo_id);
+                            if e.get().is_empty() {
+                                e.remove();
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        state.counters.memos_collected += collected.len() as u64;
+        state.counters.memos_retained_for_peering += retained;
+
+        collected.len()
+    }
 }
 
 impl std::fmt::Debug for SlabAgent {
@@ -826,3 +1567,200 @@ impl std::fmt::Debug for SlabAgent {
         fmt.debug_struct("Slab").field("state", &self.state.read().unwrap()).finish()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        head::Head,
+        slab::{
+            EntityId,
+            MemoBody,
+            MemoId,
+            MemoPeer,
+            MemoPeerList,
+            MemoPeeringStatus,
+            SubscriptionTarget,
+        },
+        util::simulator::Simulator,
+        Entity,
+        Network,
+        Slab,
+    };
+
+    use std::time::{
+        Duration,
+        Instant,
+    };
+
+    fn has_memo(slab: &Slab, memo_id: MemoId) -> bool {
+        let state = slab.agent.state.read().unwrap();
+        state.memorefs_by_id.get(&memo_id).is_some_and(|memoref| memoref.is_resident())
+    }
+
+    /// The number of entities for which the slab holds a subscription on behalf of the subscriber
+    fn subscriptions_from(slab: &Slab, subscriber: &Slab) -> usize {
+        let state = slab.agent.state.read().unwrap();
+        state.remote_subscriptions
+             .values()
+             .filter(|subscriptions| subscriptions.iter().any(|sub| sub.slabref.slab_id == subscriber.id))
+             .count()
+    }
+
+    fn is_subscribed(slab: &Slab, entity_id: EntityId, subscriber: &Slab) -> bool {
+        let state = slab.agent.state.read().unwrap();
+        state.remote_subscriptions
+             .get(&entity_id)
+             .is_some_and(|subscriptions| subscriptions.iter().any(|sub| sub.slabref.slab_id == subscriber.id))
+    }
+
+    #[unbase_test_util::async_test]
+    async fn remote_subscription_lease() {
+        let net = Network::create_new_system();
+        let simulator = Simulator::new();
+        net.add_transport(Box::new(simulator.clone()));
+        simulator.start();
+
+        let slab_a = Slab::new(&net);
+        let slab_b = Slab::new(&net);
+        let slab_c = Slab::new(&net);
+        let context_a = slab_a.create_context();
+
+        let mut record = Entity::new_with_single_kv(&context_a, "animal_sound", "Moo").await.unwrap();
+        let root_index = context_a.root_index().await.unwrap().get_root_entity_id();
+        simulator.quiesce().await;
+
+        // Long enough that the slabs' own maintenance doesn't renew anything while we're looking
+        let lease = Duration::from_secs(60);
+        slab_b.subscribe_remote(SubscriptionTarget::Entity(record.id), lease);
+        slab_c.subscribe_remote(SubscriptionTarget::IndexSubtree(root_index), lease);
+        simulator.quiesce().await;
+
+        // Subscriptions are registered with every other slab, but never with the subscriber itself
+        assert_eq!(subscriptions_from(&slab_c, &slab_b), 1);
+        assert_eq!(subscriptions_from(&slab_b, &slab_b), 0);
+
+        // The subtree subscription covers the index nodes and records known to slab A, and extends to new ones
+        let subscribed = subscriptions_from(&slab_a, &slab_c);
+        assert!(subscribed > 2);
+
+        let _other = Entity::new_with_single_kv(&context_a, "animal_sound", "Meow").await.unwrap();
+        record.set_value("animal_sound", "Woof").await.unwrap();
+        simulator.quiesce().await;
+
+        assert!(subscriptions_from(&slab_a, &slab_c) > subscribed);
+        for memo_id in record.head.memo_ids() {
+            assert!(has_memo(&slab_b, memo_id));
+        }
+
+        // Slabs which come along later are asked too
+        let slab_d = Slab::new(&net);
+        simulator.quiesce().await;
+        assert_eq!(subscriptions_from(&slab_d, &slab_b), 1);
+
+        // A zero lease cancels the subscription
+        slab_b.subscribe_remote(SubscriptionTarget::Entity(record.id), Duration::from_millis(0));
+        simulator.quiesce().await;
+        assert_eq!(subscriptions_from(&slab_c, &slab_b), 0);
+        assert_eq!(subscriptions_from(&slab_d, &slab_b), 0);
+
+        // The rest lapse unless they're renewed
+        let lapsed = Instant::now() + lease;
+        slab_a.agent.maintain_subscriptions(lapsed);
+        assert_eq!(subscriptions_from(&slab_a, &slab_c), 0);
+
+        slab_c.agent.maintain_subscriptions(lapsed);
+        simulator.quiesce().await;
+        assert!(subscriptions_from(&slab_a, &slab_c) > 0);
+
+        simulator.quiesce_and_stop().await;
+    }
+
+    #[unbase_test_util::async_test]
+    async fn garbage_collection_of_events() {
+        let net = Network::create_new_system();
+        let simulator = Simulator::new();
+        net.add_transport(Box::new(simulator.clone()));
+        simulator.start();
+
+        let slab_a = Slab::new(&net);
+        let slab_b = Slab::new(&net);
+        let context_a = slab_a.create_context();
+
+        let record = Entity::new_with_single_kv(&context_a, "animal_sound", "Moo").await.unwrap();
+        simulator.quiesce().await;
+
+        let target = SubscriptionTarget::Entity(record.id);
+        slab_b.subscribe_remote(target, Duration::from_secs(60));
+        simulator.quiesce().await;
+
+        let held = slab_b.agent.state.read().unwrap().held_subscriptions[&target].memoref.id;
+        let awaited = slab_b.agent.new_memo(None, Head::Null, MemoBody::MemoRequest(Vec::new(), slab_b.my_ref.clone())).id;
+        let _waiter = slab_b.agent.memo_wait_channel(awaited);
+        let forgotten = slab_b.agent.new_memo(None, Head::Null, MemoBody::MemoRequest(vec![held], slab_b.my_ref.clone())).id;
+
+        slab_b.collect_garbage();
+
+        // Events are kept for as long as something refers to them
+        assert!(has_memo(&slab_b, held));
+        assert!(has_memo(&slab_b, awaited));
+        assert!(!has_memo(&slab_b, forgotten));
+
+        simulator.quiesce_and_stop().await;
+    }
+
+    #[unbase_test_util::async_test]
+    async fn never_a_peer_of_our_own_memos() {
+        let net = Network::create_new_system();
+        let simulator = Simulator::new();
+        net.add_transport(Box::new(simulator.clone()));
+        simulator.start();
+
+        let slab_a = Slab::new(&net);
+        let slab_b = Slab::new(&net);
+
+        // As where a peer tells us of a memo we haven't heard of, and mentions that we hold it
+        let peerlist = MemoPeerList::new(vec![MemoPeer::new(slab_b.my_ref.clone(), MemoPeeringStatus::Resident),
+                                              MemoPeer::new(slab_b.agent.localize_slabref(&slab_a.my_ref),
+                                                            MemoPeeringStatus::Resident)]);
+        let (memoref, had_memoref) = slab_b.agent.assert_memoref(MemoId([7; 32]), None, peerlist, None);
+
+        assert!(!had_memoref);
+        let peers: Vec<_> = memoref.peerlist.read().unwrap().iter().map(|peer| peer.slabref.slab_id).collect();
+        assert_eq!(peers, vec![slab_a.id]);
+
+        simulator.quiesce_and_stop().await;
+    }
+
+    #[unbase_test_util::async_test]
+    async fn observer_subscription() {
+        let net = Network::create_new_system();
+        let simulator = Simulator::new();
+        net.add_transport(Box::new(simulator.clone()));
+        simulator.start();
+
+        let slab_a = Slab::new(&net);
+        let slab_b = Slab::new(&net);
+        let context_a = slab_a.create_context();
+
+        let record = Entity::new_with_single_kv(&context_a, "animal_sound", "Moo").await.unwrap();
+        let root_index = context_a.root_index().await.unwrap().get_root_entity_id();
+        simulator.quiesce().await;
+
+        // The context observes the index, and the observer its entity, by way of subscriptions with our peers
+        let observer = record.observe();
+        simulator.quiesce().await;
+
+        assert!(is_subscribed(&slab_b, root_index, &slab_a));
+        assert!(is_subscribed(&slab_b, record.id, &slab_a));
+
+        // Which are cancelled once there's no longer anyone observing
+        drop(observer);
+        slab_a.agent.maintain_subscriptions(Instant::now());
+        simulator.quiesce().await;
+
+        assert!(!is_subscribed(&slab_b, record.id, &slab_a));
+        assert!(is_subscribed(&slab_b, root_index, &slab_a));
+
+        simulator.quiesce_and_stop().await;
+    }
+}
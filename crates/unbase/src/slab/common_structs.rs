@@ -2,6 +2,14 @@ use std::{
     collections::HashMap,
     fmt,
     ops::Deref,
+    sync::atomic::{
+        AtomicU64,
+        Ordering,
+    },
+    time::{
+        SystemTime,
+        UNIX_EPOCH,
+    },
 };
 
 use crate::{
@@ -13,6 +21,10 @@ use crate::{
     slab::SlabId,
 };
 use itertools::Itertools;
+use sha2::{
+    Digest,
+    Sha256,
+};
 
 pub const MAX_SLOTS: usize = 256;
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Serialize, Deserialize)]
@@ -22,10 +34,13 @@ pub enum EntityType {
     Record,
 }
 
+/// Entity ids are prefixed with the id of the slab which issued them, and are thus globally unique without any
+/// coordination between slabs
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Serialize, Deserialize)]
 pub struct EntityId {
-    pub id:    u64,
-    pub stype: EntityType,
+    pub slab_id: SlabId,
+    pub id:      u64,
+    pub stype:   EntityType,
 }
 impl<'a> core::cmp::PartialEq<&'a str> for EntityId {
     fn eq(&self, other: &&'a str) -> bool {
@@ -35,30 +50,50 @@ impl<'a> core::cmp::PartialEq<&'a str> for EntityId {
 
 impl EntityId {
     pub fn test(test_id: u64) -> Self {
-        EntityId { id:    test_id,
-                   stype: EntityType::Record, }
+        EntityId { slab_id: 0,
+                   id:      test_id,
+                   stype:   EntityType::Record, }
     }
 
     /// Create a EntityId with a EntityType of IndexNode and a manually provided id
     /// Used by the test suite
     pub fn index_test(test_id: u64) -> Self {
-        EntityId { id:    test_id,
-                   stype: EntityType::IndexNode, }
+        EntityId { slab_id: 0,
+                   id:      test_id,
+                   stype:   EntityType::IndexNode, }
+    }
+
+    /// The key under which this entity is filed in the root index.
+    ///
+    /// The issuing slab and slab-local id are hashed together and folded to the 40 bits which the root index spans,
+    /// such that entities of every slab are spread evenly across it, and each write rewrites only `ROOT_INDEX_DEPTH`
+    /// nodes. Two entities may share a key, though this is unlikely until there are some hundreds of thousands of them.
+    pub fn index_key(&self) -> u64 {
+        let mut hasher = Sha256::new();
+        hasher.input(self.slab_id.to_be_bytes());
+        hasher.input(self.id.to_be_bytes());
+
+        let digest = hasher.result();
+        let mut key = [0u8; 8];
+        key[3..].copy_from_slice(&digest[0..5]);
+
+        u64::from_be_bytes(key)
     }
 
-    /// Human readable version of the EntityID which denotes whether the entity is an (I)ndex or a (R)ecord type
+    /// Human readable version of the EntityID which denotes whether the entity is an (I)ndex or a (R)ecord type,
+    /// followed by the issuing slab and the slab-local id
     pub fn concise_string(&self) -> String {
         use self::EntityType::*;
         match self.stype {
-            IndexNode => format!("I{}", self.id),
-            Record => format!("R{}", self.id),
+            IndexNode => format!("I{}.{}", self.slab_id, self.id),
+            Record => format!("R{}.{}", self.slab_id, self.id),
         }
     }
 }
 
 impl fmt::Display for EntityId {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}-{}", self.stype, self.id)
+        write!(f, "{:?}-{:x}-{}", self.stype, self.slab_id, self.id)
     }
 }
 
@@ -86,6 +121,24 @@ impl fmt::Debug for SlabPresence {
     }
 }
 
+/// That which a remote slab is asked to forward memos for
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum SubscriptionTarget {
+    /// Memos for a single entity
+    Entity(EntityId),
+    /// Memos for an index node and every node beneath it
+    IndexSubtree(EntityId),
+}
+
+impl SubscriptionTarget {
+    pub fn entity_id(&self) -> EntityId {
+        match *self {
+            SubscriptionTarget::Entity(entity_id) => entity_id,
+            SubscriptionTarget::IndexSubtree(entity_id) => entity_id,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum SlabAnticipatedLifetime {
     Ephmeral,
@@ -95,6 +148,47 @@ pub enum SlabAnticipatedLifetime {
     Unknown,
 }
 
+impl SlabAnticipatedLifetime {
+    /// How much a copy of a memo held by a slab of this lifetime contributes to the durability score of the memo
+    pub fn durability_weight(&self) -> u8 {
+        match self {
+            SlabAnticipatedLifetime::Ephmeral => 2,
+            SlabAnticipatedLifetime::Session | SlabAnticipatedLifetime::Unknown => 4,
+            SlabAnticipatedLifetime::Long => 8,
+            SlabAnticipatedLifetime::VeryLong => 16,
+        }
+    }
+}
+
+/// The durability score which memos are replicated to, unless another is set for their entity type. That is five copies
+/// held by slabs of unknown lifetime.
+pub const DEFAULT_DURABILITY_TARGET: u8 = 20;
+
+/// When a slab rewrites a record which it has edited as a fully materialized keyframe, such that projecting the record
+/// needn't traverse an ever longer chain of edits. Either limit may be disabled with `None`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyframePolicy {
+    /// The number of memos since the most recent materialized memo of each causal branch
+    pub max_edits: Option<usize>,
+    /// The length of the longest chain of memos back to a materialized memo
+    pub max_depth: Option<usize>,
+}
+
+impl KeyframePolicy {
+    /// Never write keyframes
+    pub fn disabled() -> Self {
+        KeyframePolicy { max_edits: None,
+                         max_depth: None, }
+    }
+}
+
+impl Default for KeyframePolicy {
+    fn default() -> Self {
+        KeyframePolicy { max_edits: Some(64),
+                         max_depth: Some(32), }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct MemoPeerList(pub Vec<MemoPeer>);
 
@@ -118,19 +212,28 @@ impl MemoPeerList {
         let peerlist = &mut self.0;
         {
             if let Some(my_peer) = peerlist.iter_mut().find(|p| p.slabref.slab_id == peer.slabref.slab_id) {
-                if peer.status != my_peer.status {
-                    // same slabref, so no need to apply the peer presence
-                    my_peer.status = peer.status;
-                    return true;
-                } else {
+                // Peerlists are often relayed second hand, and may be stale. Reports of a peer's status supersede one
+                // another in the order in which the peer made them, however they reach us.
+                if peer.as_of <= my_peer.as_of {
                     return false;
                 }
+
+                let changed = peer.status != my_peer.status;
+                // same slabref, so no need to apply the peer presence
+                my_peer.status = peer.status;
+                my_peer.as_of = peer.as_of;
+                return changed;
             }
         }
 
         peerlist.push(peer);
         true
     }
+
+    /// How well the memo is preserved by other slabs, as the sum of the durability weights of its peers
+    pub fn durability_score(&self) -> u8 {
+        self.0.iter().fold(0u8, |score, peer| score.saturating_add(peer.durability_weight()))
+    }
 }
 
 impl Deref for MemoPeerList {
@@ -145,6 +248,39 @@ impl Deref for MemoPeerList {
 pub struct MemoPeer {
     pub slabref: SlabRef,
     pub status:  MemoPeeringStatus,
+    /// When the peer reported this status, by its own `peering_stamp`
+    pub as_of:   u64,
+}
+
+impl MemoPeer {
+    /// A slab's report of its own status, as of now
+    pub fn new(slabref: SlabRef, status: MemoPeeringStatus) -> Self {
+        MemoPeer { slabref,
+                   status,
+                   as_of: peering_stamp() }
+    }
+
+    /// A resident peer holds a copy of the memo, which counts for as much as the peer is expected to stay around, whereas
+    /// a participating peer merely knows where copies are to be had
+    pub fn durability_weight(&self) -> u8 {
+        match self.status {
+            MemoPeeringStatus::Resident => self.slabref.anticipated_lifetime().durability_weight(),
+            MemoPeeringStatus::Participating => 1,
+            MemoPeeringStatus::NonParticipating | MemoPeeringStatus::Unknown => 0,
+        }
+    }
+}
+
+/// Stamps which order the reports a slab makes of its own peering status, even across restarts. Microseconds since the
+/// epoch, but never less than one more than the previous stamp.
+pub fn peering_stamp() -> u64 {
+    static LAST_STAMP: AtomicU64 = AtomicU64::new(0);
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_micros() as u64).unwrap_or(0);
+    let previous = LAST_STAMP.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| Some(now.max(last + 1)))
+                             .unwrap();
+
+    now.max(previous + 1)
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
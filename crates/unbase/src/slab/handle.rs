@@ -10,25 +10,30 @@ use std::sync::Arc;
 use tracing::trace;
 
 use crate::{
+    context::stash::WeakStash,
     error::{
         RetrieveError,
         StorageOpDeclined,
     },
     head::Head,
+    merge::MergePolicy,
     network::{
         SlabRef,
         TransportAddress,
     },
     slab::{
         agent::SlabAgent,
+        subscriber::HeadSender,
         EntityId,
         EntityType,
+        KeyframePolicy,
         Memo,
         MemoBody,
         MemoId,
         MemoRef,
         SlabAnticipatedLifetime,
         SlabPresence,
+        SubscriptionTarget,
     },
     Network,
 };
@@ -60,10 +65,14 @@ impl SlabHandle {
     //        unimplemented!()
     //    }
 
-    pub(crate) fn observe_entity(&self, entity_id: EntityId, tx: mpsc::Sender<Head>) {
+    pub(crate) fn observe_entity(&self, entity_id: EntityId, tx: HeadSender) {
         self.agent.observe_entity(entity_id, tx)
     }
 
+    pub(crate) fn get_memoref(&self, memo_id: MemoId) -> Option<MemoRef> {
+        self.agent.get_memoref(memo_id)
+    }
+
     #[tracing::instrument]
     pub async fn request_memo(&self, memoref: MemoRef) -> Result<Memo, RetrieveError> {
         // we're looking for this memo
@@ -119,6 +128,17 @@ impl SlabHandle {
         self.agent.new_memo(entity_id, parents, body)
     }
 
+    /// Create a memo which other slabs must request from us, rather than having it sent to them
+    #[tracing::instrument]
+    pub fn new_memo_unannounced(&self, entity_id: Option<EntityId>, parents: Head, body: MemoBody) -> MemoRef {
+        self.agent.new_memo_unannounced(entity_id, parents, body)
+    }
+
+    /// Send a memo which was created by way of `new_memo_unannounced` as though it had been created by `new_memo`
+    pub fn announce_memo(&self, memoref: &MemoRef) {
+        self.agent.announce_memo(memoref)
+    }
+
     #[tracing::instrument]
     pub fn new_memo_noparent(&self, entity_id: Option<EntityId>, body: MemoBody) -> MemoRef {
         self.agent.new_memo(entity_id, Head::Null, body)
@@ -177,9 +197,90 @@ impl SlabHandle {
         self.agent.count_of_memos_reduntantly_received()
     }
 
-    pub(crate) fn observe_index(&self, tx: mpsc::Sender<Head>) {
+    pub fn count_of_memos_collected(&self) -> u64 {
+        self.agent.count_of_memos_collected()
+    }
+
+    /// Superseded memos which garbage collection has so far retained because they weren't durable enough elsewhere
+    pub fn count_of_memos_retained_for_peering(&self) -> u64 {
+        self.agent.count_of_memos_retained_for_peering()
+    }
+
+    pub fn keyframe_policy(&self) -> KeyframePolicy {
+        self.agent.keyframe_policy()
+    }
+
+    /// Set when records edited through this slab are rewritten as keyframes. See `KeyframePolicy`.
+    pub fn set_keyframe_policy(&self, policy: KeyframePolicy) {
+        self.agent.set_keyframe_policy(policy)
+    }
+
+    /// The durability score which memos of the given entity type are replicated to. See `DEFAULT_DURABILITY_TARGET`.
+    pub fn durability_target(&self, stype: EntityType) -> u8 {
+        self.agent.durability_target(stype)
+    }
+
+    pub fn set_durability_target(&self, stype: EntityType, target: u8) {
+        self.agent.set_durability_target(stype, target)
+    }
+
+    /// The policy which resolves concurrent writes to the given field of entities of the given type
+    pub fn merge_policy(&self, stype: EntityType, field: &str) -> Arc<dyn MergePolicy> {
+        self.agent.merge_policy(stype, field)
+    }
+
+    /// Resolve concurrent writes to the given field with the policy provided, for entities of any type. Policies are
+    /// declared on the slab, such that every context of it agrees on the values which it reads and indexes.
+    pub fn set_merge_policy_for_field<P>(&self, field: &str, policy: P)
+        where P: MergePolicy + 'static
+    {
+        self.agent.set_merge_policy_for_field(field, Arc::new(policy))
+    }
+
+    /// Resolve concurrent writes to entities of the given type with the policy provided, except for those fields which
+    /// have a policy of their own
+    pub fn set_merge_policy_for_type<P>(&self, stype: EntityType, policy: P)
+        where P: MergePolicy + 'static
+    {
+        self.agent.set_merge_policy_for_type(stype, Arc::new(policy))
+    }
+
+    /// How well the given memo is preserved by other slabs, as the sum of the durability weights of its peers
+    pub fn memo_durability_score(&self, memo_id: MemoId) -> u8 {
+        self.agent.memo_durability_score(memo_id)
+    }
+
+    /// Push memos which fall short of their durability target to further peers, returning how many are short of it
+    pub fn replicate_memos(&self) -> usize {
+        self.agent.replicate_memos()
+    }
+
+    /// Drop the memos which no local head needs, where other slabs hold them, returning how many were dropped. See
+    /// `SlabAgent::collect_garbage`.
+    pub fn collect_garbage(&self) -> usize {
+        self.agent.collect_garbage()
+    }
+
+    pub(crate) fn register_stash(&self, stash: WeakStash) {
+        self.agent.register_stash(stash)
+    }
+
+    pub(crate) fn observe_index(&self, tx: HeadSender) {
         self.agent.observe_index(tx)
     }
+
+    pub(crate) fn observe_memos(&self, tx: mpsc::UnboundedSender<Head>) {
+        self.agent.observe_memos(tx)
+    }
+
+    /// Ask our peers to forward memos for the target to us, renewing the lease until it is cancelled with a zero lease
+    pub fn subscribe_remote(&self, target: SubscriptionTarget, lease: Duration) {
+        self.agent.subscribe_remote(target, lease)
+    }
+
+    pub fn count_of_remote_subscriptions(&self) -> usize {
+        self.agent.count_of_remote_subscriptions()
+    }
 }
 
 impl std::fmt::Debug for SlabHandle {
@@ -0,0 +1,421 @@
+//! Content addressing for memos.
+//!
+//! A `MemoId` is derived from a SHA-256 digest over the entity id, parents and body of the memo, such that identical
+//! memos created on different slabs receive the same id. HashMaps have no stable iteration order, so every collection
+//! is fed to the hasher in sorted order.
+
+use super::*;
+use crate::{
+    crdt::CrdtOp,
+    slab::{
+        MemoPeer,
+        MemoPeeringStatus,
+        SlotId,
+        SubscriptionTarget,
+    },
+    value::Value,
+};
+
+use sha2::{
+    Digest,
+    Sha256,
+};
+
+/// Calculate the id of a memo from its contents.
+///
+/// Memos which don't belong to an entity (presence, peering, requests) are not meant to converge with one another,
+/// and nor are identical CRDT operations, so the caller provides a nonce which distinguishes them.
+pub fn generate_memo_id(entity_id: Option<EntityId>, parents: &Head, body: &MemoBody, nonce: Option<(SlabId, u32)>)
+                        -> MemoId {
+    let mut hasher = Sha256::new();
+
+    entity_id.content_hash(&mut hasher);
+    parents.content_hash(&mut hasher);
+    body.content_hash(&mut hasher);
+    nonce.content_hash(&mut hasher);
+
+    let mut id = [0u8; 32];
+    id.copy_from_slice(&hasher.result());
+
+    MemoId(id)
+}
+
+trait ContentHash {
+    fn content_hash(&self, hasher: &mut Sha256);
+}
+
+impl ContentHash for u8 {
+    fn content_hash(&self, hasher: &mut Sha256) {
+        hasher.input([*self]);
+    }
+}
+
+impl ContentHash for u32 {
+    fn content_hash(&self, hasher: &mut Sha256) {
+        hasher.input(self.to_be_bytes());
+    }
+}
+
+impl ContentHash for u64 {
+    fn content_hash(&self, hasher: &mut Sha256) {
+        hasher.input(self.to_be_bytes());
+    }
+}
+
+impl ContentHash for i64 {
+    fn content_hash(&self, hasher: &mut Sha256) {
+        hasher.input(self.to_be_bytes());
+    }
+}
+
+impl ContentHash for usize {
+    fn content_hash(&self, hasher: &mut Sha256) {
+        (*self as u64).content_hash(hasher);
+    }
+}
+
+impl ContentHash for String {
+    fn content_hash(&self, hasher: &mut Sha256) {
+        // Length-prefixed so that adjacent strings cannot run into one another
+        self.len().content_hash(hasher);
+        hasher.input(self.as_bytes());
+    }
+}
+
+impl ContentHash for MemoId {
+    fn content_hash(&self, hasher: &mut Sha256) {
+        hasher.input(self.0);
+    }
+}
+
+impl<A: ContentHash, B: ContentHash> ContentHash for (A, B) {
+    fn content_hash(&self, hasher: &mut Sha256) {
+        self.0.content_hash(hasher);
+        self.1.content_hash(hasher);
+    }
+}
+
+impl<T: ContentHash> ContentHash for Option<T> {
+    fn content_hash(&self, hasher: &mut Sha256) {
+        match self {
+            None => 0u8.content_hash(hasher),
+            Some(value) => {
+                1u8.content_hash(hasher);
+                value.content_hash(hasher);
+            },
+        }
+    }
+}
+
+impl<T: ContentHash + Ord> ContentHash for Vec<T> {
+    fn content_hash(&self, hasher: &mut Sha256) {
+        let mut sorted: Vec<&T> = self.iter().collect();
+        sorted.sort();
+
+        sorted.len().content_hash(hasher);
+        for item in sorted {
+            item.content_hash(hasher);
+        }
+    }
+}
+
+impl ContentHash for EntityType {
+    fn content_hash(&self, hasher: &mut Sha256) {
+        match self {
+            EntityType::IndexNode => 0u8,
+            EntityType::Record => 1u8,
+        }.content_hash(hasher)
+    }
+}
+
+impl ContentHash for EntityId {
+    fn content_hash(&self, hasher: &mut Sha256) {
+        self.slab_id.content_hash(hasher);
+        self.id.content_hash(hasher);
+        self.stype.content_hash(hasher);
+    }
+}
+
+impl ContentHash for SubscriptionTarget {
+    fn content_hash(&self, hasher: &mut Sha256) {
+        match self {
+            SubscriptionTarget::Entity(entity_id) => {
+                0u8.content_hash(hasher);
+                entity_id.content_hash(hasher);
+            },
+            SubscriptionTarget::IndexSubtree(entity_id) => {
+                1u8.content_hash(hasher);
+                entity_id.content_hash(hasher);
+            },
+        }
+    }
+}
+
+impl ContentHash for Head {
+    fn content_hash(&self, hasher: &mut Sha256) {
+        match self {
+            Head::Null => 0u8.content_hash(hasher),
+            Head::Anonymous { .. } => {
+                1u8.content_hash(hasher);
+                self.memo_ids().content_hash(hasher);
+            },
+            Head::Entity { entity_id, .. } => {
+                2u8.content_hash(hasher);
+                entity_id.content_hash(hasher);
+                self.memo_ids().content_hash(hasher);
+            },
+        }
+    }
+}
+
+impl<V: ContentHash> ContentHash for HashMap<String, V> {
+    fn content_hash(&self, hasher: &mut Sha256) {
+        let mut sorted: Vec<(&String, &V)> = self.iter().collect();
+        sorted.sort_by_key(|(key, _)| *key);
+
+        sorted.len().content_hash(hasher);
+        for (key, value) in sorted {
+            key.content_hash(hasher);
+            value.content_hash(hasher);
+        }
+    }
+}
+
+impl ContentHash for Value {
+    fn content_hash(&self, hasher: &mut Sha256) {
+        match self {
+            Value::Null => 0u8.content_hash(hasher),
+            Value::Bool(b) => {
+                1u8.content_hash(hasher);
+                (*b as u8).content_hash(hasher);
+            },
+            Value::Int(n) => {
+                2u8.content_hash(hasher);
+                n.content_hash(hasher);
+            },
+            Value::Float(n) => {
+                3u8.content_hash(hasher);
+                n.to_bits().content_hash(hasher);
+            },
+            Value::String(s) => {
+                4u8.content_hash(hasher);
+                s.content_hash(hasher);
+            },
+            Value::Bytes(b) => {
+                5u8.content_hash(hasher);
+                b.len().content_hash(hasher);
+                hasher.input(b);
+            },
+            Value::Timestamp(ms) => {
+                6u8.content_hash(hasher);
+                ms.content_hash(hasher);
+            },
+            Value::List(l) => {
+                // Unlike the other collections, the order of a list is significant
+                7u8.content_hash(hasher);
+                l.len().content_hash(hasher);
+                for v in l {
+                    v.content_hash(hasher);
+                }
+            },
+        }
+    }
+}
+
+impl ContentHash for CrdtOp {
+    fn content_hash(&self, hasher: &mut Sha256) {
+        match self {
+            CrdtOp::Increment(n) => {
+                0u8.content_hash(hasher);
+                n.content_hash(hasher);
+            },
+            CrdtOp::SetAdd(item) => {
+                1u8.content_hash(hasher);
+                item.content_hash(hasher);
+            },
+            CrdtOp::SetRemove(item, tags) => {
+                2u8.content_hash(hasher);
+                item.content_hash(hasher);
+                tags.content_hash(hasher);
+            },
+            CrdtOp::Map(entry, op) => {
+                3u8.content_hash(hasher);
+                entry.content_hash(hasher);
+                op.content_hash(hasher);
+            },
+            CrdtOp::TextInsert { after, seq, text } => {
+                4u8.content_hash(hasher);
+                after.content_hash(hasher);
+                seq.content_hash(hasher);
+                text.content_hash(hasher);
+            },
+            CrdtOp::TextDelete(ids) => {
+                5u8.content_hash(hasher);
+                ids.content_hash(hasher);
+            },
+        }
+    }
+}
+
+impl ContentHash for RelationSet {
+    fn content_hash(&self, hasher: &mut Sha256) {
+        let mut sorted: Vec<(&SlotId, &Option<EntityId>)> = self.0.iter().collect();
+        sorted.sort();
+
+        sorted.len().content_hash(hasher);
+        for (slot_id, entity_id) in sorted {
+            slot_id.content_hash(hasher);
+            entity_id.content_hash(hasher);
+        }
+    }
+}
+
+impl ContentHash for EdgeSet {
+    fn content_hash(&self, hasher: &mut Sha256) {
+        let mut sorted: Vec<(&SlotId, &Head)> = self.0.iter().collect();
+        sorted.sort_by_key(|(slot_id, _)| **slot_id);
+
+        sorted.len().content_hash(hasher);
+        for (slot_id, head) in sorted {
+            slot_id.content_hash(hasher);
+            head.content_hash(hasher);
+        }
+    }
+}
+
+impl ContentHash for MemoPeerList {
+    fn content_hash(&self, hasher: &mut Sha256) {
+        let mut sorted: Vec<&MemoPeer> = self.iter().collect();
+        sorted.sort_by_key(|peer| peer.slabref.slab_id);
+
+        sorted.len().content_hash(hasher);
+        for peer in sorted {
+            peer.slabref.slab_id.content_hash(hasher);
+            match peer.status {
+                MemoPeeringStatus::Resident => 0u8,
+                MemoPeeringStatus::Participating => 1u8,
+                MemoPeeringStatus::NonParticipating => 2u8,
+                MemoPeeringStatus::Unknown => 3u8,
+            }.content_hash(hasher);
+            peer.as_of.content_hash(hasher);
+        }
+    }
+}
+
+impl ContentHash for SlabPresence {
+    fn content_hash(&self, hasher: &mut Sha256) {
+        self.slab_id.content_hash(hasher);
+        self.address.to_string().content_hash(hasher);
+    }
+}
+
+impl ContentHash for MemoBody {
+    fn content_hash(&self, hasher: &mut Sha256) {
+        use MemoBody::*;
+
+        match self {
+            SlabPresence { p, r } => {
+                0u8.content_hash(hasher);
+                p.content_hash(hasher);
+                r.content_hash(hasher);
+            },
+            Relation(relation_set) => {
+                1u8.content_hash(hasher);
+                relation_set.content_hash(hasher);
+            },
+            Edge(edge_set) => {
+                2u8.content_hash(hasher);
+                edge_set.content_hash(hasher);
+            },
+            Edit(values) => {
+                3u8.content_hash(hasher);
+                values.content_hash(hasher);
+            },
+            FullyMaterialized { v, r, e, o, t } => {
+                4u8.content_hash(hasher);
+                v.content_hash(hasher);
+                r.content_hash(hasher);
+                e.content_hash(hasher);
+                o.content_hash(hasher);
+                t.content_hash(hasher);
+            },
+            PartiallyMaterialized { v, r, e, t } => {
+                5u8.content_hash(hasher);
+                v.content_hash(hasher);
+                r.content_hash(hasher);
+                e.content_hash(hasher);
+                t.content_hash(hasher);
+            },
+            Peering(memo_id, entity_id, peerlist) => {
+                6u8.content_hash(hasher);
+                memo_id.content_hash(hasher);
+                entity_id.content_hash(hasher);
+                peerlist.content_hash(hasher);
+            },
+            MemoRequest(memo_ids, slabref) => {
+                7u8.content_hash(hasher);
+                memo_ids.content_hash(hasher);
+                slabref.slab_id.content_hash(hasher);
+            },
+            Subscription { t, s, x } => {
+                8u8.content_hash(hasher);
+                t.content_hash(hasher);
+                s.slab_id.content_hash(hasher);
+                x.content_hash(hasher);
+            },
+            Operation(ops) => {
+                9u8.content_hash(hasher);
+                ops.content_hash(hasher);
+            },
+            Tombstone(t) => {
+                10u8.content_hash(hasher);
+                t.content_hash(hasher);
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn memo_id_is_independent_of_map_order() {
+        let entity_id = Some(EntityId::test(1));
+
+        let mut a = HashMap::new();
+        let mut b = HashMap::new();
+        for i in 0..32 {
+            a.insert(format!("key{}", i), Some(Value::from(format!("value{}", i))));
+        }
+        for i in (0..32).rev() {
+            b.insert(format!("key{}", i), Some(Value::from(format!("value{}", i))));
+        }
+
+        let id_a = generate_memo_id(entity_id, &Head::Null, &MemoBody::Edit(a.clone()), None);
+        let id_b = generate_memo_id(entity_id, &Head::Null, &MemoBody::Edit(b), None);
+        assert_eq!(id_a, id_b);
+
+        // Any difference in content or nonce yields a different id
+        a.insert("key0".to_string(), Some(Value::from("other")));
+        assert_ne!(id_a, generate_memo_id(entity_id, &Head::Null, &MemoBody::Edit(a.clone()), None));
+        assert_ne!(id_a, generate_memo_id(Some(EntityId::test(2)), &Head::Null, &MemoBody::Edit(a.clone()), None));
+        assert_ne!(generate_memo_id(None, &Head::Null, &MemoBody::Edit(a.clone()), Some((1, 1))),
+                   generate_memo_id(None, &Head::Null, &MemoBody::Edit(a), Some((1, 2))));
+
+        // As does a difference in type alone
+        let mut int = HashMap::new();
+        int.insert("key".to_string(), Some(Value::Int(1)));
+        let mut string = HashMap::new();
+        string.insert("key".to_string(), Some(Value::from("1")));
+        assert_ne!(generate_memo_id(entity_id, &Head::Null, &MemoBody::Edit(int), None),
+                   generate_memo_id(entity_id, &Head::Null, &MemoBody::Edit(string.clone()), None));
+
+        // And a removal differs from a null value
+        let mut removal = HashMap::new();
+        removal.insert("key".to_string(), None);
+        string.insert("key".to_string(), Some(Value::Null));
+        assert_ne!(generate_memo_id(entity_id, &Head::Null, &MemoBody::Edit(removal), None),
+                   generate_memo_id(entity_id, &Head::Null, &MemoBody::Edit(string), None));
+    }
+}
@@ -1,7 +1,10 @@
 // Memo
 // A memo is an immutable message.
+mod hash;
 pub mod serde;
 
+pub use self::hash::generate_memo_id;
+
 use core::ops::Deref;
 use futures::future::{
     BoxFuture,
@@ -14,6 +17,10 @@ use std::{
 };
 
 use crate::{
+    crdt::{
+        CrdtOp,
+        FieldOperations,
+    },
     error::RetrieveError,
     head::Head,
     network::{
@@ -29,12 +36,30 @@ use crate::{
         RelationSet,
         SlabHandle,
         SlabId,
+        SubscriptionTarget,
     },
+    value::Value,
 };
 use itertools::Itertools;
 
-// pub type MemoId = [u8; 32];
-pub type MemoId = u64;
+/// The SHA-256 digest of the memo's contents. See `generate_memo_id`
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
+pub struct MemoId(pub [u8; 32]);
+
+impl fmt::Display for MemoId {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        for byte in self.0.iter() {
+            write!(fmt, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for MemoId {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, fmt)
+    }
+}
 
 // All portions of this struct should be immutable
 
@@ -50,9 +75,12 @@ impl Deref for Memo {
 }
 
 pub struct MemoInner {
-    pub id:             u64,
+    pub id:             MemoId,
     pub entity_id:      Option<EntityId>,
     pub owning_slab_id: SlabId,
+    /// The slab which created the memo. This isn't part of its content, so where two slabs create the same memo, the
+    /// copy which arrived first determines it.
+    pub origin_slab_id: SlabId,
     pub parents:        Head,
     pub body:           MemoBody,
 }
@@ -65,21 +93,38 @@ pub enum MemoBody {
     }, // TODO: split out root_index_seed conveyance to another memobody type
     Relation(RelationSet),
     Edge(EdgeSet),
-    Edit(HashMap<String, String>),
+    /// Set the given keys, or remove those which are `None`
+    Edit(HashMap<String, Option<Value>>),
+    /// Operations upon CRDT fields, which are merged with those in the rest of the causal history rather than
+    /// superseding them
+    Operation(HashMap<String, CrdtOp>),
     FullyMaterialized {
-        v: HashMap<String, String>,
+        v: HashMap<String, Value>,
         r: RelationSet,
         e: EdgeSet,
+        /// Every operation upon CRDT fields in the causal history, with the id of the memo which first carried it.
+        /// These are carried as they are, rather than projected, as those of concurrent branches overlap.
+        o: FieldOperations,
         t: EntityType,
     },
     PartiallyMaterialized {
-        v: HashMap<String, String>,
+        v: HashMap<String, Value>,
         r: RelationSet,
         e: EdgeSet,
         t: EntityType,
     },
     Peering(MemoId, Option<EntityId>, MemoPeerList),
     MemoRequest(Vec<MemoId>, SlabRef),
+    /// Ask the recipient to forward memos for the target to the subscriber `s` for the next `x` milliseconds. A lease of
+    /// zero cancels the subscription.
+    Subscription {
+        t: SubscriptionTarget,
+        s: SlabRef,
+        x: u64,
+    },
+    /// Marks the entity as deleted. Supersedes everything in its causal history, as though it were a fully materialized
+    /// memo of nothing, and prevails over any write made concurrently with it.
+    Tombstone(EntityType),
 }
 
 // use std::hash::{Hash, Hasher};
@@ -96,6 +141,7 @@ impl fmt::Debug for Memo {
         fmt.debug_struct("Memo")
            .field("id", &self.id)
            .field("entity_id", &self.entity_id)
+           .field("origin_slab_id", &self.origin_slab_id)
            .field("parents", &self.parents)
            .field("body", &self.body)
            .finish()
@@ -111,10 +157,15 @@ impl Memo {
         self.parents.clone()
     }
 
-    pub fn get_values(&self) -> Option<(HashMap<String, String>, bool)> {
+    /// The values written by this memo, if any, where `None` marks a removal, and whether the memo is materialized.
+    /// Materialized memos supersede everything before them, so any key they lack is absent.
+    pub fn get_values(&self) -> Option<(HashMap<String, Option<Value>>, bool)> {
         match self.body {
             MemoBody::Edit(ref v) => Some((v.clone(), false)),
-            MemoBody::FullyMaterialized { ref v, .. } => Some((v.clone(), true)),
+            MemoBody::FullyMaterialized { ref v, .. } => {
+                Some((v.iter().map(|(key, value)| (key.clone(), Some(value.clone()))).collect(), true))
+            },
+            MemoBody::Tombstone(_) => Some((HashMap::new(), true)),
             _ => None,
         }
     }
@@ -123,6 +174,7 @@ impl Memo {
         match self.body {
             MemoBody::Relation(ref r) => Some((r.clone(), false)),
             MemoBody::FullyMaterialized { ref r, .. } => Some((r.clone(), true)),
+            MemoBody::Tombstone(_) => Some((RelationSet::empty(), true)),
             _ => None,
         }
     }
@@ -131,15 +183,47 @@ impl Memo {
         match self.body {
             MemoBody::Edge(ref e) => Some((e.clone(), false)),
             MemoBody::FullyMaterialized { ref e, .. } => Some((e.clone(), true)),
+            MemoBody::Tombstone(_) => Some((EdgeSet::empty(), true)),
             _ => None,
         }
     }
 
+    /// The operations upon CRDT fields which this memo carries, each with the id of the memo which first carried it, and
+    /// whether the memo is materialized. Materialized memos carry every operation before them.
+    pub fn get_operations(&self) -> Option<(FieldOperations, bool)> {
+        match self.body {
+            MemoBody::Operation(ref ops) => {
+                Some((ops.iter().map(|(key, op)| (key.clone(), vec![(self.id, op.clone())])).collect(), false))
+            },
+            MemoBody::FullyMaterialized { ref o, .. } => Some((o.clone(), true)),
+            MemoBody::Tombstone(_) => Some((HashMap::new(), true)),
+            _ => None,
+        }
+    }
+
+    /// The keys of the values and CRDT fields which this memo writes or removes
+    pub fn get_changed_keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = match self.get_values() {
+            Some((values, _)) => values.into_keys().collect(),
+            None => Vec::new(),
+        };
+        if let MemoBody::Operation(ref ops) = self.body {
+            keys.extend(ops.keys().cloned());
+        }
+
+        keys
+    }
+
+    pub fn is_tombstone(&self) -> bool {
+        matches!(self.body, MemoBody::Tombstone(_))
+    }
+
     pub fn does_peering(&self) -> bool {
         match self.body {
             MemoBody::MemoRequest(_, _) => false,
             MemoBody::Peering(_, _, _) => false,
             MemoBody::SlabPresence { p: _, r: _ } => false,
+            MemoBody::Subscription { .. } => false,
             _ => true,
         }
     }
@@ -185,12 +269,15 @@ impl MemoBody {
             Relation(ref rel_set) => format!("RelationSet({})", rel_set.to_string()),
             Edge(ref _edge_set) => format!("EdgeSet"),
             Edit(ref _e) => format!("Edit"),
+            Operation(ref _o) => format!("Operation"),
             FullyMaterialized { .. } => format!("FullyMaterialized"),
             PartiallyMaterialized { .. } => format!("PartiallyMaterialized"),
             Peering(ref _memo_id, ref _entity_id, ref _peerlist) => format!("Peering"),
             MemoRequest(ref memo_ids, ref slabref) => {
                 format!("MemoRequest({} to {})", memo_ids.iter().join(","), slabref.slab_id)
             },
+            Subscription { ref t, ref s, x } => format!("Subscription({:?} to {} for {}ms)", t, s.slab_id, x),
+            Tombstone(_) => format!("Tombstone"),
         }
     }
 }
@@ -5,6 +5,7 @@ use crate::{
         memoref::serde::MemoPeerSeed,
         slabref::serde::SlabRefSeed,
         SlotId,
+        SubscriptionTarget,
     },
     util::serde::*,
 };
@@ -17,6 +18,50 @@ use std::fmt;
 
 use tracing::debug;
 
+/// Memo ids are encoded as hex strings, rather than as arrays of 32 numbers
+impl Serialize for MemoId {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl Deserialize for MemoId {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where D: Deserializer
+    {
+        deserializer.deserialize_str(MemoIdVisitor)
+    }
+}
+
+struct MemoIdVisitor;
+
+impl Visitor for MemoIdVisitor {
+    type Value = MemoId;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a memo id of 64 hex digits")
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<MemoId, E>
+        where E: ::serde::de::Error
+    {
+        if value.len() != 64 || !value.is_ascii() {
+            return Err(E::invalid_value(Unexpected::Str(value), &self));
+        }
+
+        let mut id = [0u8; 32];
+        for (i, byte) in id.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&value[i * 2..i * 2 + 2], 16).map_err(|_| {
+                                                                        E::invalid_value(Unexpected::Str(value), &self)
+                                                                    })?;
+        }
+
+        Ok(MemoId(id))
+    }
+}
+
 pub struct MemoBodySeed<'a> {
     dest_slab:      &'a SlabHandle,
     origin_slabref: &'a SlabRef,
@@ -34,6 +79,9 @@ struct MBFullyMaterializedSeed<'a> {
     dest_slab:      &'a SlabHandle,
     origin_slabref: &'a SlabRef,
 }
+struct MBSubscriptionSeed<'a> {
+    dest_slab: &'a SlabHandle,
+}
 // TODO convert this to a non-seed deserializer
 struct MBPeeringSeed<'a> {
     dest_slab: &'a SlabHandle,
@@ -43,11 +91,12 @@ impl StatefulSerialize for Memo {
     fn serialize<S>(&self, serializer: S, helper: &SerializeHelper) -> Result<S::Ok, S::Error>
         where S: Serializer
     {
-        let mut seq = serializer.serialize_seq(Some(4))?;
+        let mut seq = serializer.serialize_seq(Some(5))?;
         seq.serialize_element(&self.id)?;
         seq.serialize_element(&self.entity_id)?;
         seq.serialize_element(&SerializeWrapper(&self.body, helper))?;
         seq.serialize_element(&SerializeWrapper(&self.parents, helper))?;
+        seq.serialize_element(&self.origin_slab_id)?;
         seq.end()
     }
 }
@@ -85,11 +134,13 @@ impl StatefulSerialize for MemoBody {
             FullyMaterialized { ref v,
                                 ref r,
                                 ref e,
+                                ref o,
                                 ref t, } => {
-                let mut sv = serializer.serialize_struct_variant("MemoBody", 4, "FullyMaterialized", 3)?;
+                let mut sv = serializer.serialize_struct_variant("MemoBody", 4, "FullyMaterialized", 5)?;
                 sv.serialize_field("r", &SerializeWrapper(&r, helper))?;
                 sv.serialize_field("e", &SerializeWrapper(&e.0, helper))?;
                 sv.serialize_field("v", v)?;
+                sv.serialize_field("o", o)?;
                 sv.serialize_field("t", t)?;
                 sv.end()
             },
@@ -117,6 +168,15 @@ impl StatefulSerialize for MemoBody {
                 sv.serialize_field("s", &SerializeWrapper(slabref, helper))?;
                 sv.end()
             },
+            Subscription { ref t, ref s, ref x } => {
+                let mut sv = serializer.serialize_struct_variant("MemoBody", 8, "Subscription", 3)?;
+                sv.serialize_field("t", t)?;
+                sv.serialize_field("s", &SerializeWrapper(s, helper))?;
+                sv.serialize_field("x", x)?;
+                sv.end()
+            },
+            Operation(ref o) => serializer.serialize_newtype_variant("MemoBody", 9, "Operation", &o),
+            Tombstone(ref t) => serializer.serialize_newtype_variant("MemoBody", 10, "Tombstone", t),
         }
     }
 }
@@ -168,46 +228,93 @@ impl<'a> Visitor for MemoSeed<'a> {
     fn visit_seq<V>(self, mut visitor: V) -> Result<Self::Value, V::Error>
         where V: SeqVisitor
     {
-        let id: MemoId = match visitor.visit()? {
-            Some(value) => value,
-            None => {
-                return Err(DeError::invalid_length(0, &self));
-            },
-        };
-        let entity_id: Option<EntityId> = match visitor.visit()? {
-            Some(value) => value,
-            None => {
-                return Err(DeError::invalid_length(1, &self));
-            },
-        };
-        let body: MemoBody = match visitor.visit_seed(MemoBodySeed { dest_slab:      self.dest_slab,
-                                                                     origin_slabref: self.origin_slabref, })?
-        {
-            Some(value) => value,
-            None => {
-                return Err(DeError::invalid_length(2, &self));
-            },
-        };
-
-        let parents: Head = match visitor.visit_seed(HeadSeed { dest_slab:      self.dest_slab,
-                                                                origin_slabref: self.origin_slabref, })?
-        {
-            Some(value) => value,
-            None => {
-                return Err(DeError::invalid_length(3, &self));
-            },
-        };
+        let (id, entity_id, body, parents, origin_slab_id) =
+            visit_memo_fields(&mut visitor, self.dest_slab, self.origin_slabref, &self)?;
 
         debug!("SERDE calling reconstitute_memo");
         let _memo = self.dest_slab
                         .agent
-                        .reconstitute_memo(id, entity_id, parents, body, self.origin_slabref, &self.peerlist)
+                        .reconstitute_memo(id, entity_id, parents, body, origin_slab_id, self.origin_slabref, &self.peerlist)
                         .0;
 
         Ok(())
     }
 }
 
+/// Deserializes a memo which was previously recorded by this slab's `Storage`.
+///
+/// Unlike `MemoSeed`, the memo is merely made resident again. It is not treated as having been received, so no peering,
+/// emission or subscriber notification takes place.
+pub struct StoredMemoSeed<'a> {
+    pub dest_slab: &'a SlabHandle,
+}
+
+impl<'a> DeserializeSeed for StoredMemoSeed<'a> {
+    type Value = MemoRef;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where D: Deserializer
+    {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'a> Visitor for StoredMemoSeed<'a> {
+    type Value = MemoRef;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("struct Memo")
+    }
+
+    fn visit_seq<V>(self, mut visitor: V) -> Result<Self::Value, V::Error>
+        where V: SeqVisitor
+    {
+        let (id, entity_id, body, parents, origin_slab_id) =
+            visit_memo_fields(&mut visitor, self.dest_slab, &self.dest_slab.my_ref, &self)?;
+
+        Ok(self.dest_slab.agent.restore_memo(id, entity_id, parents, body, origin_slab_id))
+    }
+}
+
+/// The id, entity id, body, parents and originating slab of a memo
+type MemoFields = (MemoId, Option<EntityId>, MemoBody, Head, SlabId);
+
+fn visit_memo_fields<V>(visitor: &mut V, dest_slab: &SlabHandle, origin_slabref: &SlabRef, expected: &dyn Expected)
+                        -> Result<MemoFields, V::Error>
+    where V: SeqVisitor
+{
+    let id: MemoId = match visitor.visit()? {
+        Some(value) => value,
+        None => {
+            return Err(DeError::invalid_length(0, expected));
+        },
+    };
+    let entity_id: Option<EntityId> = match visitor.visit()? {
+        Some(value) => value,
+        None => {
+            return Err(DeError::invalid_length(1, expected));
+        },
+    };
+    let body: MemoBody = match visitor.visit_seed(MemoBodySeed { dest_slab, origin_slabref })? {
+        Some(value) => value,
+        None => {
+            return Err(DeError::invalid_length(2, expected));
+        },
+    };
+
+    let parents: Head = match visitor.visit_seed(HeadSeed { dest_slab, origin_slabref })? {
+        Some(value) => value,
+        None => {
+            return Err(DeError::invalid_length(3, expected));
+        },
+    };
+
+    // Memos recorded before the originating slab was carried are attributed to whichever slab we had them from
+    let origin_slab_id: SlabId = visitor.visit()?.unwrap_or(origin_slabref.slab_id);
+
+    Ok((id, entity_id, body, parents, origin_slab_id))
+}
+
 #[derive(Deserialize)]
 enum MBVariant {
     SlabPresence,
@@ -218,6 +325,9 @@ enum MBVariant {
     PartiallyMaterialized,
     Peering,
     MemoRequest,
+    Subscription,
+    Operation,
+    Tombstone,
 }
 
 impl<'a> DeserializeSeed for MemoBodySeed<'a> {
@@ -233,7 +343,10 @@ impl<'a> DeserializeSeed for MemoBodySeed<'a> {
                                                              "FullyMaterialized",
                                                              "PartiallyMaterialized",
                                                              "Peering",
-                                                             "MemoRequest"];
+                                                             "MemoRequest",
+                                                             "Subscription",
+                                                             "Operation",
+                                                             "Tombstone"];
 
         deserializer.deserialize_enum("MemoBody", MEMOBODY_VARIANTS, self)
     }
@@ -275,6 +388,11 @@ impl<'a> Visitor for MemoBodySeed<'a> {
                 variant.visit_newtype_seed(MBMemoRequestSeed { dest_slab:      self.dest_slab,
                                                                origin_slabref: self.origin_slabref, })
             },
+            (MBVariant::Subscription, variant) => {
+                variant.visit_newtype_seed(MBSubscriptionSeed { dest_slab: self.dest_slab })
+            },
+            (MBVariant::Operation, variant) => variant.visit_newtype().map(MemoBody::Operation),
+            (MBVariant::Tombstone, variant) => variant.visit_newtype().map(MemoBody::Tombstone),
             _ => unimplemented!(),
         }
     }
@@ -392,6 +510,45 @@ impl<'a> Visitor for EdgeSetSeed<'a> {
     }
 }
 
+impl<'a> DeserializeSeed for MBSubscriptionSeed<'a> {
+    type Value = MemoBody;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where D: Deserializer
+    {
+        deserializer.deserialize(self)
+    }
+}
+
+impl<'a> Visitor for MBSubscriptionSeed<'a> {
+    type Value = MemoBody;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("MemoBody::Subscription")
+    }
+
+    fn visit_map<V>(self, mut visitor: V) -> Result<Self::Value, V::Error>
+        where V: MapVisitor
+    {
+        let mut target: Option<SubscriptionTarget> = None;
+        let mut slabref: Option<SlabRef> = None;
+        let mut lease: Option<u64> = None;
+        while let Some(key) = visitor.visit_key()? {
+            match key {
+                't' => target = Some(visitor.visit_value()?),
+                's' => slabref = Some(visitor.visit_value_seed(SlabRefSeed { dest_slab: self.dest_slab, })?),
+                'x' => lease = Some(visitor.visit_value()?),
+                _ => {},
+            }
+        }
+
+        match (target, slabref, lease) {
+            (Some(t), Some(s), Some(x)) => Ok(MemoBody::Subscription { t, s, x }),
+            _ => Err(DeError::invalid_length(0, &self)),
+        }
+    }
+}
+
 impl<'a> DeserializeSeed for MBSlabPresenceSeed<'a> {
     type Value = MemoBody;
 
@@ -455,6 +612,7 @@ impl<'a> Visitor for MBFullyMaterializedSeed<'a> {
         let mut relations = None;
         let mut edges = None;
         let mut values = None;
+        let mut operations = None;
         let mut stype = None;
         while let Some(key) = visitor.visit_key()? {
             match key {
@@ -467,14 +625,17 @@ impl<'a> Visitor for MBFullyMaterializedSeed<'a> {
                                                                         origin_slabref: self.origin_slabref, })?)
                 },
                 'v' => values = visitor.visit_value()?,
+                'o' => operations = visitor.visit_value()?,
                 't' => stype = visitor.visit_value()?,
                 _ => {},
             }
         }
         if relations.is_some() && values.is_some() && stype.is_some() {
+            // Memos recorded before keyframes carried CRDT operations have none
             Ok(MemoBody::FullyMaterialized { v: values.unwrap(),
                                              r: relations.unwrap(),
                                              e: edges.unwrap(),
+                                             o: operations.unwrap_or_default(),
                                              t: stype.unwrap(), })
         } else {
             Err(DeError::invalid_length(0, &self))
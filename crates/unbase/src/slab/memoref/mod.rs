@@ -84,8 +84,7 @@ impl MemoRef {
     pub fn get_peerlist_for_peer(&self, my_ref: &SlabRef, maybe_dest_slab_id: Option<SlabId>) -> MemoPeerList {
         let mut list: Vec<MemoPeer> = Vec::new();
 
-        list.push(MemoPeer { slabref: my_ref.clone(),
-                             status:  self.ptr.read().unwrap().to_peering_status(), });
+        list.push(MemoPeer::new(my_ref.clone(), self.ptr.read().unwrap().to_peering_status()));
 
         // Tell the peer about all other presences except for ones belonging to them
         // we don't need to tell them they have it. They know, they were there :)
@@ -156,36 +155,6 @@ impl MemoRef {
             Ok(false)
         }
     }
-
-    pub fn update_peer(&self, slabref: &SlabRef, status: MemoPeeringStatus) -> bool {
-        let mut acted = false;
-        let mut found = false;
-        let ref mut list = self.peerlist.write().unwrap().0;
-        for peer in list.iter_mut() {
-            if peer.slabref.slab_id == self.owning_slab_id {
-                warn!("WARNING - not allowed to apply self-peer");
-                // panic!("memoref.update_peers is not allowed to apply for self-peers");
-                continue;
-            }
-            if peer.slabref.slab_id == slabref.slab_id {
-                found = true;
-                if peer.status != status {
-                    acted = true;
-                    peer.status = status.clone();
-                }
-                // TODO remove the peer entirely for MemoPeeringStatus::NonParticipating
-                // TODO prune excess peers - Should keep this list O(10) peers
-            }
-        }
-
-        if !found {
-            acted = true;
-            list.push(MemoPeer { slabref: slabref.clone(),
-                                 status:  status.clone(), })
-        }
-
-        acted
-    }
 }
 
 impl fmt::Debug for MemoRef {
@@ -29,7 +29,7 @@ impl StatefulSerialize for MemoRef {
     {
         use super::MemoRefPtr::*;
 
-        let mut seq = serializer.serialize_seq(Some(4))?;
+        let mut seq = serializer.serialize_seq(Some(5))?;
         seq.serialize_element(&self.id)?;
         seq.serialize_element(&self.entity_id)?;
         seq.serialize_element(&match &*self.ptr.read().unwrap() {
@@ -40,6 +40,8 @@ impl StatefulSerialize for MemoRef {
         // QUESTION: Should we be using memoref.get_peerlist_for_peer instead of has_memo?
         //           What about relayed memos which Slab A requests from B but actually receives from C?
         seq.serialize_element(&SerializeWrapper(&*self.peerlist.read().unwrap(), helper))?;
+        // As of when we reported whether we have the memo
+        seq.serialize_element(&peering_stamp())?;
         seq.end()
     }
 }
@@ -71,9 +73,10 @@ impl StatefulSerialize for MemoPeer {
     fn serialize<S>(&self, serializer: S, helper: &SerializeHelper) -> Result<S::Ok, S::Error>
         where S: Serializer
     {
-        let mut seq = serializer.serialize_seq(Some(2))?;
+        let mut seq = serializer.serialize_seq(Some(3))?;
         seq.serialize_element(&SerializeWrapper(&self.slabref, helper))?;
         seq.serialize_element(&self.status)?;
+        seq.serialize_element(&self.as_of)?;
         seq.end()
     }
 }
@@ -131,12 +134,23 @@ impl<'a> Visitor for MemoRefSeed<'a> {
             },
         };
 
-        peers.push(MemoPeer { slabref: self.origin_slabref.clone(),
-                              status:  if has_memo {
-                                  MemoPeeringStatus::Resident
-                              } else {
-                                  MemoPeeringStatus::Participating
-                              }, });
+        let as_of: u64 = match visitor.visit()? {
+            Some(value) => value,
+            None => {
+                return Err(DeError::invalid_length(4, &self));
+            },
+        };
+
+        // The origin is never a peer of its own memos. This is the case when reading back from our own storage.
+        if self.origin_slabref.slab_id != self.dest_slab.my_ref.slab_id {
+            peers.push(MemoPeer { slabref: self.origin_slabref.clone(),
+                                  status: if has_memo {
+                                      MemoPeeringStatus::Resident
+                                  } else {
+                                      MemoPeeringStatus::Participating
+                                  },
+                                  as_of });
+        }
 
         Ok(self.dest_slab
                .agent
@@ -182,7 +196,13 @@ impl<'a> Visitor for MemoPeerSeed<'a> {
                 return Err(DeError::invalid_length(1, &self));
             },
         };
+        let as_of: u64 = match visitor.visit()? {
+            Some(value) => value,
+            None => {
+                return Err(DeError::invalid_length(2, &self));
+            },
+        };
 
-        Ok(MemoPeer { slabref, status })
+        Ok(MemoPeer { slabref, status, as_of })
     }
 }
@@ -49,6 +49,17 @@ impl SlabRef {
         self.return_address.read().unwrap().clone()
     }
 
+    /// How long the slab is expected to stay around, by the longest of its presences
+    pub fn anticipated_lifetime(&self) -> SlabAnticipatedLifetime {
+        self.presence
+            .read()
+            .unwrap()
+            .iter()
+            .map(|presence| presence.lifetime.clone())
+            .max_by_key(|lifetime| lifetime.durability_weight())
+            .unwrap_or(SlabAnticipatedLifetime::Unknown)
+    }
+
     pub fn apply_presence(&self, presence: &SlabPresence) -> bool {
         // TODO - what about old presence information? Presumably SlabPresence should also be causal, no?
 
@@ -1,4 +1,13 @@
-use std::collections::HashMap;
+use std::{
+    collections::{
+        HashMap,
+        HashSet,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
 
 use futures::channel::{
     mpsc,
@@ -6,13 +15,19 @@ use futures::channel::{
 };
 
 use crate::{
+    context::stash::WeakStash,
     head::Head,
+    merge::MergePolicies,
     network::SlabRef,
     slab::{
+        subscriber::HeadSender,
         EntityId,
+        EntityType,
+        KeyframePolicy,
         Memo,
         MemoId,
         MemoRef,
+        SubscriptionTarget,
     },
 };
 
@@ -21,20 +36,54 @@ use crate::{
 /// Consider making SlabState a child of SlabAgent to further discourage this
 pub(super) struct SlabState {
     pub memorefs_by_id:       HashMap<MemoId, MemoRef>,
+    /// The ids of the memos in `memorefs_by_id` which belong to each entity
+    pub memo_ids_by_entity:   HashMap<EntityId, HashSet<MemoId>>,
     pub counters:             SlabCounters,
     pub peer_refs:            Vec<SlabRef>,
     pub memo_wait_channels:   HashMap<MemoId, Vec<oneshot::Sender<Memo>>>,
-    pub entity_subscriptions: HashMap<EntityId, Vec<mpsc::Sender<Head>>>,
-    pub index_subscriptions:  Vec<mpsc::Sender<Head>>,
+    pub entity_subscriptions: HashMap<EntityId, Vec<HeadSender>>,
+    pub index_subscriptions:  Vec<HeadSender>,
+    pub memo_subscriptions:   Vec<mpsc::UnboundedSender<Head>>,
+    pub remote_subscriptions: HashMap<EntityId, Vec<RemoteSubscription>>,
+    /// The subscriptions which we hold with our peers, and renew for as long as we're interested
+    pub held_subscriptions:   HashMap<SubscriptionTarget, HeldSubscription>,
+    pub restored_index_heads: Vec<Head>,
+    pub keyframe_policy:      KeyframePolicy,
+    pub durability_targets:   HashMap<EntityType, u8>,
+    pub merge_policies:       MergePolicies,
+    /// The stashes of our contexts, whose heads are retained by garbage collection
+    pub stashes:              Vec<WeakStash>,
     pub running:              bool,
 }
 
+/// A remote slab which has asked us to forward memos for a given entity
+#[derive(Clone)]
+pub(crate) struct RemoteSubscription {
+    pub slabref: SlabRef,
+    pub expires: Instant,
+    /// Whether the subscription extends to the entities which this one has edges to
+    pub subtree: bool,
+}
+
+/// A subscription which we hold with our peers
+pub(crate) struct HeldSubscription {
+    pub lease:    Duration,
+    /// The latest Subscription memo, from which each renewal descends such that it isn't mistaken for a duplicate
+    pub memoref:  MemoRef,
+    pub renewed:  Instant,
+    /// Whether the subscription is held on behalf of a local observer, and thus lapses once there is none
+    pub observed: bool,
+}
+
 #[derive(Debug)]
 pub(crate) struct SlabCounters {
     pub last_memo_id:               u32,
-    pub last_entity_id:             u32,
+    pub last_entity_id:             u64,
     pub memos_received:             u64,
     pub memos_redundantly_received: u64,
+    pub memos_collected:            u64,
+    /// Superseded memos which garbage collection retained because they weren't durable enough elsewhere
+    pub memos_retained_for_peering: u64,
 }
 
 // SlabState is forbidden from any blocking operations
@@ -43,14 +92,25 @@ pub(crate) struct SlabCounters {
 impl SlabState {
     pub fn new() -> Self {
         SlabState { memorefs_by_id:       HashMap::new(),
+                    memo_ids_by_entity:   HashMap::new(),
                     counters:             SlabCounters { last_memo_id:               5000,
                                                          last_entity_id:             9000,
                                                          memos_received:             0,
-                                                         memos_redundantly_received: 0, },
+                                                         memos_redundantly_received: 0,
+                                                         memos_collected:            0,
+                                                         memos_retained_for_peering: 0, },
                     peer_refs:            Vec::new(),
                     memo_wait_channels:   HashMap::new(),
                     entity_subscriptions: HashMap::new(),
                     index_subscriptions:  Vec::new(),
+                    memo_subscriptions:   Vec::new(),
+                    remote_subscriptions: HashMap::new(),
+                    held_subscriptions:   HashMap::new(),
+                    restored_index_heads: Vec::new(),
+                    keyframe_policy:      KeyframePolicy::default(),
+                    durability_targets:   HashMap::new(),
+                    merge_policies:       MergePolicies::default(),
+                    stashes:              Vec::new(),
                     running:              true, }
     }
 }
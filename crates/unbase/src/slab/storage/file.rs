@@ -0,0 +1,501 @@
+use super::*;
+use crate::{
+    network::TransportAddress,
+    util::serde::{
+        SerializeHelper,
+        SerializeWrapper,
+    },
+};
+
+use std::{
+    collections::{
+        hash_map::DefaultHasher,
+        HashMap,
+    },
+    fs::{
+        self,
+        File,
+        OpenOptions,
+    },
+    io::{
+        Read,
+        Seek,
+        SeekFrom,
+        Write,
+    },
+    hash::Hasher,
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::Mutex,
+};
+use tracing::warn;
+
+const LOG_FILE: &str = "memos.log";
+const COMPACT_FILE: &str = "memos.log.compact";
+
+/// Rewrite the log once superseded records outnumber live ones by this factor
+const COMPACTION_RATIO: usize = 2;
+/// While running, the log isn't rewritten until it has accumulated at least this many superseded records
+const COMPACTION_MIN_DEAD: usize = 1024;
+
+const TAG_MEMO: u8 = 1;
+const TAG_REMOVE: u8 = 2;
+const TAG_PEERLIST: u8 = 3;
+const TAG_COUNTERS: u8 = 4;
+const TAG_SLAB_ID: u8 = 5;
+const TAG_PEERS: u8 = 6;
+const TAG_ROOT_INDEX_SEED: u8 = 7;
+
+/// Durable storage consisting of a single append-only log file within the provided directory.
+///
+/// Each record is framed as `[tag][key length][key][value length][value]`, where the key is the memo id (plus the
+/// entity id, for peerlists) and the value is the wire encoding of the memo or peerlist. Records which are not specific
+/// to a memo, such as the slab id or counters, have an empty key. The index of live records is rebuilt by scanning the
+/// log on open. The log is compacted whenever it has accumulated too many superseded records, whether on open or as
+/// records are appended.
+///
+/// Writes which would not change the stored state (re-storing a memo, or an unchanged peerlist) are skipped, so that
+/// reconstituting a slab from its own storage does not grow the log.
+pub struct FileStorage {
+    inner: Mutex<FileStorageInner>,
+}
+
+struct FileStorageInner {
+    dir:        PathBuf,
+    log:        File,
+    len:        u64,
+    memos:      HashMap<MemoId, Frame>,
+    peerlists:  HashMap<MemoId, Frame>,
+    singletons: HashMap<u8, Frame>,
+    dead:       usize,
+}
+
+#[derive(Clone, Copy)]
+struct Frame {
+    offset: u64,
+    len:    usize,
+    digest: u64,
+}
+
+impl FileStorage {
+    /// Open the storage directory at the provided path, creating it if necessary
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<FileStorage, StorageError> {
+        let dir = path.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let mut inner = FileStorageInner::open(dir)?;
+
+        if inner.needs_compaction() {
+            inner.compact()?;
+        }
+
+        Ok(FileStorage { inner: Mutex::new(inner) })
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.inner.lock().unwrap().dir.clone()
+    }
+
+    fn serialize_key<K: ::serde::Serialize>(key: &K) -> Result<Vec<u8>, StorageError> {
+        serde_json::to_vec(key).map_err(|_| StorageError::Serialization)
+    }
+
+    fn put_singleton<T: ::serde::Serialize>(&self, tag: u8, value: &T) -> Result<(), StorageError> {
+        let value = serde_json::to_vec(value).map_err(|_| StorageError::Serialization)?;
+
+        self.inner.lock().unwrap().append(tag, MemoId::default(), &[], &value)
+    }
+}
+
+impl Storage for FileStorage {
+    fn put_memo(&self, memo: &Memo) -> Result<(), StorageError> {
+        let helper = SerializeHelper { dest_slab_id:   &memo.owning_slab_id,
+                                       return_address: &TransportAddress::Local, };
+
+        // Memos are immutable, so there is nothing to do if we have this one already
+        if self.inner.lock().unwrap().memos.contains_key(&memo.id) {
+            return Ok(());
+        }
+
+        let key = Self::serialize_key(&memo.id)?;
+        let value = serde_json::to_vec(&SerializeWrapper(memo, &helper)).map_err(|_| StorageError::Serialization)?;
+
+        self.inner.lock().unwrap().append(TAG_MEMO, memo.id, &key, &value)
+    }
+
+    fn remove_memo(&self, memo_id: MemoId) -> Result<(), StorageError> {
+        let key = Self::serialize_key(&memo_id)?;
+
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.memos.contains_key(&memo_id) {
+            return Ok(());
+        }
+        inner.append(TAG_REMOVE, memo_id, &key, &[])
+    }
+
+    fn put_peerlist(&self, memoref: &MemoRef) -> Result<(), StorageError> {
+        let helper = SerializeHelper { dest_slab_id:   &memoref.owning_slab_id,
+                                       return_address: &TransportAddress::Local, };
+
+        let key = Self::serialize_key(&(memoref.id, memoref.entity_id))?;
+        let value = {
+            let peerlist = memoref.peerlist.read().unwrap();
+            serde_json::to_vec(&SerializeWrapper(&*peerlist, &helper)).map_err(|_| StorageError::Serialization)?
+        };
+
+        self.inner.lock().unwrap().append(TAG_PEERLIST, memoref.id, &key, &value)
+    }
+
+    fn put_counters(&self, counters: &StoredCounters) -> Result<(), StorageError> {
+        self.put_singleton(TAG_COUNTERS, counters)
+    }
+
+    fn put_slab_id(&self, slab_id: SlabId) -> Result<(), StorageError> {
+        self.put_singleton(TAG_SLAB_ID, &slab_id)
+    }
+
+    fn put_peers(&self, peers: &[SlabPresence]) -> Result<(), StorageError> {
+        self.put_singleton(TAG_PEERS, &peers)
+    }
+
+    fn put_root_index_seed(&self, seed: &Head) -> Result<(), StorageError> {
+        let owning_slab_id = match seed.owning_slab_id() {
+            Some(slab_id) => slab_id,
+            None => return Ok(()),
+        };
+
+        let helper = SerializeHelper { dest_slab_id:   &owning_slab_id,
+                                       return_address: &TransportAddress::Local, };
+
+        let value = serde_json::to_vec(&SerializeWrapper(seed, &helper)).map_err(|_| StorageError::Serialization)?;
+
+        self.inner.lock().unwrap().append(TAG_ROOT_INDEX_SEED, MemoId::default(), &[], &value)
+    }
+
+    fn load(&self) -> Result<StoredSlab, StorageError> {
+        let mut inner = self.inner.lock().unwrap();
+        let buf = inner.read_all()?;
+
+        let memos = inner.memos.values().map(|frame| frame.value(&buf).to_vec()).collect();
+
+        let mut peerlists = Vec::with_capacity(inner.peerlists.len());
+        for frame in inner.peerlists.values() {
+            let (memo_id, entity_id) = serde_json::from_slice(frame.key(&buf)).map_err(|_| StorageError::Corrupt)?;
+            peerlists.push((memo_id, entity_id, frame.value(&buf).to_vec()));
+        }
+
+        Ok(StoredSlab { slab_id: inner.parse_singleton(&buf, TAG_SLAB_ID)?,
+                        memos,
+                        peerlists,
+                        counters: inner.parse_singleton(&buf, TAG_COUNTERS)?,
+                        peers: inner.parse_singleton(&buf, TAG_PEERS)?.unwrap_or_default(),
+                        root_index_seed: inner.singleton(&buf, TAG_ROOT_INDEX_SEED).map(|value| value.to_vec()) })
+    }
+
+    fn flush(&self) -> Result<(), StorageError> {
+        let inner = self.inner.lock().unwrap();
+        inner.log.sync_data()?;
+        Ok(())
+    }
+}
+
+impl FileStorageInner {
+    fn open(dir: PathBuf) -> Result<Self, StorageError> {
+        let log = OpenOptions::new().read(true)
+                                    .append(true)
+                                    .create(true)
+                                    .open(dir.join(LOG_FILE))?;
+
+        let mut inner = FileStorageInner { dir,
+                                           log,
+                                           len: 0,
+                                           memos: HashMap::new(),
+                                           peerlists: HashMap::new(),
+                                           singletons: HashMap::new(),
+                                           dead: 0 };
+
+        let buf = inner.read_all()?;
+        let mut offset = 0;
+
+        while let Some((tag, key, frame)) = Frame::parse(&buf, offset) {
+            let next = frame.offset + frame.len as u64;
+
+            let memo_id = match tag {
+                TAG_MEMO | TAG_REMOVE => serde_json::from_slice(key).map_err(|_| StorageError::Corrupt)?,
+                TAG_PEERLIST => {
+                    let (memo_id, _entity_id): (MemoId, Option<EntityId>) =
+                        serde_json::from_slice(key).map_err(|_| StorageError::Corrupt)?;
+                    memo_id
+                },
+                _ => MemoId::default(),
+            };
+            inner.index(tag, memo_id, frame);
+
+            offset = next;
+        }
+
+        if offset < buf.len() as u64 {
+            // The tail of the log was only partially written, presumably because we were interrupted mid-write
+            warn!("FileStorage discarding {} bytes of incomplete log", buf.len() as u64 - offset);
+            inner.log.set_len(offset)?;
+        }
+        inner.len = offset;
+
+        Ok(inner)
+    }
+
+    fn live(&self) -> usize {
+        self.memos.len() + self.peerlists.len() + self.singletons.len()
+    }
+
+    fn needs_compaction(&self) -> bool {
+        self.dead > COMPACTION_RATIO * self.live()
+    }
+
+    fn index(&mut self, tag: u8, memo_id: MemoId, frame: Frame) {
+        let superseded = match tag {
+            TAG_MEMO => self.memos.insert(memo_id, frame).is_some(),
+            TAG_REMOVE => {
+                // The removal record is itself dead weight once applied
+                self.dead += 1;
+                self.memos.remove(&memo_id).is_some()
+            },
+            TAG_PEERLIST => self.peerlists.insert(memo_id, frame).is_some(),
+            TAG_COUNTERS | TAG_SLAB_ID | TAG_PEERS | TAG_ROOT_INDEX_SEED => self.singletons.insert(tag, frame).is_some(),
+            _ => false,
+        };
+
+        if superseded {
+            self.dead += 1;
+        }
+    }
+
+    fn singleton<'a>(&self, buf: &'a [u8], tag: u8) -> Option<&'a [u8]> {
+        self.singletons.get(&tag).map(|frame| frame.value(buf))
+    }
+
+    fn parse_singleton<T: ::serde::Deserialize>(&self, buf: &[u8], tag: u8) -> Result<Option<T>, StorageError> {
+        match self.singleton(buf, tag) {
+            Some(value) => serde_json::from_slice(value).map(Some).map_err(|_| StorageError::Corrupt),
+            None => Ok(None),
+        }
+    }
+
+    fn append(&mut self, tag: u8, memo_id: MemoId, key: &[u8], value: &[u8]) -> Result<(), StorageError> {
+        let digest = digest(value);
+
+        // Skip records which would merely restate what we already have
+        let current = match tag {
+            TAG_PEERLIST => self.peerlists.get(&memo_id),
+            TAG_MEMO | TAG_REMOVE => None,
+            _ => self.singletons.get(&tag),
+        };
+        if let Some(frame) = current {
+            if frame.digest == digest {
+                return Ok(());
+            }
+        }
+
+        let mut buf = Vec::with_capacity(9 + key.len() + value.len());
+        buf.push(tag);
+        buf.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        buf.extend_from_slice(key);
+        buf.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        buf.extend_from_slice(value);
+
+        // Write the whole frame at once so that an interruption leaves at most one incomplete frame at the tail
+        self.log.write_all(&buf)?;
+
+        let frame = Frame { offset: self.len,
+                            len: buf.len(),
+                            digest };
+        self.len += buf.len() as u64;
+        self.index(tag, memo_id, frame);
+
+        if self.dead >= COMPACTION_MIN_DEAD && self.needs_compaction() {
+            self.compact()?;
+        }
+
+        Ok(())
+    }
+
+    fn read_all(&mut self) -> Result<Vec<u8>, StorageError> {
+        let mut buf = Vec::new();
+        self.log.seek(SeekFrom::Start(0))?;
+        self.log.read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Rewrite the log such that it contains only live records
+    fn compact(&mut self) -> Result<(), StorageError> {
+        let buf = self.read_all()?;
+
+        let mut frames: Vec<Frame> = self.memos
+                                         .values()
+                                         .chain(self.peerlists.values())
+                                         .chain(self.singletons.values())
+                                         .cloned()
+                                         .collect();
+        frames.sort_by_key(|f| f.offset);
+
+        let compact_path = self.dir.join(COMPACT_FILE);
+        {
+            let mut out = File::create(&compact_path)?;
+            for frame in frames.iter() {
+                out.write_all(frame.bytes(&buf))?;
+            }
+            out.sync_data()?;
+        }
+        fs::rename(&compact_path, self.dir.join(LOG_FILE))?;
+
+        *self = FileStorageInner::open(self.dir.clone())?;
+        Ok(())
+    }
+}
+
+impl Frame {
+    /// Parse the frame beginning at the given offset, returning None if it is absent or incomplete
+    fn parse(buf: &[u8], offset: u64) -> Option<(u8, &[u8], Frame)> {
+        let start = offset as usize;
+        let tag = *buf.get(start)?;
+
+        let key_len = read_u32(buf, start + 1)? as usize;
+        let key_start = start + 5;
+        let key = buf.get(key_start..key_start + key_len)?;
+
+        let value_start = key_start + key_len + 4;
+        let value_len = read_u32(buf, key_start + key_len)? as usize;
+        let end = value_start + value_len;
+        if end > buf.len() {
+            return None;
+        }
+
+        Some((tag,
+              key,
+              Frame { offset,
+                      len: end - start,
+                      digest: digest(&buf[value_start..end]) }))
+    }
+
+    fn bytes<'a>(&self, buf: &'a [u8]) -> &'a [u8] {
+        &buf[self.offset as usize..self.offset as usize + self.len]
+    }
+
+    fn key<'a>(&self, buf: &'a [u8]) -> &'a [u8] {
+        let bytes = self.bytes(buf);
+        let key_len = read_u32(bytes, 1).unwrap() as usize;
+        &bytes[5..5 + key_len]
+    }
+
+    fn value<'a>(&self, buf: &'a [u8]) -> &'a [u8] {
+        let bytes = self.bytes(buf);
+        let key_len = read_u32(bytes, 1).unwrap() as usize;
+        &bytes[9 + key_len..]
+    }
+}
+
+fn digest(value: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(value);
+    hasher.finish()
+}
+
+fn read_u32(buf: &[u8], at: usize) -> Option<u32> {
+    let bytes = buf.get(at..at + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+impl std::fmt::Debug for FileStorage {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let inner = self.inner.lock().unwrap();
+        fmt.debug_struct("FileStorage")
+           .field("dir", &inner.dir)
+           .field("memos", &inner.memos.len())
+           .field("peerlists", &inner.peerlists.len())
+           .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        Entity,
+        Network,
+        Slab,
+    };
+
+    #[unbase_test_util::async_test]
+    async fn file_storage_retains_memos() {
+        let dir = std::env::temp_dir().join(format!("unbase-file-storage-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let record_id;
+        {
+            let net = Network::create_new_system();
+            let slab = Slab::new_with_storage(&net, Box::new(FileStorage::open(&dir).unwrap()));
+            let context = slab.create_context();
+
+            let mut record = Entity::new_with_single_kv(&context, "animal_type", "Cat").await.unwrap();
+            record.set_value("sound", "Meow").await.unwrap();
+            record_id = record.id;
+        }
+
+        let storage = FileStorage::open(&dir).unwrap();
+        let stored = storage.load().unwrap();
+
+        let counters = stored.counters.expect("counters were stored when the slab stopped");
+        assert!(counters.last_entity_id >= record_id.id);
+
+        // Entity memos are retained. Peering and presence memos are not.
+        let record_memos = stored.memos
+                                 .iter()
+                                 .filter(|m| String::from_utf8_lossy(m).contains("animal_type"))
+                                 .count();
+        assert_eq!(record_memos, 1);
+        assert!(stored.memos.iter().all(|m| !String::from_utf8_lossy(m).contains("SlabPresence")));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[unbase_test_util::async_test]
+    async fn file_storage_persists_counters_with_memos() {
+        let dir = std::env::temp_dir().join(format!("unbase-file-storage-counters-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let net = Network::create_new_system();
+        let slab = Slab::new_with_storage(&net, Box::new(FileStorage::open(&dir).unwrap()));
+        let context = slab.create_context();
+        let record = Entity::new_with_single_kv(&context, "animal_type", "Cat").await.unwrap();
+
+        // Nor was the slab stopped
+        let counters = FileStorage::open(&dir).unwrap().load().unwrap().counters.expect("counters were stored");
+        assert!(counters.last_entity_id >= record.id.id);
+
+        drop(context);
+        drop(slab);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn file_storage_compacts_while_running() {
+        let dir = std::env::temp_dir().join(format!("unbase-file-storage-compaction-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let storage = FileStorage::open(&dir).unwrap();
+        for last_memo_id in 0..(4 * COMPACTION_MIN_DEAD as u32) {
+            storage.put_counters(&StoredCounters { last_memo_id,
+                                                   last_entity_id: 0 })
+                   .unwrap();
+        }
+
+        // Each record superseded the last, and only the most recent few are still in the log
+        let len = fs::metadata(dir.join(LOG_FILE)).unwrap().len();
+        assert!(len < (COMPACTION_MIN_DEAD as u64 + 1) * 100);
+        assert_eq!(storage.load().unwrap().counters.unwrap().last_memo_id, 4 * COMPACTION_MIN_DEAD as u32 - 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
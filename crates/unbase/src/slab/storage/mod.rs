@@ -0,0 +1,66 @@
+//! Pluggable durable storage for the memos held by a `Slab`.
+//!
+//! The `SlabAgent` keeps its working set of `MemoRef`s in memory regardless of the storage backend. A `Storage` is
+//! told about every entity memo which becomes resident (or ceases to be resident), about peerlist changes for those
+//! memos, and about the slab's identity, counters, peers and root index seed, such that a slab may later be
+//! reconstituted from whatever the backend retained. See `Slab::open`.
+
+#[cfg(not(target_arch = "wasm32"))]
+mod file;
+mod volatile;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::file::FileStorage;
+pub use self::volatile::VolatileStorage;
+
+use crate::{
+    error::StorageError,
+    head::Head,
+    slab::{
+        EntityId,
+        Memo,
+        MemoId,
+        MemoRef,
+        SlabId,
+        SlabPresence,
+    },
+};
+
+/// The subset of slab counters which must survive a restart
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct StoredCounters {
+    pub last_memo_id:   u32,
+    pub last_entity_id: u64,
+}
+
+/// Everything a `Storage` retained for a given slab, in serialized form.
+///
+/// Memos, peerlists and the root index seed are encoded using the same format that is used on the wire, so they must
+/// be deserialized against the slab which is being reconstituted.
+#[derive(Default)]
+pub struct StoredSlab {
+    pub slab_id:         Option<SlabId>,
+    pub memos:           Vec<Vec<u8>>,
+    pub peerlists:       Vec<(MemoId, Option<EntityId>, Vec<u8>)>,
+    pub counters:        Option<StoredCounters>,
+    pub peers:           Vec<SlabPresence>,
+    pub root_index_seed: Option<Vec<u8>>,
+}
+
+pub trait Storage: Send + Sync {
+    /// Durably record a memo which is now resident on this slab
+    fn put_memo(&self, memo: &Memo) -> Result<(), StorageError>;
+    /// Forget the body of a memo which is no longer resident on this slab. Its peerlist is retained.
+    fn remove_memo(&self, memo_id: MemoId) -> Result<(), StorageError>;
+    /// Record the latest peerlist for the given memoref, replacing any previous one
+    fn put_peerlist(&self, memoref: &MemoRef) -> Result<(), StorageError>;
+    fn put_counters(&self, counters: &StoredCounters) -> Result<(), StorageError>;
+    fn put_slab_id(&self, slab_id: SlabId) -> Result<(), StorageError>;
+    /// Record the presences of all known peer slabs, replacing any previous ones
+    fn put_peers(&self, peers: &[SlabPresence]) -> Result<(), StorageError>;
+    fn put_root_index_seed(&self, seed: &Head) -> Result<(), StorageError>;
+    /// Retrieve everything which was previously stored
+    fn load(&self) -> Result<StoredSlab, StorageError>;
+    /// Ensure that all prior writes have reached the underlying medium
+    fn flush(&self) -> Result<(), StorageError>;
+}
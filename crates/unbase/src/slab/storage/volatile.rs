@@ -0,0 +1,49 @@
+use super::*;
+
+/// Storage which retains nothing. The slab's memos live only as long as the process does.
+#[derive(Clone, Default)]
+pub struct VolatileStorage;
+
+impl VolatileStorage {
+    pub fn new() -> Self {
+        VolatileStorage
+    }
+}
+
+impl Storage for VolatileStorage {
+    fn put_memo(&self, _memo: &Memo) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    fn remove_memo(&self, _memo_id: MemoId) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    fn put_peerlist(&self, _memoref: &MemoRef) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    fn put_counters(&self, _counters: &StoredCounters) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    fn put_slab_id(&self, _slab_id: SlabId) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    fn put_peers(&self, _peers: &[SlabPresence]) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    fn put_root_index_seed(&self, _seed: &Head) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    fn load(&self) -> Result<StoredSlab, StorageError> {
+        Ok(StoredSlab::default())
+    }
+
+    fn flush(&self) -> Result<(), StorageError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+use crate::{
+    head::Head,
+    slab::{
+        EntityId,
+        MemoRef,
+    },
+};
+
+use futures::{
+    task::{
+        Context,
+        Poll,
+        Waker,
+    },
+    Stream,
+};
+use std::{
+    collections::{
+        HashMap,
+        VecDeque,
+    },
+    pin::Pin,
+    sync::{
+        Arc,
+        Mutex,
+    },
+};
+
+/// Create a channel for delivering entity heads to a subscriber.
+///
+/// Sending never blocks, and never fails for want of capacity. Rather, a head sent for an entity which already has one
+/// awaiting receipt is merged into it, such that a slow subscriber holds at most one pending head per entity and
+/// receives the latest state of each entity whenever it catches up.
+pub(crate) fn head_channel() -> (HeadSender, HeadReceiver) {
+    let shared = Arc::new(Mutex::new(Shared { order:  VecDeque::new(),
+                                              heads:  HashMap::new(),
+                                              waker:  None,
+                                              sender: true,
+                                              closed: false, }));
+
+    (HeadSender { shared: shared.clone() }, HeadReceiver { shared })
+}
+
+struct Shared {
+    /// Entities with a pending head, in the order in which they were first sent
+    order:  VecDeque<EntityId>,
+    heads:  HashMap<EntityId, Head>,
+    waker:  Option<Waker>,
+    /// Whether the sender still exists
+    sender: bool,
+    /// Whether the receiver has been dropped
+    closed: bool,
+}
+
+pub(crate) struct HeadSender {
+    shared: Arc<Mutex<Shared>>,
+}
+
+/// The stream of heads delivered to a subscriber. The stream ends when the slab goes away.
+pub struct HeadReceiver {
+    shared: Arc<Mutex<Shared>>,
+}
+
+#[derive(Debug)]
+pub(crate) struct ReceiverClosed;
+
+impl HeadSender {
+    /// Whether the receiver has been dropped
+    pub fn is_closed(&self) -> bool {
+        self.shared.lock().unwrap().closed
+    }
+
+    /// Queue a head for the subscriber, merging it with any which is already pending for the same entity.
+    /// Heads which aren't for an entity are disregarded.
+    pub fn send(&self, head: Head) -> Result<(), ReceiverClosed> {
+        let mut shared = self.shared.lock().unwrap();
+        if shared.closed {
+            return Err(ReceiverClosed);
+        }
+
+        let entity_id = match head.entity_id() {
+            Some(entity_id) => entity_id,
+            None => return Ok(()),
+        };
+
+        match shared.heads.get_mut(&entity_id) {
+            Some(pending) => coalesce(pending, head),
+            None => {
+                shared.heads.insert(entity_id, head);
+                shared.order.push_back(entity_id);
+            },
+        }
+
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for HeadSender {
+    fn drop(&mut self) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.sender = false;
+
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Stream for HeadReceiver {
+    type Item = Head;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Head>> {
+        let mut shared = self.shared.lock().unwrap();
+
+        if let Some(entity_id) = shared.order.pop_front() {
+            let head = shared.heads.remove(&entity_id).expect("pending head");
+            return Poll::Ready(Some(head));
+        }
+
+        if !shared.sender {
+            return Poll::Ready(None);
+        }
+
+        shared.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for HeadReceiver {
+    fn drop(&mut self) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.closed = true;
+        shared.order.clear();
+        shared.heads.clear();
+    }
+}
+
+/// Merge a newer head into a pending one without traversal. Pending memorefs which the new memorefs directly descend
+/// are superseded, and any others are retained alongside them, so that no concurrent edit is lost. The receiver
+/// applies the result to its own head, which resolves whatever remains.
+///
+/// Only resident memos can be inspected for their parents without blocking, so a new memoref which isn't resident
+/// supersedes nothing, and is merely retained alongside those pending. The pending head then grows by one memoref for
+/// each such head sent until it's received.
+fn coalesce(pending: &mut Head, new: Head) {
+    let (pending_memorefs, new_memorefs): (&mut Vec<MemoRef>, Vec<MemoRef>) = match (pending, new) {
+        (Head::Entity { head: pending_memorefs, .. }, Head::Entity { head: new_memorefs, .. }) => {
+            (pending_memorefs, new_memorefs)
+        },
+        (pending, new) => {
+            *pending = new;
+            return;
+        },
+    };
+
+    for memoref in new_memorefs {
+        if pending_memorefs.contains(&memoref) {
+            continue;
+        }
+
+        if let Some(memo) = memoref.get_memo_if_resident() {
+            let parent_ids = memo.get_parent_head().memo_ids();
+            pending_memorefs.retain(|pending| !parent_ids.contains(&pending.id));
+        }
+
+        pending_memorefs.push(memoref);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::head_channel;
+    use crate::{
+        head::Head,
+        slab::{
+            EntityType,
+            MemoBody,
+            MemoId,
+            MemoPeerList,
+        },
+        value::Value,
+        Network,
+        Slab,
+    };
+
+    use futures::StreamExt;
+    use std::collections::HashMap;
+
+    fn edit(key: &str, value: &str) -> HashMap<String, Option<Value>> {
+        let mut values = HashMap::new();
+        values.insert(key.to_string(), Some(Value::from(value)));
+        values
+    }
+
+    #[unbase_test_util::async_test]
+    async fn head_channel_coalesces() {
+        let net = Network::create_new_system();
+        let slab = Slab::new(&net);
+
+        let entity_a = slab.generate_entity_id(EntityType::Record);
+        let entity_b = slab.generate_entity_id(EntityType::Record);
+
+        let (tx, mut rx) = head_channel();
+
+        // Far more heads than any bounded queue would have held, none of which are received until the end
+        let mut head_a = slab.new_memo(Some(entity_a), Head::Null, MemoBody::Edit(HashMap::new()))
+                             .to_head();
+        tx.send(head_a.clone()).unwrap();
+        for _ in 0..500 {
+            head_a = slab.new_memo(Some(entity_a), head_a, MemoBody::Edit(HashMap::new())).to_head();
+            tx.send(head_a.clone()).unwrap();
+        }
+
+        // Concurrent heads for the same entity are both retained
+        let head_b1 = slab.new_memo(Some(entity_b), Head::Null, MemoBody::Edit(edit("sound", "Moo")))
+                          .to_head();
+        let head_b2 = slab.new_memo(Some(entity_b), Head::Null, MemoBody::Edit(edit("sound", "Woof")))
+                          .to_head();
+        tx.send(head_b1.clone()).unwrap();
+        tx.send(head_b2.clone()).unwrap();
+
+        assert_eq!(rx.next().await.unwrap().memo_ids(), head_a.memo_ids());
+
+        let mut expected = head_b1.memo_ids();
+        expected.extend(head_b2.memo_ids());
+        assert_eq!(rx.next().await.unwrap().memo_ids(), expected);
+
+        // A memo which isn't resident can't be inspected for its parents, so it's retained alongside the pending head
+        // rather than superseding it
+        let remote = slab.agent
+                         .assert_memoref(MemoId([9; 32]), Some(entity_a), MemoPeerList::new(Vec::new()), None)
+                         .0;
+        assert!(!remote.is_resident());
+        tx.send(head_a.clone()).unwrap();
+        tx.send(remote.to_head()).unwrap();
+
+        let mut expected = head_a.memo_ids();
+        expected.extend(remote.to_head().memo_ids());
+        assert_eq!(rx.next().await.unwrap().memo_ids(), expected);
+
+        // The stream ends once the sender is gone, and sending fails once the receiver is gone
+        let (tx2, rx2) = head_channel();
+        drop(tx);
+        assert!(rx.next().await.is_none());
+
+        drop(rx2);
+        assert!(tx2.send(head_a).is_err());
+    }
+}
@@ -9,6 +9,7 @@ use crate::{
         RelationSet,
         SlabHandle,
     },
+    value::Value,
 };
 
 pub struct SystemCreator;
@@ -16,12 +17,13 @@ pub struct SystemCreator;
 impl SystemCreator {
     pub fn generate_root_index_seed(slab: &SlabHandle) -> Head {
         let mut values = HashMap::new();
-        values.insert("tier".to_string(), 0.to_string());
+        values.insert("tier".to_string(), Value::Int(0));
 
         let memoref = slab.new_memo_noparent(Some(slab.generate_entity_id(EntityType::IndexNode)),
                                              MemoBody::FullyMaterialized { v: values,
                                                                            r: RelationSet::empty(),
                                                                            e: EdgeSet::empty(),
+                                                                           o: HashMap::new(),
                                                                            t: EntityType::IndexNode, });
 
         memoref.to_head()
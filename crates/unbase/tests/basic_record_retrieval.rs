@@ -1,11 +1,14 @@
 extern crate unbase;
 use unbase::{
+    slab::KeyframePolicy,
     util::simulator::Simulator,
+    value::Value,
     Entity,
     Network,
     Slab,
 };
 
+use std::collections::HashMap;
 use tracing::debug;
 
 #[unbase_test_util::async_test]
@@ -52,3 +55,66 @@ async fn basic_record_retrieval_simulator() {
 
     assert!(record_retrieved.is_some(), "Failed to retrieve record")
 }
+
+#[unbase_test_util::async_test]
+async fn all_values_and_relations() {
+    unbase_test_util::init_test_logger();
+
+    let net = Network::create_new_system();
+    let slab = Slab::new(&net);
+    let context = slab.create_context();
+
+    let mut vals = HashMap::new();
+    vals.insert("animal_type".to_string(), Value::from("Cat"));
+    vals.insert("name".to_string(), Value::from("Tom"));
+    let mut cat = Entity::new(&context, vals).await.unwrap();
+
+    let mouse = Entity::new_with_single_kv(&context, "animal_type", "Mouse").await.unwrap();
+    let mut dog = Entity::new_with_single_kv(&context, "animal_type", "Dog").await.unwrap();
+
+    cat.set_value("lives", 9).await.unwrap();
+    cat.set_value("name", "Thomas").await.unwrap();
+    cat.remove_value("animal_type").await.unwrap();
+    cat.set_relation(0, &mouse).await.unwrap();
+    cat.set_relation(1, &dog).await.unwrap();
+    cat.set_relation(0, &dog).await.unwrap();
+
+    let mut expected = HashMap::new();
+    expected.insert("name".to_string(), Value::from("Thomas"));
+    expected.insert("lives".to_string(), Value::Int(9));
+    assert_eq!(cat.get_all_values().await.unwrap(), expected);
+
+    let relations = cat.get_all_relations().await.unwrap();
+    assert_eq!(relations.len(), 2);
+    assert_eq!(relations[&0].id, dog.id);
+    assert_eq!(relations[&1].id, dog.id);
+
+    // Relations to deleted entities are omitted
+    dog.delete().await.unwrap();
+    assert!(cat.get_all_relations().await.unwrap().is_empty());
+}
+
+#[unbase_test_util::async_test]
+async fn all_values_of_concurrent_branches() {
+    unbase_test_util::init_test_logger();
+
+    let net = Network::create_new_system();
+    let slab = Slab::new(&net);
+    let context = slab.create_context();
+
+    let mut cat = Entity::new_with_single_kv(&context, "name", "Tom").await.unwrap();
+    let mut other = cat.clone();
+    other.set_value("color", "Black").await.unwrap();
+
+    // One branch is materialized by a keyframe, which mustn't hide the keys of the other
+    slab.set_keyframe_policy(KeyframePolicy { max_edits: Some(1),
+                                              max_depth: None, });
+    cat.set_value("lives", 8).await.unwrap();
+    cat.set_value("lives", 9).await.unwrap();
+
+    let mut expected = HashMap::new();
+    expected.insert("name".to_string(), Value::from("Tom"));
+    expected.insert("color".to_string(), Value::from("Black"));
+    expected.insert("lives".to_string(), Value::Int(9));
+    assert_eq!(cat.get_all_values().await.unwrap(), expected);
+}
@@ -0,0 +1,75 @@
+use futures::StreamExt;
+use unbase::{
+    error::WriteError,
+    util::simulator::Simulator,
+    Entity,
+    Network,
+    Slab,
+};
+
+#[unbase_test_util::async_test]
+async fn batch_edits_are_published_together() {
+    let net = Network::create_new_system();
+    let simulator = Simulator::new();
+    net.add_transport(Box::new(simulator.clone()));
+
+    simulator.start();
+
+    let slab_a = Slab::new(&net);
+    let slab_b = Slab::new(&net);
+    let context_a = slab_a.create_context();
+    let context_b = slab_b.create_context();
+
+    let mut alice = Entity::new_with_single_kv(&context_a, "name", "Alice").await.unwrap();
+    let mut bob = Entity::new_with_single_kv(&context_a, "name", "Bob").await.unwrap();
+    alice.set_value("balance", 10).await.unwrap();
+    bob.set_value("balance", 0).await.unwrap();
+
+    simulator.quiesce().await;
+
+    let mut batch = context_a.batch();
+    assert!(batch.is_empty());
+    batch.set_value(&alice, "balance", 3).unwrap();
+    batch.set_value(&bob, "balance", 7).unwrap();
+    batch.set_relation(&bob, 0, &alice).unwrap();
+    batch.remove_value(&alice, "name").unwrap();
+
+    // Nothing is written until the batch is committed
+    assert_eq!(alice.get_value("balance").await.unwrap(), Some("10".to_string()));
+
+    batch.commit().await.unwrap();
+
+    assert_eq!(alice.get_value("balance").await.unwrap(), Some("3".to_string()));
+    assert_eq!(alice.get_value("name").await.unwrap(), None);
+    assert_eq!(bob.get_value("balance").await.unwrap(), Some("7".to_string()));
+    assert_eq!(bob.get_relation(0).await.unwrap().map(|e| e.id), Some(alice.id));
+    assert!(context_a.try_fetch_kv("name", "Alice").await.unwrap().is_none());
+
+    simulator.quiesce().await;
+
+    let mut alice_b = context_b.get_entity_by_id(alice.id).await.unwrap().expect("alice on slab B");
+    let mut bob_b = context_b.get_entity_by_id(bob.id).await.unwrap().expect("bob on slab B");
+    assert_eq!(alice_b.get_value("balance").await.unwrap(), Some("3".to_string()));
+    assert_eq!(bob_b.get_value("balance").await.unwrap(), Some("7".to_string()));
+    assert_eq!(bob_b.get_relation(0).await.unwrap().map(|e| e.id), Some(alice.id));
+}
+
+#[unbase_test_util::async_test]
+async fn batch_rejects_historical_entities() {
+    let net = Network::create_new_system();
+    let slab = Slab::new(&net);
+    let context = slab.create_context();
+
+    let mut tiger = Entity::new_with_single_kv(&context, "beast", "Tiger").await.unwrap();
+    let before = tiger.history().await.unwrap().next().await.unwrap().unwrap().head;
+    tiger.set_value("sound", "Rawr").await.unwrap();
+
+    let then = tiger.at(before).unwrap();
+
+    let mut batch = context.batch();
+    assert_eq!(batch.set_value(&then, "sound", "Purr"), Err(WriteError::Historical));
+    assert!(batch.is_empty());
+
+    batch.commit().await.unwrap();
+    assert_eq!(tiger.get_value("sound").await.unwrap(), Some("Rawr".to_string()));
+}
@@ -0,0 +1,110 @@
+extern crate unbase;
+use futures::TryStreamExt;
+use unbase::{
+    context::Context,
+    index::IndexBTree,
+    util::simulator::Simulator,
+    Entity,
+    Network,
+    Slab,
+};
+
+async fn names(index: &IndexBTree, context: &Context) -> Vec<String> {
+    let mut stream = index.iter(context);
+    let mut names = Vec::new();
+    while let Some(mut entity) = stream.try_next().await.unwrap() {
+        names.push(entity.get_value("name").await.unwrap().unwrap());
+    }
+    names
+}
+
+async fn insert(index: &mut IndexBTree, context: &Context, name: String, expected: &mut Vec<String>) {
+    let record = Entity::new_with_single_kv(context, "name", &name).await.unwrap();
+    index.insert(context, &name, record.head().clone()).await.unwrap();
+    expected.push(name);
+}
+
+async fn exchange(context_a: &Context, context_b: &Context) {
+    context_a.hack_send_context(context_b).await.unwrap();
+    context_b.hack_send_context(context_a).await.unwrap();
+}
+
+#[unbase_test_util::async_test]
+async fn btree_concurrent_inserts() {
+    unbase_test_util::init_test_logger();
+
+    let net = Network::create_new_system();
+    let simulator = Simulator::new();
+    net.add_transport(Box::new(simulator.clone()));
+    simulator.start();
+
+    let slab_a = Slab::new(&net);
+    let slab_b = Slab::new(&net);
+    let context_a = slab_a.create_context();
+    let context_b = slab_b.create_context();
+
+    let mut index_a = IndexBTree::new(&context_a).await.unwrap();
+    let root_id = index_a.get_root_entity_id();
+
+    let mut expected = Vec::new();
+    for i in 0..60 {
+        insert(&mut index_a, &context_a, format!("k{:03}", i * 4), &mut expected).await;
+    }
+
+    simulator.quiesce().await;
+    exchange(&context_a, &context_b).await;
+
+    let mut index_b = IndexBTree::new_from_head(context_b.get_resident_entity_head(root_id));
+
+    // Slab A splits the root while slab B writes to it as a leaf
+    for i in 0..20 {
+        insert(&mut index_a, &context_a, format!("k{:03}", i * 4 + 1), &mut expected).await;
+    }
+    for i in 0..3 {
+        insert(&mut index_b, &context_b, format!("k{:03}", i * 4 + 2), &mut expected).await;
+    }
+
+    simulator.quiesce().await;
+    exchange(&context_a, &context_b).await;
+    expected.sort();
+
+    assert_eq!(context_a.get_resident_entity_head(root_id).len(), 2, "the root should have been written concurrently");
+
+    for (index, context) in &[(&index_a, &context_a), (&index_b, &context_b)] {
+        assert_eq!(names(index, context).await, expected);
+        assert!(index.get(context, "k002").await.unwrap().is_some());
+        assert!(index.get(context, "k005").await.unwrap().is_some());
+        assert!(index.get(context, "k003").await.unwrap().is_none());
+    }
+
+    // Both slabs now split leaves which the other writes to, and the root settles its loose entries
+    for i in 0..60 {
+        insert(&mut index_a, &context_a, format!("k{:03}", i * 4 + 3), &mut expected).await;
+    }
+    for i in 3..60 {
+        insert(&mut index_b, &context_b, format!("k{:03}", i * 4 + 2), &mut expected).await;
+    }
+
+    simulator.quiesce().await;
+    exchange(&context_a, &context_b).await;
+    expected.sort();
+
+    let within: Vec<String> = expected.iter().filter(|name| name.as_str() >= "k100" && name.as_str() < "k120").cloned().collect();
+
+    for (index, context) in &[(&index_a, &context_a), (&index_b, &context_b)] {
+        assert_eq!(names(index, context).await, expected);
+
+        let range: Vec<String> = index.range(context, "k100".."k120")
+                                      .and_then(|mut entity| async move { Ok(entity.get_value("name").await?.unwrap()) })
+                                      .try_collect()
+                                      .await
+                                      .unwrap();
+        assert_eq!(range, within);
+
+        for name in &expected {
+            assert!(index.get(context, name).await.unwrap().is_some(), "{} should be found", name);
+        }
+    }
+
+    simulator.quiesce_and_stop().await;
+}
@@ -0,0 +1,112 @@
+extern crate unbase;
+use unbase::{
+    crdt::CrdtValue,
+    Entity,
+    Network,
+    Slab,
+};
+
+use std::{
+    collections::{
+        BTreeMap,
+        BTreeSet,
+    },
+    fs,
+    time::Duration,
+};
+
+fn set(items: &[&str]) -> CrdtValue {
+    CrdtValue::Set(items.iter().map(|item| item.to_string()).collect::<BTreeSet<String>>())
+}
+
+#[unbase_test_util::async_test]
+async fn identical_concurrent_operations_each_count() {
+    let net = Network::create_new_system();
+    let slab = Slab::new(&net);
+    let context = slab.create_context();
+
+    let record = Entity::new_with_single_kv(&context, "name", "Pantry").await.unwrap();
+
+    // Three handles make the same operations from the same head
+    let mut handles = [record.clone(), record.clone(), record.clone()];
+    for handle in handles.iter_mut() {
+        handle.increment("visits", 1).await.unwrap();
+        handle.set_add("fruit", "apple").await.unwrap();
+    }
+
+    let mut record = context.get_entity(record.id).await.unwrap().expect("record");
+    assert_eq!(record.head().len(), 3);
+    assert_eq!(record.get_crdt("visits").await.unwrap(), Some(CrdtValue::Counter(3)));
+    assert_eq!(record.get_crdt("fruit").await.unwrap(), Some(set(&["apple"])));
+
+    // A removal which observed all three adds cancels them all
+    record.set_remove("fruit", "apple").await.unwrap();
+    assert_eq!(record.get_crdt("fruit").await.unwrap(), Some(set(&[])));
+}
+
+#[unbase_test_util::async_test]
+async fn crdt_fields_merge_concurrent_operations() {
+    unbase_test_util::init_test_logger();
+
+    let dir = std::env::temp_dir().join(format!("unbase-crdt-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    let record_id;
+    {
+        let net = Network::create_new_system();
+        let slab = Slab::open(&net, &dir).expect("open new slab");
+        let context = slab.create_context();
+
+        let mut record = Entity::new_with_single_kv(&context, "name", "Pantry").await.unwrap();
+        record_id = record.id;
+
+        record.increment("visits", 1).await.unwrap();
+        record.set_add("fruit", "apple").await.unwrap();
+
+        // Two handles operate on the same fields without having seen one another's operations
+        let mut left = record.clone();
+        let mut right = record.clone();
+
+        left.increment("visits", 5).await.unwrap();
+        right.increment("visits", -2).await.unwrap();
+
+        left.set_remove("fruit", "apple").await.unwrap();
+        right.set_add("fruit", "apple").await.unwrap();
+        right.set_add("fruit", "pear").await.unwrap();
+
+        left.map_increment("stock", &["shelf", "pear"], 3).await.unwrap();
+        right.map_increment("stock", &["shelf", "pear"], 4).await.unwrap();
+        right.map_set_add("stock", &["labels"], "organic").await.unwrap();
+
+        let mut record = context.get_entity(record_id).await.unwrap().expect("record");
+        assert_eq!(record.get_crdt("visits").await.unwrap(), Some(CrdtValue::Counter(4)));
+
+        // The removal only cancels the add it observed, so the concurrent add survives
+        assert_eq!(record.get_crdt("fruit").await.unwrap(), Some(set(&["apple", "pear"])));
+
+        record.set_remove("fruit", "apple").await.unwrap();
+        assert_eq!(record.get_crdt("fruit").await.unwrap(), Some(set(&["pear"])));
+
+        assert_eq!(record.get_crdt("missing").await.unwrap(), None);
+    }
+
+    // The operations survive a round trip through storage
+    let net = Network::new();
+    let slab = Slab::open(&net, &dir).expect("reopen slab");
+    let context = slab.create_context();
+
+    let mut record = context.fetch_kv("name", "Pantry", Duration::from_secs(1)).await.unwrap();
+    assert_eq!(record.id, record_id);
+    assert_eq!(record.get_crdt("visits").await.unwrap(), Some(CrdtValue::Counter(4)));
+
+    let mut shelf = BTreeMap::new();
+    shelf.insert("pear".to_string(), CrdtValue::Counter(7));
+    let mut stock = BTreeMap::new();
+    stock.insert("shelf".to_string(), CrdtValue::Map(shelf));
+    stock.insert("labels".to_string(), set(&["organic"]));
+    assert_eq!(record.get_crdt("stock").await.unwrap(), Some(CrdtValue::Map(stock)));
+
+    drop(context);
+    drop(slab);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,74 @@
+use futures::TryStreamExt;
+use unbase::{
+    util::simulator::Simulator,
+    Entity,
+    Network,
+    Slab,
+};
+
+#[unbase_test_util::async_test]
+async fn delete_removes_from_lookups() {
+    let net = Network::create_new_system();
+    let slab = Slab::new(&net);
+    let context = slab.create_context();
+    context.add_field_index("beast").await.unwrap();
+
+    let mut tiger = Entity::new_with_single_kv(&context, "beast", "Tiger").await.unwrap();
+    let lion = Entity::new_with_single_kv(&context, "beast", "Lion").await.unwrap();
+    tiger.set_value("sound", "Rawr").await.unwrap();
+
+    let mut stale = tiger.clone();
+
+    tiger.delete().await.unwrap();
+
+    assert!(tiger.is_deleted().await.unwrap());
+    assert!(context.get_entity(tiger.id).await.unwrap().is_none());
+    assert!(context.get_entity_by_id(tiger.id).await.unwrap().is_none());
+    assert!(context.try_fetch_kv("beast", "Tiger").await.unwrap().is_none());
+    assert_eq!(tiger.get_value("sound").await.unwrap(), None);
+
+    let beasts: Vec<Entity> = context.query().execute().await.unwrap().try_collect().await.unwrap();
+    assert_eq!(beasts.iter().map(|e| e.id).collect::<Vec<_>>(), vec![lion.id]);
+
+    // An edit through a handle which predates the deletion doesn't bring the entity back
+    stale.set_value("sound", "Purr").await.unwrap();
+    assert!(stale.is_deleted().await.unwrap());
+    assert!(context.get_entity(tiger.id).await.unwrap().is_none());
+
+    assert!(context.get_entity(lion.id).await.unwrap().is_some());
+    assert!(context.try_fetch_kv("beast", "Lion").await.unwrap().is_some());
+}
+
+#[unbase_test_util::async_test]
+async fn delete_prevails_over_concurrent_edit() {
+    let net = Network::create_new_system();
+    let simulator = Simulator::new();
+    net.add_transport(Box::new(simulator.clone()));
+
+    simulator.start();
+
+    let slab_a = Slab::new(&net);
+    let slab_b = Slab::new(&net);
+    let context_a = slab_a.create_context();
+    let context_b = slab_b.create_context();
+
+    let mut rec_a = Entity::new_with_single_kv(&context_a, "animal_sound", "Moo").await.unwrap();
+
+    simulator.quiesce().await;
+
+    let mut rec_b = context_b.get_entity_by_id(rec_a.id).await.unwrap().expect("record on slab B");
+
+    // Each slab acts without having seen what the other did
+    rec_a.delete().await.unwrap();
+    rec_b.set_value("animal_sound", "Woof").await.unwrap();
+    assert!(context_b.get_entity(rec_a.id).await.unwrap().is_some());
+
+    simulator.quiesce().await;
+
+    for context in &[&context_a, &context_b] {
+        assert!(context.get_entity(rec_a.id).await.unwrap().is_none());
+        assert!(context.try_fetch_kv("animal_sound", "Woof").await.unwrap().is_none());
+    }
+    assert!(rec_a.is_deleted().await.unwrap());
+    assert!(rec_b.is_deleted().await.unwrap());
+}
@@ -0,0 +1,109 @@
+use futures::StreamExt;
+use std::collections::BTreeSet;
+use unbase::{
+    head::{
+        Change,
+        ChangeTarget,
+        Head,
+        Side,
+    },
+    util::simulator::Simulator,
+    Entity,
+    Network,
+    Slab,
+};
+
+async fn head_of(entity: &Entity) -> Head {
+    entity.observe().next().await.unwrap()
+}
+
+fn change(side: Side, target: ChangeTarget) -> Change {
+    Change { side, target }
+}
+
+#[unbase_test_util::async_test]
+async fn diff_between_successive_heads() {
+    let net = Network::create_new_system();
+    let slab = Slab::new(&net);
+    let context = slab.create_context();
+
+    let mut tiger = Entity::new_with_single_kv(&context, "beast", "Tiger").await.unwrap();
+    let lion = Entity::new_with_single_kv(&context, "beast", "Lion").await.unwrap();
+    tiger.set_value("mood", "calm").await.unwrap();
+
+    let before = head_of(&tiger).await;
+
+    tiger.set_value("sound", "Rawr").await.unwrap();
+    tiger.set_relation(0, &lion).await.unwrap();
+    tiger.increment("visits", 1).await.unwrap();
+    // Changed and changed back
+    tiger.set_value("mood", "angry").await.unwrap();
+    tiger.set_value("mood", "calm").await.unwrap();
+
+    let after = head_of(&tiger).await;
+
+    let changes = after.diff(&before, &context.slab).await.unwrap();
+    let expected: BTreeSet<Change> = vec![change(Side::This, ChangeTarget::Field("sound".to_string())),
+                                          change(Side::This, ChangeTarget::Field("visits".to_string())),
+                                          change(Side::This, ChangeTarget::Relation(0)),].into_iter()
+                                                                                         .collect();
+    assert_eq!(changes, expected);
+
+    // The other way around, the same changes are attributed to the other head
+    let changes = before.diff(&after, &context.slab).await.unwrap();
+    assert!(changes.iter().all(|c| c.side == Side::Other));
+    assert_eq!(changes.len(), 3);
+
+    assert!(after.diff(&after, &context.slab).await.unwrap().is_empty());
+
+    tiger.delete().await.unwrap();
+    let deleted = head_of(&tiger).await;
+    assert!(deleted.diff(&after, &context.slab)
+                   .await
+                   .unwrap()
+                   .contains(&change(Side::This, ChangeTarget::Deletion)));
+
+    assert!(after.diff(&head_of(&lion).await, &context.slab).await.is_err());
+}
+
+#[unbase_test_util::async_test]
+async fn diff_between_sides_of_a_fork() {
+    let net = Network::create_new_system();
+    let simulator = Simulator::new();
+    net.add_transport(Box::new(simulator.clone()));
+
+    simulator.start();
+
+    let slab_a = Slab::new(&net);
+    let slab_b = Slab::new(&net);
+    let context_a = slab_a.create_context();
+    let context_b = slab_b.create_context();
+
+    let mut rec_a = Entity::new_with_single_kv(&context_a, "animal_sound", "Moo").await.unwrap();
+
+    simulator.quiesce().await;
+
+    let mut rec_b = context_b.get_entity_by_id(rec_a.id).await.unwrap().expect("record on slab B");
+
+    // Each slab acts without having seen what the other did
+    rec_a.set_value("animal_sound", "Woof").await.unwrap();
+    rec_a.set_value("legs", 4).await.unwrap();
+    rec_b.set_value("animal_sound", "Baa").await.unwrap();
+    rec_b.set_value("name", "Dolly").await.unwrap();
+    rec_b.set_value("legs", 4).await.unwrap();
+
+    simulator.quiesce().await;
+
+    // The tips of the two branches, as seen by slab A
+    let mut tips: Vec<_> = rec_a.history().await.unwrap().take(2).map(|entry| entry.unwrap()).collect().await;
+    tips.sort_by_key(|entry| entry.origin_slab_id != slab_a.id);
+    assert_eq!(tips[0].origin_slab_id, slab_a.id);
+    assert_eq!(tips[1].origin_slab_id, slab_b.id);
+
+    let changes = tips[0].head.diff(&tips[1].head, &context_a.slab).await.unwrap();
+    let expected: BTreeSet<Change> = vec![change(Side::This, ChangeTarget::Field("animal_sound".to_string())),
+                                          change(Side::Other, ChangeTarget::Field("animal_sound".to_string())),
+                                          change(Side::Other, ChangeTarget::Field("name".to_string())),].into_iter()
+                                                                                                       .collect();
+    assert_eq!(changes, expected);
+}
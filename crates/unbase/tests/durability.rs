@@ -0,0 +1,61 @@
+use unbase::{
+    slab::{
+        EntityType,
+        KeyframePolicy,
+        DEFAULT_DURABILITY_TARGET,
+    },
+    util::simulator::Simulator,
+    Entity,
+    Network,
+    Slab,
+};
+
+#[unbase_test_util::async_test]
+async fn memos_are_replicated_toward_the_durability_target() {
+    let net = Network::create_new_system();
+    let simulator = Simulator::new();
+    net.add_transport(Box::new(simulator.clone()));
+
+    simulator.start();
+
+    let slabs: Vec<Slab> = (0..4).map(|_| Slab::new(&net)).collect();
+    let slab_a = &slabs[0];
+    let context_a = slab_a.create_context();
+
+    assert_eq!(slab_a.durability_target(EntityType::Record), DEFAULT_DURABILITY_TARGET);
+    // Index nodes go to each of the three peers
+    for slab in slabs.iter() {
+        slab.set_durability_target(EntityType::Record, 4);
+        slab.set_durability_target(EntityType::IndexNode, 12);
+    }
+    slab_a.set_keyframe_policy(KeyframePolicy::disabled());
+
+    let mut dog = Entity::new_with_single_kv(&context_a, "name", "Rex").await.unwrap();
+    let first = dog.get_all_memo_ids().await.unwrap()[0];
+    simulator.quiesce().await;
+
+    // One copy on a slab of unknown lifetime is enough. The other two slabs know of the record by way of the root index,
+    // which counts for a little.
+    assert_eq!(slab_a.memo_durability_score(first), 4 + 1 + 1);
+
+    // Raising the target pushes it to further peers, though it only counts once they confirm that they hold it
+    slab_a.set_durability_target(EntityType::Record, 12);
+    assert!(slab_a.replicate_memos() > 0);
+    assert_eq!(slab_a.memo_durability_score(first), 4 + 1 + 1);
+    simulator.quiesce().await;
+    assert_eq!(slab_a.memo_durability_score(first), 4 * 3);
+    assert_eq!(slab_a.replicate_memos(), 0);
+
+    // Superseded memos may only be dropped once they're comfortably above the target
+    dog.set_value("name", "Max").await.unwrap();
+    dog.set_value("name", "Buddy").await.unwrap();
+    slab_a.set_keyframe_policy(KeyframePolicy { max_edits: Some(1),
+                                                max_depth: None, });
+    dog.set_value("name", "Rover").await.unwrap();
+    simulator.quiesce().await;
+    assert_eq!(slab_a.collect_garbage(), 0);
+
+    slab_a.set_durability_target(EntityType::Record, 8);
+    assert!(slab_a.collect_garbage() > 0);
+    assert_eq!(dog.get_value("name").await.unwrap(), Some("Rover".to_string()));
+}
@@ -7,6 +7,8 @@ use std::sync::{
     Mutex,
 };
 use unbase::{
+    index::ROOT_INDEX_DEPTH,
+    slab::EntityId,
     util::{
         simulator::Simulator,
         task::spawn_with_handle,
@@ -68,9 +70,8 @@ async fn eventual_detail() {
 
     simulator.quiesce().await;
 
-    assert!(slab_a.id == 0, "Slab A ID shoud be 0");
-    assert!(slab_b.id == 1, "Slab B ID shoud be 1");
-    assert!(slab_c.id == 2, "Slab C ID shoud be 2");
+    assert!(slab_a.id != slab_b.id && slab_b.id != slab_c.id && slab_a.id != slab_c.id,
+            "Slab IDs should be distinct");
 
     assert!(slab_a.peer_slab_count() == 2, "Slab A Should know two peers");
     assert!(slab_b.peer_slab_count() == 2, "Slab B Should know two peers");
@@ -165,9 +166,7 @@ async fn eventual_detail() {
     simulator.quiesce().await;
 
     // Nowwww it should have propagated
-    let expected_contents = "I9001>I9003;I9003>I9004;I9004>I9005;I9005>_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,\
-                             _,_,_,_,_,_,_,I9006;I9006>_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,\
-                             _,_,_,_,_,_,R9002";
+    let expected_contents = expected_index_contents(record_id);
     assert_eq!(context_a.concise_contents(), expected_contents);
     assert_eq!(context_b.concise_contents(), expected_contents);
 
@@ -175,3 +174,28 @@ async fn eventual_detail() {
     assert_eq!(rec_a1.get_value("animal_sound").await.unwrap().unwrap(), "Woof");
     assert_eq!(rec_a1.get_value("animal_type").await.unwrap().unwrap(), "Kanine");
 }
+
+/// The stash contents expected after a single record is inserted into a fresh root index. Index nodes are created
+/// by the record's slab after the record itself, one per tier, and the slot at each tier is a byte of its index key.
+fn expected_index_contents(record_id: EntityId) -> String {
+    let key = record_id.index_key();
+    let depth = ROOT_INDEX_DEPTH as u64;
+
+    let mut nodes = Vec::new();
+    for tier in 0..depth {
+        let node = if tier == 0 { 9001 } else { 9002 + tier };
+        let target = if tier == depth - 1 {
+            record_id.concise_string()
+        } else {
+            format!("I{}.{}", record_id.slab_id, 9003 + tier)
+        };
+
+        let slot = (key >> (8 * (depth - 1 - tier))) & 0xff;
+        let mut edges = vec!["_".to_string(); slot as usize];
+        edges.push(target);
+
+        nodes.push(format!("I{}.{}>{}", record_id.slab_id, node, edges.join(",")));
+    }
+
+    nodes.join(";")
+}
@@ -0,0 +1,52 @@
+extern crate unbase;
+use unbase::{
+    util::simulator::Simulator,
+    Entity,
+    Network,
+    Slab,
+};
+
+#[unbase_test_util::async_test]
+async fn field_index_is_shared() {
+    unbase_test_util::init_test_logger();
+
+    let net = Network::create_new_system();
+    let simulator = Simulator::new();
+    net.add_transport(Box::new(simulator.clone()));
+    simulator.start();
+
+    let slab_a = Slab::new(&net);
+    let slab_b = Slab::new(&net);
+    let context_a = slab_a.create_context();
+    let context_b = slab_b.create_context();
+    let context_a2 = slab_a.create_context();
+
+    let tiger = Entity::new_with_single_kv(&context_a, "beast", "Tiger").await.unwrap();
+    context_a.add_field_index("beast").await.unwrap();
+
+    simulator.quiesce().await;
+
+    // The declaration is visible to other contexts, whose writes are filed
+    let mut lion = Entity::new_with_single_kv(&context_b, "beast", "Lion").await.unwrap();
+    let _bear = Entity::new_with_single_kv(&context_a2, "beast", "Bear").await.unwrap();
+
+    simulator.quiesce().await;
+
+    for context in &[&context_a, &context_a2, &context_b] {
+        assert_eq!(context.try_fetch_kv("beast", "Tiger").await.unwrap().map(|e| e.id), Some(tiger.id));
+        assert_eq!(context.try_fetch_kv("beast", "Lion").await.unwrap().map(|e| e.id), Some(lion.id));
+        assert!(context.try_fetch_kv("beast", "Bear").await.unwrap().is_some());
+    }
+
+    // A change of value moves the entity to another posting
+    lion.set_value("beast", "Puma").await.unwrap();
+
+    simulator.quiesce().await;
+
+    for context in &[&context_a, &context_b] {
+        assert!(context.try_fetch_kv("beast", "Lion").await.unwrap().is_none());
+        assert_eq!(context.try_fetch_kv("beast", "Puma").await.unwrap().map(|e| e.id), Some(lion.id));
+    }
+
+    simulator.quiesce_and_stop().await;
+}
@@ -0,0 +1,63 @@
+use unbase::{
+    slab::{
+        EntityType,
+        KeyframePolicy,
+    },
+    util::simulator::Simulator,
+    Entity,
+    Network,
+    Slab,
+};
+
+#[unbase_test_util::async_test]
+async fn superseded_memos_are_collected_once_peers_hold_them() {
+    let net = Network::create_new_system();
+    let simulator = Simulator::new();
+    net.add_transport(Box::new(simulator.clone()));
+
+    simulator.start();
+
+    let slab_a = Slab::new(&net);
+    let slab_b = Slab::new(&net);
+    let context_a = slab_a.create_context();
+    let context_b = slab_b.create_context();
+
+    slab_a.set_keyframe_policy(KeyframePolicy { max_edits: Some(4),
+                                                max_depth: None, });
+    // A single copy on slab B is durable enough
+    slab_a.set_durability_target(EntityType::Record, 2);
+
+    let mut dog = Entity::new_with_single_kv(&context_a, "name", "Rex").await.unwrap();
+    let mut cat = Entity::new_with_single_kv(&context_a, "name", "Tom").await.unwrap();
+    for i in 0..12 {
+        dog.set_value("tricks", i).await.unwrap();
+        cat.increment("lives", 1).await.unwrap();
+    }
+
+    // Until slab B has acknowledged them, nothing is collected
+    assert_eq!(slab_a.collect_garbage(), 0);
+    assert!(slab_a.count_of_memos_retained_for_peering() > 0);
+
+    simulator.quiesce().await;
+
+    let before = slab_a.count_of_memorefs_resident();
+    let collected = slab_a.collect_garbage();
+    assert!(collected > 0);
+    assert_eq!(slab_a.count_of_memos_collected(), collected as u64);
+    assert!(slab_a.count_of_memorefs_resident() < before);
+
+    // Nothing more to collect until there are further edits
+    assert_eq!(slab_a.collect_garbage(), 0);
+
+    // Projections stop short of what was collected, CRDT fields included, as keyframes carry their operations
+    assert_eq!(dog.get_value("tricks").await.unwrap(), Some("11".to_string()));
+    assert_eq!(dog.get_value("name").await.unwrap(), Some("Rex".to_string()));
+    assert_eq!(cat.get_crdt("lives").await.unwrap().map(|v| format!("{:?}", v)),
+               Some("Counter(12)".to_string()));
+    assert!(context_a.try_fetch_kv("tricks", "11").await.unwrap().is_some());
+
+    // The history can still be had, from slab B
+    let mut dog_b = context_b.get_entity_by_id(dog.id).await.unwrap().expect("record on slab B");
+    assert_eq!(dog_b.get_value("tricks").await.unwrap(), Some("11".to_string()));
+    assert_eq!(dog.get_all_memo_ids().await.unwrap().len(), dog_b.get_all_memo_ids().await.unwrap().len());
+}
@@ -0,0 +1,137 @@
+use futures::StreamExt;
+use unbase::{
+    error::WriteError,
+    history::HistoryEntry,
+    util::simulator::Simulator,
+    Entity,
+    Network,
+    Slab,
+};
+
+#[unbase_test_util::async_test]
+async fn history_and_time_travel() {
+    let net = Network::create_new_system();
+    let slab = Slab::new(&net);
+    let context = slab.create_context();
+
+    let mut tiger = Entity::new_with_single_kv(&context, "beast", "Tiger").await.unwrap();
+    let lion = Entity::new_with_single_kv(&context, "beast", "Lion").await.unwrap();
+    tiger.set_value("sound", "Rawr").await.unwrap();
+    tiger.set_relation(0, &lion).await.unwrap();
+    tiger.set_value("sound", "Purr").await.unwrap();
+    tiger.remove_value("beast").await.unwrap();
+
+    let history: Vec<HistoryEntry> = tiger.history().await.unwrap().map(|entry| entry.unwrap()).collect().await;
+
+    let fields: Vec<Vec<&str>> = history.iter().map(|e| e.fields.iter().map(|f| f.as_str()).collect()).collect();
+    assert_eq!(fields, vec![vec!["beast"], vec!["sound"], vec![], vec!["sound"], vec!["beast"]]);
+    assert_eq!(history[2].relations, vec![0]);
+    assert!(history[4].materialized);
+    assert!(history.iter().all(|e| e.origin_slab_id == slab.id && !e.deleted));
+
+    // Projected from the head as of the first change to the sound
+    let mut then = tiger.at(history[3].head.clone()).unwrap();
+    assert_eq!(then.get_value("sound").await.unwrap(), Some("Rawr".to_string()));
+    assert_eq!(then.get_value("beast").await.unwrap(), Some("Tiger".to_string()));
+    assert!(then.get_relation(0).await.unwrap().is_none());
+
+    // Later changes don't reach it, and it can't be changed itself
+    tiger.set_value("sound", "Roar").await.unwrap();
+    assert_eq!(then.get_value("sound").await.unwrap(), Some("Rawr".to_string()));
+    assert_eq!(then.set_value("sound", "Hiss").await, Err(WriteError::Historical));
+
+    let mut then = context.get_entity_at(tiger.id, &[history[1].memo_id]).unwrap();
+    assert_eq!(then.get_value("sound").await.unwrap(), Some("Purr".to_string()));
+    assert_eq!(then.get_relation(0).await.unwrap().map(|e| e.id), Some(lion.id));
+
+    assert!(context.get_entity_at(lion.id, &[history[1].memo_id]).is_err());
+    assert_eq!(tiger.get_value("sound").await.unwrap(), Some("Roar".to_string()));
+}
+
+#[unbase_test_util::async_test]
+async fn history_records_originating_slab() {
+    let net = Network::create_new_system();
+    let simulator = Simulator::new();
+    net.add_transport(Box::new(simulator.clone()));
+
+    simulator.start();
+
+    let slab_a = Slab::new(&net);
+    let slab_b = Slab::new(&net);
+    let context_a = slab_a.create_context();
+    let context_b = slab_b.create_context();
+
+    let mut rec_a = Entity::new_with_single_kv(&context_a, "animal_sound", "Moo").await.unwrap();
+
+    simulator.quiesce().await;
+
+    let mut rec_b = context_b.get_entity_by_id(rec_a.id).await.unwrap().expect("record on slab B");
+    rec_b.set_value("animal_sound", "Woof").await.unwrap();
+
+    simulator.quiesce().await;
+
+    assert_eq!(rec_a.get_value("animal_sound").await.unwrap(), Some("Woof".to_string()));
+
+    for rec in &mut [&mut rec_a, &mut rec_b] {
+        let origins: Vec<u64> = rec.history().await.unwrap().map(|entry| entry.unwrap().origin_slab_id).collect().await;
+        assert_eq!(origins, vec![slab_b.id, slab_a.id]);
+    }
+}
+
+#[unbase_test_util::async_test]
+async fn revert_to_earlier_head() {
+    let net = Network::create_new_system();
+    let simulator = Simulator::new();
+    net.add_transport(Box::new(simulator.clone()));
+
+    simulator.start();
+
+    let slab_a = Slab::new(&net);
+    let slab_b = Slab::new(&net);
+    let context_a = slab_a.create_context();
+    let context_b = slab_b.create_context();
+
+    let mut tiger = Entity::new_with_single_kv(&context_a, "beast", "Tiger").await.unwrap();
+    let lion = Entity::new_with_single_kv(&context_a, "beast", "Lion").await.unwrap();
+    tiger.set_value("sound", "Rawr").await.unwrap();
+    tiger.set_relation(0, &lion).await.unwrap();
+
+    let before = tiger.history().await.unwrap().next().await.unwrap().unwrap().head;
+
+    // An accidental overwrite
+    tiger.set_value("sound", "Purr").await.unwrap();
+    tiger.remove_value("beast").await.unwrap();
+    tiger.set_relation(1, &lion).await.unwrap();
+
+    tiger.revert_to(&before).await.unwrap();
+
+    assert_eq!(tiger.get_value("sound").await.unwrap(), Some("Rawr".to_string()));
+    assert_eq!(tiger.get_value("beast").await.unwrap(), Some("Tiger".to_string()));
+    assert_eq!(tiger.get_relation(0).await.unwrap().map(|e| e.id), Some(lion.id));
+    assert!(tiger.get_relation(1).await.unwrap().is_none());
+    assert!(context_a.try_fetch_kv("beast", "Tiger").await.unwrap().is_some());
+
+    // The reversion is a change like any other, which descends from the overwrite
+    let latest = tiger.history().await.unwrap().next().await.unwrap().unwrap();
+    assert!(latest.materialized);
+    assert_eq!(latest.fields, vec!["beast".to_string(), "sound".to_string()]);
+
+    simulator.quiesce().await;
+
+    let mut copy = context_b.get_entity_by_id(tiger.id).await.unwrap().expect("record on slab B");
+    assert_eq!(copy.get_value("sound").await.unwrap(), Some("Rawr".to_string()));
+    assert_eq!(copy.get_value("beast").await.unwrap(), Some("Tiger".to_string()));
+
+    // Reverting to a head from before a deletion brings the entity back, and vice versa
+    tiger.delete().await.unwrap();
+    let deleted = tiger.history().await.unwrap().next().await.unwrap().unwrap().head;
+
+    tiger.revert_to(&before).await.unwrap();
+    assert!(!tiger.is_deleted().await.unwrap());
+    assert_eq!(context_a.get_entity(tiger.id).await.unwrap().map(|e| e.id), Some(tiger.id));
+    assert_eq!(tiger.get_value("sound").await.unwrap(), Some("Rawr".to_string()));
+
+    tiger.revert_to(&deleted).await.unwrap();
+    assert!(tiger.is_deleted().await.unwrap());
+    assert!(context_a.get_entity(tiger.id).await.unwrap().is_none());
+}
@@ -53,9 +53,8 @@ async fn init_local_multi() {
         let slab_b = unbase::Slab::new(&net);
         let slab_c = unbase::Slab::new(&net);
 
-        assert!(slab_a.id == 0, "Slab A ID shoud be 0");
-        assert!(slab_b.id == 1, "Slab B ID shoud be 1");
-        assert!(slab_c.id == 2, "Slab C ID shoud be 2");
+        assert!(slab_a.id != slab_b.id && slab_b.id != slab_c.id && slab_a.id != slab_c.id,
+                "Slab IDs should be distinct");
 
         assert!(slab_a.peer_slab_count() == 2, "Slab A Should know two peers");
         assert!(slab_b.peer_slab_count() == 2, "Slab B Should know two peers");
@@ -106,7 +105,6 @@ async fn udp_station_one() {
 
 async fn udp_station_two() {
     let net2 = unbase::Network::new();
-    net2.hack_set_next_slab_id(200);
     Delay::new(Duration::from_millis(50)).await;
     {
         let udp2 = unbase::network::transport::TransportUDP::new("127.0.0.1:1337".to_string());
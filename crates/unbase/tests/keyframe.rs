@@ -0,0 +1,173 @@
+use futures::StreamExt;
+use unbase::{
+    crdt::CrdtValue,
+    history::HistoryEntry,
+    slab::KeyframePolicy,
+    Entity,
+    Network,
+    Slab,
+};
+
+async fn history_of(entity: &mut Entity) -> Vec<HistoryEntry> {
+    entity.history().await.unwrap().map(|entry| entry.unwrap()).collect().await
+}
+
+/// The number of memos which a projection traverses before it reaches a materialized one
+async fn depth_of(entity: &mut Entity) -> usize {
+    history_of(entity).await.iter().position(|entry| entry.materialized).unwrap()
+}
+
+#[unbase_test_util::async_test]
+async fn keyframes_bound_projection() {
+    let net = Network::create_new_system();
+    let slab = Slab::new(&net);
+    let context = slab.create_context();
+
+    slab.set_keyframe_policy(KeyframePolicy { max_edits: Some(4),
+                                              max_depth: None, });
+
+    let mut dog = Entity::new_with_single_kv(&context, "name", "Rex").await.unwrap();
+    let owner = Entity::new_with_single_kv(&context, "name", "Alice").await.unwrap();
+    dog.set_relation(0, &owner).await.unwrap();
+    dog.set_value("legs", 3).await.unwrap();
+    dog.remove_value("name").await.unwrap();
+
+    for i in 0..20 {
+        dog.set_value("tricks", i).await.unwrap();
+        dog.increment("walks", 1).await.unwrap();
+        assert!(depth_of(&mut dog).await <= 4);
+    }
+
+    let history = history_of(&mut dog).await;
+    assert!(history.iter().filter(|entry| entry.materialized).count() > 5);
+
+    // Keyframes present the state as it was, and CRDT fields are still merged from the entire history
+    assert_eq!(dog.get_value("tricks").await.unwrap(), Some("19".to_string()));
+    assert_eq!(dog.get_value("legs").await.unwrap(), Some("3".to_string()));
+    assert_eq!(dog.get_value("name").await.unwrap(), None);
+    assert_eq!(dog.get_relation(0).await.unwrap().map(|e| e.id), Some(owner.id));
+    assert_eq!(dog.get_crdt("walks").await.unwrap(), Some(CrdtValue::Counter(20)));
+    assert!(context.try_fetch_kv("tricks", "19").await.unwrap().is_some());
+
+    // Without a policy, the chain is as long as it gets
+    slab.set_keyframe_policy(KeyframePolicy::disabled());
+    for i in 0..10 {
+        dog.set_value("tricks", i).await.unwrap();
+    }
+    assert!(depth_of(&mut dog).await >= 10);
+}
+
+#[unbase_test_util::async_test]
+async fn keyframes_by_depth_retain_concurrent_values() {
+    let net = Network::create_new_system();
+    let slab = Slab::new(&net);
+    let context = slab.create_context();
+
+    slab.set_keyframe_policy(KeyframePolicy { max_edits: None,
+                                              max_depth: Some(3), });
+
+    let mut dog = Entity::new_with_single_kv(&context, "name", "Rex").await.unwrap();
+    for i in 0..4 {
+        dog.set_value("tricks", i).await.unwrap();
+    }
+    assert!(history_of(&mut dog).await[0].materialized);
+
+    // A key with concurrent values can't be summarized by a keyframe, so none is written until they're resolved
+    let mut left = dog.clone();
+    let mut right = dog.clone();
+    left.set_value("name", "Max").await.unwrap();
+    right.set_value("name", "Buddy").await.unwrap();
+
+    let mut dog = context.get_entity(dog.id).await.unwrap().expect("record");
+    for i in 0..5 {
+        dog.set_value("tricks", i).await.unwrap();
+    }
+    assert!(depth_of(&mut dog).await > 3);
+
+    dog.set_value("name", "Rex").await.unwrap();
+    assert_eq!(depth_of(&mut dog).await, 0);
+    assert_eq!(dog.get_values("name").await.unwrap(), vec!["Rex".to_string()]);
+}
+
+#[unbase_test_util::async_test]
+async fn keyframes_retain_concurrent_edges() {
+    let net = Network::create_new_system();
+    let slab = Slab::new(&net);
+    let context = slab.create_context();
+
+    slab.set_keyframe_policy(KeyframePolicy { max_edits: None,
+                                              max_depth: Some(3), });
+
+    let owner = Entity::new_with_single_kv(&context, "name", "Alice").await.unwrap();
+    let dog = Entity::new_with_single_kv(&context, "name", "Rex").await.unwrap();
+
+    // Each branch points the edge at a different edit of its target
+    let mut owner_left = owner.clone();
+    let mut owner_right = owner.clone();
+    owner_left.set_value("city", "Paris").await.unwrap();
+    owner_right.set_value("age", 30).await.unwrap();
+
+    let mut left = dog.clone();
+    let mut right = dog.clone();
+    left.set_edges(vec![(0, &owner_left)].into_iter().collect()).await.unwrap();
+    right.set_edges(vec![(0, &owner_right)].into_iter().collect()).await.unwrap();
+
+    // Which can't be summarized by a keyframe, so none is written until they're resolved
+    let mut dog = context.get_entity(dog.id).await.unwrap().expect("record");
+    for i in 0..5 {
+        dog.set_value("tricks", i).await.unwrap();
+    }
+    assert!(depth_of(&mut dog).await > 3);
+
+    let mut owner = dog.get_edge(0).await.unwrap().expect("edge");
+    assert_eq!(owner.get_value("city").await.unwrap(), Some("Paris".to_string()));
+    assert_eq!(owner.get_i64("age").await.unwrap(), Some(30));
+
+    dog.set_edges(vec![(0, &owner)].into_iter().collect()).await.unwrap();
+    assert_eq!(depth_of(&mut dog).await, 0);
+
+    let mut owner = dog.get_edge(0).await.unwrap().expect("edge");
+    assert_eq!(owner.get_value("city").await.unwrap(), Some("Paris".to_string()));
+    assert_eq!(owner.get_i64("age").await.unwrap(), Some(30));
+}
+
+#[unbase_test_util::async_test]
+async fn keyframes_carry_crdt_operations() {
+    let net = Network::create_new_system();
+    let slab = Slab::new(&net);
+    let context = slab.create_context();
+
+    slab.set_keyframe_policy(KeyframePolicy { max_edits: Some(2),
+                                              max_depth: None, });
+
+    let mut cat = Entity::new_with_single_kv(&context, "name", "Tom").await.unwrap();
+    cat.set_add("toys", "ball").await.unwrap();
+    for _ in 0..3 {
+        cat.increment("lives", 1).await.unwrap();
+    }
+
+    // Each branch writes keyframes of its own, which carry the operations the branches have in common
+    let mut left = cat.clone();
+    let mut right = cat.clone();
+    right.set_remove("toys", "ball").await.unwrap();
+    for _ in 0..4 {
+        left.increment("lives", 1).await.unwrap();
+        right.increment("lives", 2).await.unwrap();
+    }
+    left.set_add("toys", "mouse").await.unwrap();
+
+    // Those are merged as one
+    let mut cat = context.get_entity(cat.id).await.unwrap().expect("record");
+    assert_eq!(cat.head().len(), 2);
+    assert_eq!(cat.get_crdt("lives").await.unwrap(), Some(CrdtValue::Counter(15)));
+    assert_eq!(cat.get_crdt("toys").await.unwrap(),
+               Some(CrdtValue::Set(vec!["mouse".to_string()].into_iter().collect())));
+
+    cat.increment("lives", 1).await.unwrap();
+    cat.increment("lives", 1).await.unwrap();
+    cat.increment("lives", 1).await.unwrap();
+    assert!(depth_of(&mut cat).await <= 2);
+    assert_eq!(cat.get_crdt("lives").await.unwrap(), Some(CrdtValue::Counter(18)));
+    assert_eq!(cat.get_crdt("toys").await.unwrap(),
+               Some(CrdtValue::Set(vec!["mouse".to_string()].into_iter().collect())));
+}
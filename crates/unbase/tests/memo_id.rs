@@ -0,0 +1,51 @@
+use futures::StreamExt;
+use unbase::{
+    slab::MemoId,
+    util::simulator::Simulator,
+    Entity,
+    Network,
+    Slab,
+};
+
+async fn head_memo_ids(entity: &mut Entity) -> Vec<MemoId> {
+    entity.history().await.unwrap().next().await.unwrap().unwrap().head.memo_ids()
+}
+
+#[unbase_test_util::async_test]
+async fn identical_memos_converge_across_slabs() {
+    let net = Network::create_new_system();
+    let simulator = Simulator::new();
+    net.add_transport(Box::new(simulator.clone()));
+
+    simulator.start();
+
+    let slab_a = Slab::new(&net);
+    let slab_b = Slab::new(&net);
+    let context_a = slab_a.create_context();
+    let context_b = slab_b.create_context();
+
+    let mut dog_a = Entity::new_with_single_kv(&context_a, "name", "Rex").await.unwrap();
+    simulator.quiesce().await;
+
+    let mut dog_b = context_b.get_entity_by_id(dog_a.id).await.unwrap().expect("record on slab B");
+    assert_eq!(head_memo_ids(&mut dog_a).await, head_memo_ids(&mut dog_b).await);
+
+    // Each slab independently makes the same edit to the same head, which yields the same memo
+    dog_a.set_value("sound", "Woof").await.unwrap();
+    dog_b.set_value("sound", "Woof").await.unwrap();
+
+    let converged = head_memo_ids(&mut dog_a).await;
+    assert_eq!(converged.len(), 1);
+    assert_eq!(converged, head_memo_ids(&mut dog_b).await);
+
+    // Each receives the other's copy, and recognizes it as one it already has
+    let redundant_a = slab_a.count_of_memos_reduntantly_received();
+    let redundant_b = slab_b.count_of_memos_reduntantly_received();
+    simulator.quiesce().await;
+    assert!(slab_a.count_of_memos_reduntantly_received() > redundant_a);
+    assert!(slab_b.count_of_memos_reduntantly_received() > redundant_b);
+
+    // Rather than as a concurrent edit
+    assert_eq!(head_memo_ids(&mut dog_a).await, converged);
+    assert_eq!(dog_b.get_values("sound").await.unwrap(), vec!["Woof".to_string()]);
+}
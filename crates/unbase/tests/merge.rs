@@ -0,0 +1,108 @@
+extern crate unbase;
+use std::collections::HashMap;
+use unbase::{
+    merge::{
+        ConcurrentValue,
+        MultiValue,
+    },
+    slab::EntityType,
+    value::Value,
+    Entity,
+    Network,
+    Slab,
+};
+
+#[unbase_test_util::async_test]
+async fn merge_policies() {
+    unbase_test_util::init_test_logger();
+
+    let net = Network::create_new_system();
+    let slab = Slab::new(&net);
+    let context = slab.create_context();
+
+    let record = Entity::new_with_single_kv(&context, "sound", "Moo").await.unwrap();
+
+    // Two handles write the same fields without having seen one another's writes
+    let mut left = record.clone();
+    let mut right = record.clone();
+    for (field, left_value, right_value) in &[("sound", "Woof", "Meow"), ("name", "Rex", "Tom")] {
+        left.set_value(field, left_value).await.unwrap();
+        right.set_value(field, right_value).await.unwrap();
+    }
+    left.set_value("legs", 4).await.unwrap();
+    right.set_value("legs", 2).await.unwrap();
+
+    let mut record = context.get_entity(record.id).await.unwrap().expect("record");
+
+    // By default, a single value is chosen, and every handle agrees on it
+    let sound = record.get_values("sound").await.unwrap();
+    assert_eq!(sound.len(), 1);
+    assert_eq!(left.get_value("sound").await.unwrap(), Some(sound[0].clone()));
+    assert_eq!(right.get_value("sound").await.unwrap(), Some(sound[0].clone()));
+
+    // As does a scan of the index
+    let other_sound = if sound[0] == "Woof" { "Meow" } else { "Woof" };
+    assert_eq!(context.try_fetch_kv("sound", &sound[0]).await.unwrap().map(|e| e.id), Some(record.id));
+    assert!(context.try_fetch_kv("sound", other_sound).await.unwrap().is_none());
+
+    // A multi-value policy presents both sides of the conflict
+    slab.set_merge_policy_for_type(EntityType::Record, MultiValue);
+    let mut sounds = record.get_values("sound").await.unwrap();
+    sounds.sort();
+    assert_eq!(sounds, vec!["Meow".to_string(), "Woof".to_string()]);
+
+    // Policies are declared on the slab, such that its other contexts present the same values
+    let other_context = slab.create_context();
+    context.hack_send_context(&other_context).await.unwrap();
+    let mut other_record = other_context.get_entity(record.id).await.unwrap().expect("record");
+    let mut other_sounds = other_record.get_values("sound").await.unwrap();
+    other_sounds.sort();
+    assert_eq!(other_sounds, sounds);
+
+    // Which a field policy takes precedence over
+    slab.set_merge_policy_for_field("legs", |_field: &str, values: Vec<ConcurrentValue>| {
+        let max = values.into_iter().filter_map(|v| v.value.as_i64()).max().unwrap();
+        vec![Value::Int(max)]
+    });
+    assert_eq!(record.get_i64("legs").await.unwrap(), Some(4));
+    assert_eq!(other_record.get_i64("legs").await.unwrap(), Some(4));
+
+    // A write which has seen the conflict supersedes both sides of it
+    record.set_value("sound", "Quack").await.unwrap();
+    assert_eq!(record.get_values("sound").await.unwrap(), vec!["Quack".to_string()]);
+    assert_eq!(record.get_values("name").await.unwrap().len(), 2);
+    assert_eq!(record.get_values("missing").await.unwrap(), Vec::<String>::new());
+}
+
+#[unbase_test_util::async_test]
+async fn concurrent_edges() {
+    unbase_test_util::init_test_logger();
+
+    let net = Network::create_new_system();
+    let slab = Slab::new(&net);
+    let context = slab.create_context();
+
+    let owner = Entity::new_with_single_kv(&context, "name", "Alice").await.unwrap();
+    let vet = Entity::new_with_single_kv(&context, "name", "Dr. Bob").await.unwrap();
+    let mut record = Entity::new_with_single_kv(&context, "name", "Rex").await.unwrap();
+
+    let mut edges = HashMap::new();
+    edges.insert(0, &owner);
+    record.set_edges(edges).await.unwrap();
+
+    // One handle overwrites the edge, while another concurrently writes something else entirely
+    let mut left = record.clone();
+    let mut right = record.clone();
+    let mut edges = HashMap::new();
+    edges.insert(0, &vet);
+    left.set_edges(edges).await.unwrap();
+    right.set_value("legs", 4).await.unwrap();
+
+    // The overwritten edge is superseded, rather than merged with that which overwrote it
+    let mut record = context.get_entity(record.id).await.unwrap().expect("record");
+    assert_eq!(record.head().len(), 2);
+    let target = record.get_edge(0).await.unwrap().expect("edge");
+    assert_eq!(target.id, vet.id);
+    assert_eq!(target.head().len(), 1);
+    assert_eq!(record.get_i64("legs").await.unwrap(), Some(4));
+}
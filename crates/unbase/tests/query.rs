@@ -0,0 +1,164 @@
+extern crate unbase;
+use futures::{
+    Stream,
+    StreamExt,
+    TryStreamExt,
+};
+use std::{
+    collections::HashMap,
+    time::Duration,
+};
+use unbase::{
+    error::RetrieveError,
+    query::{
+        Comparison,
+        QueryEvent,
+        QueryObserver,
+    },
+    Entity,
+    Network,
+    Slab,
+};
+
+async fn names(stream: impl Stream<Item = Result<Entity, RetrieveError>>) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    let mut stream = Box::pin(stream);
+    while let Some(mut entity) = stream.try_next().await.unwrap() {
+        names.push(entity.get_value("name").await.unwrap().unwrap());
+    }
+    names.sort();
+    names
+}
+
+#[unbase_test_util::async_test]
+async fn query_filter_traverse_limit() {
+    unbase_test_util::init_test_logger();
+
+    let net = Network::create_new_system();
+    let slab = Slab::new(&net);
+    let context = slab.create_context();
+
+    let alice = Entity::new_with_single_kv(&context, "name", "Alice").await.unwrap();
+    let bob = Entity::new_with_single_kv(&context, "name", "Bob").await.unwrap();
+
+    for (name, beast, age, owner) in &[("Rex", "Dog", 9, &alice), ("Fido", "Dog", 10, &bob), ("Tom", "Cat", 2, &alice)] {
+        let mut pet = Entity::new_with_single_kv(&context, "name", name).await.unwrap();
+        pet.set_value("beast", beast).await.unwrap();
+        pet.set_value("age", *age).await.unwrap();
+        pet.set_relation(0, owner).await.unwrap();
+    }
+
+    let dogs = context.query().eq("beast", "Dog").execute().await.unwrap();
+    assert_eq!(names(dogs).await, vec!["Fido", "Rex"]);
+
+    let late = context.query().filter("name", Comparison::Gt, "Fido").execute().await.unwrap();
+    assert_eq!(names(late).await, vec!["Rex", "Tom"]);
+
+    // Numbers compare numerically, and not at all with strings
+    let old = context.query().filter("age", Comparison::Ge, 9).execute().await.unwrap();
+    assert_eq!(names(old).await, vec!["Fido", "Rex"]);
+
+    let old = context.query().filter("age", Comparison::Ge, "9").execute().await.unwrap();
+    assert_eq!(names(old).await, Vec::<String>::new());
+
+    // Traversal yields each related entity once
+    let dog_owners = context.query().eq("beast", "Dog").relation(0).execute().await.unwrap();
+    assert_eq!(names(dog_owners).await, vec!["Alice", "Bob"]);
+
+    let cat_owners = context.query().eq("beast", "Cat").relation(0).eq("name", "Bob").execute().await.unwrap();
+    assert_eq!(names(cat_owners).await, Vec::<String>::new());
+
+    let limited = context.query().eq("beast", "Dog").limit(1).execute().await.unwrap();
+    assert_eq!(names(limited).await.len(), 1);
+
+    let by_id = context.query().by_id(vec![bob.id]).execute().await.unwrap();
+    assert_eq!(names(by_id).await, vec!["Bob"]);
+
+    // The same results are obtained by way of a secondary index
+    context.add_field_index("beast").await.unwrap();
+    let dogs = context.query().eq("beast", "Dog").execute().await.unwrap();
+    assert_eq!(names(dogs).await, vec!["Fido", "Rex"]);
+}
+
+async fn next_event(observer: &mut QueryObserver) -> QueryEvent {
+    async_std::future::timeout(Duration::from_secs(5), observer.next()).await
+                                                                       .expect("query event")
+                                                                       .expect("observer open")
+}
+
+#[unbase_test_util::async_test]
+async fn observe_query() {
+    unbase_test_util::init_test_logger();
+
+    let net = Network::create_new_system();
+    let slab = Slab::new(&net);
+    let context = slab.create_context();
+
+    let mut rex = Entity::new_with_single_kv(&context, "beast", "Dog").await.unwrap();
+    let _tom = Entity::new_with_single_kv(&context, "beast", "Cat").await.unwrap();
+
+    let mut observer = context.observe_query(context.query().eq("beast", "Dog"));
+
+    match next_event(&mut observer).await {
+        QueryEvent::Added(entity) => assert_eq!(entity.id, rex.id),
+        e => panic!("unexpected event {:?}", e),
+    }
+
+    let mut vals = HashMap::new();
+    vals.insert("beast".to_string(), "Dog".to_string());
+    vals.insert("name".to_string(), "Fido".to_string());
+    let fido = Entity::new(&context, vals).await.unwrap();
+
+    match next_event(&mut observer).await {
+        QueryEvent::Added(entity) => assert_eq!(entity.id, fido.id),
+        e => panic!("unexpected event {:?}", e),
+    }
+
+    rex.set_value("sound", "Woof").await.unwrap();
+
+    match next_event(&mut observer).await {
+        QueryEvent::Changed(mut entity) => {
+            assert_eq!(entity.id, rex.id);
+            assert_eq!(entity.get_value("sound").await.unwrap(), Some("Woof".to_string()));
+        },
+        e => panic!("unexpected event {:?}", e),
+    }
+
+    rex.set_value("beast", "Wolf").await.unwrap();
+
+    match next_event(&mut observer).await {
+        QueryEvent::Removed(entity_id) => assert_eq!(entity_id, rex.id),
+        e => panic!("unexpected event {:?}", e),
+    }
+}
+
+#[unbase_test_util::async_test]
+async fn observe_query_traversal() {
+    unbase_test_util::init_test_logger();
+
+    let net = Network::create_new_system();
+    let slab = Slab::new(&net);
+    let context = slab.create_context();
+
+    let mut alice = Entity::new_with_single_kv(&context, "name", "Alice").await.unwrap();
+    let mut rex = Entity::new_with_single_kv(&context, "beast", "Dog").await.unwrap();
+    rex.set_relation(0, &alice).await.unwrap();
+
+    let mut observer = context.observe_query(context.query().eq("beast", "Dog").relation(0).eq("name", "Bob"));
+
+    // An edit to the related entity is enough to bring it into the results
+    alice.set_value("name", "Bob").await.unwrap();
+
+    match next_event(&mut observer).await {
+        QueryEvent::Added(entity) => assert_eq!(entity.id, alice.id),
+        e => panic!("unexpected event {:?}", e),
+    }
+
+    // And an edit to the entity from which it was reached takes it out again
+    rex.set_value("beast", "Wolf").await.unwrap();
+
+    match next_event(&mut observer).await {
+        QueryEvent::Removed(entity_id) => assert_eq!(entity_id, alice.id),
+        e => panic!("unexpected event {:?}", e),
+    }
+}
@@ -50,7 +50,7 @@ async fn remote_traversal_simulated() {
 
     assert_eq!(simulator.get_sent().unwrap(), 37);
     assert_eq!(simulator.get_delivered().unwrap(), 37);
-    assert_eq!(simulator.get_clock().unwrap(), 7);
+    assert_eq!(simulator.get_clock().unwrap(), 5);
 }
 
 #[async_test]
@@ -138,7 +138,6 @@ async fn udp_station_one() {
 
 async fn udp_station_two() {
     let net2 = unbase::Network::new();
-    net2.hack_set_next_slab_id(200);
 
     // HACK - Ensure slab_a is listening - TODO make this auto-retry
     Delay::new(Duration::from_millis(50)).await;
@@ -0,0 +1,54 @@
+extern crate unbase;
+use unbase::{
+    Entity,
+    Network,
+    Slab,
+};
+
+use std::{
+    fs,
+    time::Duration,
+};
+
+#[unbase_test_util::async_test]
+async fn reopen_slab_from_storage() {
+    unbase_test_util::init_test_logger();
+
+    let dir = std::env::temp_dir().join(format!("unbase-reopen-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    let slab_id;
+    let record_id;
+    {
+        let net = Network::create_new_system();
+        let slab = Slab::open(&net, &dir).expect("open new slab");
+        let context = slab.create_context();
+
+        let mut record = Entity::new_with_single_kv(&context, "animal_type", "Cat").await.unwrap();
+        record.set_value("sound", "Meow").await.unwrap();
+
+        slab_id = slab.id;
+        record_id = record.id;
+    }
+
+    // This network is not a new system, so it can only get a root index seed from the reopened slab
+    let net = Network::new();
+    let slab = Slab::open(&net, &dir).expect("reopen slab");
+    assert_eq!(slab.id, slab_id, "Reopened slab should retain its id");
+
+    let context = slab.create_context();
+    let mut record = context.fetch_kv("animal_type", "Cat", Duration::from_secs(1))
+                            .await
+                            .expect("record should be found after reopening");
+
+    assert_eq!(record.id, record_id);
+    assert_eq!(record.get_value("sound").await.unwrap(), Some("Meow".to_string()));
+
+    // Newly issued ids must not collide with those issued before the slab was reopened
+    let other = Entity::new_with_single_kv(&context, "animal_type", "Dog").await.unwrap();
+    assert!(other.id.id > record_id.id);
+
+    drop(context);
+    drop(slab);
+    fs::remove_dir_all(&dir).unwrap();
+}
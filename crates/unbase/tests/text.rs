@@ -0,0 +1,126 @@
+use futures::{
+    future::RemoteHandle,
+    StreamExt,
+};
+use std::sync::{
+    Arc,
+    Mutex,
+};
+use unbase::{
+    crdt::{
+        CrdtValue,
+        TextEvent,
+        TextObserver,
+    },
+    util::{
+        simulator::Simulator,
+        task::spawn_with_handle,
+    },
+    Entity,
+    Network,
+    Slab,
+};
+
+fn text(s: &str) -> Option<CrdtValue> {
+    Some(CrdtValue::Text(s.to_string()))
+}
+
+/// Maintain a copy of the text solely by way of the change events
+async fn follow(mut observer: TextObserver, copy: Arc<Mutex<String>>) {
+    while let Some(event) = observer.next().await {
+        let mut chars: Vec<char> = copy.lock().unwrap().chars().collect();
+        match event {
+            TextEvent::Insert { index, text } => {
+                chars.splice(index..index, text.chars());
+            },
+            TextEvent::Delete { index, len } => {
+                chars.drain(index..index + len);
+            },
+        }
+        *copy.lock().unwrap() = chars.into_iter().collect();
+    }
+}
+
+#[unbase_test_util::async_test]
+async fn text_converges_across_slabs() {
+    let net = Network::create_new_system();
+    let simulator = Simulator::new();
+    net.add_transport(Box::new(simulator.clone()));
+
+    simulator.start();
+
+    let slab_a = Slab::new(&net);
+    let slab_b = Slab::new(&net);
+    let context_a = slab_a.create_context();
+    let context_b = slab_b.create_context();
+
+    let mut rec_a = Entity::new_with_single_kv(&context_a, "title", "Notes").await.unwrap();
+    rec_a.text_insert("body", 0, "Hello").await.unwrap();
+    assert_eq!(rec_a.get_crdt("body").await.unwrap(), text("Hello"));
+
+    simulator.quiesce().await;
+
+    let mut rec_b = context_b.get_entity_by_id(rec_a.id).await.unwrap().expect("record on slab B");
+    assert_eq!(rec_b.get_crdt("body").await.unwrap(), text("Hello"));
+
+    let copy_b = Arc::new(Mutex::new(String::new()));
+    let _follower: RemoteHandle<()> = spawn_with_handle(follow(rec_b.observe_text("body"), copy_b.clone()));
+
+    simulator.quiesce().await;
+    assert_eq!(*copy_b.lock().unwrap(), "Hello");
+
+    // Both slabs edit the same position without having seen one another's edits
+    rec_a.text_insert("body", 5, " world").await.unwrap();
+    rec_b.text_insert("body", 5, "!").await.unwrap();
+    rec_b.text_delete("body", 0, 1).await.unwrap();
+    rec_b.text_insert("body", 0, "J").await.unwrap();
+
+    simulator.quiesce().await;
+
+    let value_a = rec_a.get_crdt("body").await.unwrap();
+    let value_b = rec_b.get_crdt("body").await.unwrap();
+    assert_eq!(value_a, value_b);
+    assert!(value_a == text("Jello world!") || value_a == text("Jello! world"),
+            "unexpected text {:?}",
+            value_a);
+
+    // The observer on slab B has followed along
+    assert_eq!(text(&copy_b.lock().unwrap()), value_a);
+
+    // Deleting everything after the first character, which spans the insertions of both slabs
+    rec_a.text_delete("body", 1, 11).await.unwrap();
+
+    simulator.quiesce().await;
+
+    assert_eq!(rec_b.get_crdt("body").await.unwrap(), text("J"));
+    assert_eq!(*copy_b.lock().unwrap(), "J");
+}
+
+#[unbase_test_util::async_test]
+async fn text_concurrent_inserts_at_same_position() {
+    let net = Network::create_new_system();
+    let slab = Slab::new(&net);
+    let context = slab.create_context();
+
+    let mut record = Entity::new_with_single_kv(&context, "title", "Notes").await.unwrap();
+    record.text_insert("body", 0, "ac").await.unwrap();
+
+    let mut left = record.clone();
+    let mut right = record.clone();
+    left.text_insert("body", 1, "b").await.unwrap();
+    right.text_insert("body", 1, "B").await.unwrap();
+
+    let mut record = context.get_entity(record.id).await.unwrap().expect("record");
+    let value = record.get_crdt("body").await.unwrap();
+    assert!(value == text("abBc") || value == text("aBbc"), "unexpected text {:?}", value);
+
+    // Out of range indices append, and deletions are clamped to the text
+    record.text_insert("body", 100, "d").await.unwrap();
+    record.text_delete("body", 3, 100).await.unwrap();
+
+    let expected = match value {
+        Some(CrdtValue::Text(s)) => s[..3].to_string(),
+        _ => unreachable!(),
+    };
+    assert_eq!(record.get_crdt("body").await.unwrap(), text(&expected));
+}
@@ -37,7 +37,6 @@ async fn test1_node_b() {
     Delay::new(Duration::from_millis(50)).await;
 
     let net = unbase::Network::new();
-    net.hack_set_next_slab_id(200);
     let udp = unbase::network::transport::TransportUDP::new("127.0.0.1:51002".to_string());
     net.add_transport(Box::new(udp.clone()));
     let _slab = unbase::Slab::new(&net);
@@ -83,7 +82,6 @@ async fn test2_node_b() {
     Delay::new(Duration::from_millis(50)).await;
 
     let net2 = Network::new();
-    net2.hack_set_next_slab_id(200);
     let udp2 = TransportUDP::new("127.0.0.1:52002".to_string());
     net2.add_transport(Box::new(udp2.clone()));
     let slab_b = Slab::new(&net2);
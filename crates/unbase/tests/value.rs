@@ -0,0 +1,169 @@
+extern crate unbase;
+use unbase::{
+    error::RetrieveError,
+    value::Value,
+    Entity,
+    Network,
+    Slab,
+};
+
+use std::{
+    collections::HashMap,
+    fs,
+    time::{
+        Duration,
+        UNIX_EPOCH,
+    },
+};
+
+#[unbase_test_util::async_test]
+async fn typed_values_round_trip() {
+    unbase_test_util::init_test_logger();
+
+    let dir = std::env::temp_dir().join(format!("unbase-value-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    let born = UNIX_EPOCH + Duration::from_millis(1_500_000_000_000);
+
+    let record_id;
+    {
+        let net = Network::create_new_system();
+        let slab = Slab::open(&net, &dir).expect("open new slab");
+        let context = slab.create_context();
+
+        let mut record = Entity::new_with_single_kv(&context, "name", "Rex").await.unwrap();
+        record_id = record.id;
+
+        record.set_value("legs", 4).await.unwrap();
+        record.set_value("weight", 31.5).await.unwrap();
+        record.set_value("good", true).await.unwrap();
+        record.set_value("chip", vec![0xde_u8, 0xad]).await.unwrap();
+        record.set_value("born", born).await.unwrap();
+        record.set_value("tricks", vec![Value::from("sit"), Value::Int(2)]).await.unwrap();
+        record.set_value("owner", Value::Null).await.unwrap();
+        record.set_value("collar", "red").await.unwrap();
+        record.remove_value("collar").await.unwrap();
+
+        assert_eq!(record.get_i64("legs").await.unwrap(), Some(4));
+        assert_eq!(record.get_f64("legs").await.unwrap(), Some(4.0));
+        assert_eq!(record.get_typed_value("weight").await.unwrap(), Some(Value::Float(31.5)));
+
+        // Asking for the wrong type is an error, whereas a missing or null value is simply absent
+        assert_eq!(record.get_bool("legs").await, Err(RetrieveError::TypeMismatch("int")));
+        assert_eq!(record.get_string("owner").await.unwrap(), None);
+        assert_eq!(record.get_string("missing").await.unwrap(), None);
+
+        // String values read back exactly as written, and other values are rendered
+        assert_eq!(record.get_value("name").await.unwrap(), Some("Rex".to_string()));
+        assert_eq!(record.get_value("legs").await.unwrap(), Some("4".to_string()));
+    }
+
+    // The types survive a round trip through storage
+    let net = Network::new();
+    let slab = Slab::open(&net, &dir).expect("reopen slab");
+    let context = slab.create_context();
+
+    let mut record = context.fetch_kv("name", "Rex", Duration::from_secs(1)).await.unwrap();
+    assert_eq!(record.id, record_id);
+
+    assert_eq!(record.get_i64("legs").await.unwrap(), Some(4));
+    assert_eq!(record.get_f64("weight").await.unwrap(), Some(31.5));
+    assert_eq!(record.get_bool("good").await.unwrap(), Some(true));
+    assert_eq!(record.get_bytes("chip").await.unwrap(), Some(vec![0xde, 0xad]));
+    assert_eq!(record.get_timestamp("born").await.unwrap(), Some(born));
+    assert_eq!(record.get_list("tricks").await.unwrap(),
+               Some(vec![Value::from("sit"), Value::Int(2)]));
+    assert_eq!(record.get_typed_value("owner").await.unwrap(), Some(Value::Null));
+    assert_eq!(record.get_typed_value("collar").await.unwrap(), None);
+
+    drop(context);
+    drop(slab);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[unbase_test_util::async_test]
+async fn remove_value() {
+    let net = Network::create_new_system();
+    let slab = Slab::new(&net);
+    let context = slab.create_context();
+
+    let mut record = Entity::new_with_single_kv(&context, "name", "Rex").await.unwrap();
+    record.set_value("owner", "Alice").await.unwrap();
+
+    // Keys from the initial materialized memo and from later edits may both be removed
+    record.remove_value("name").await.unwrap();
+    record.remove_value("owner").await.unwrap();
+    assert_eq!(record.get_typed_value("name").await.unwrap(), None);
+    assert_eq!(record.get_typed_value("owner").await.unwrap(), None);
+    assert!(context.try_fetch_kv("name", "Rex").await.unwrap().is_none());
+
+    // A removal is distinct from a null value, and the key may be set again
+    record.set_value("owner", Value::Null).await.unwrap();
+    assert_eq!(record.get_typed_value("owner").await.unwrap(), Some(Value::Null));
+    record.set_value("name", "Max").await.unwrap();
+    assert_eq!(record.get_value("name").await.unwrap(), Some("Max".to_string()));
+
+    // A value written concurrently with the removal survives it
+    let mut left = record.clone();
+    let mut right = record.clone();
+    left.remove_value("name").await.unwrap();
+    right.set_value("name", "Buddy").await.unwrap();
+
+    let mut record = context.get_entity(record.id).await.unwrap().expect("record");
+    assert_eq!(record.get_values("name").await.unwrap(), vec!["Buddy".to_string()]);
+}
+
+#[unbase_test_util::async_test]
+async fn set_several_values_at_once() {
+    let net = Network::create_new_system();
+    let slab = Slab::new(&net);
+    let context = slab.create_context();
+
+    let mut owner = Entity::new_with_single_kv(&context, "name", "Alice").await.unwrap();
+    let vet = Entity::new_with_single_kv(&context, "name", "Dr. Bob").await.unwrap();
+    let mut record = Entity::new_with_single_kv(&context, "name", "Rex").await.unwrap();
+
+    let mut vals = HashMap::new();
+    vals.insert("name".to_string(), Some(Value::from("Max")));
+    vals.insert("legs".to_string(), Some(Value::from(4)));
+    vals.insert("good".to_string(), Some(Value::from(true)));
+    record.set_values(vals).await.unwrap();
+
+    let mut relations = HashMap::new();
+    relations.insert(0, &owner);
+    relations.insert(1, &vet);
+    record.set_relations(relations).await.unwrap();
+
+    let mut edges = HashMap::new();
+    edges.insert(0, &owner);
+    edges.insert(1, &vet);
+    record.set_edges(edges).await.unwrap();
+
+    // One memo each for the values, the relations and the edges
+    assert_eq!(record.get_all_memo_ids().await.unwrap().len(), 4);
+
+    assert_eq!(record.get_value("name").await.unwrap(), Some("Max".to_string()));
+    assert_eq!(record.get_i64("legs").await.unwrap(), Some(4));
+    assert_eq!(record.get_bool("good").await.unwrap(), Some(true));
+    assert_eq!(record.get_relation(0).await.unwrap().map(|e| e.id), Some(owner.id));
+    assert_eq!(record.get_relation(1).await.unwrap().map(|e| e.id), Some(vet.id));
+    assert_eq!(record.get_edge(0).await.unwrap().map(|e| e.id), Some(owner.id));
+    assert_eq!(record.get_edge(1).await.unwrap().map(|e| e.id), Some(vet.id));
+    assert_eq!(context.try_fetch_kv("name", "Max").await.unwrap().map(|e| e.id), Some(record.id));
+
+    // Values may be removed alongside those which are set
+    let mut vals = HashMap::new();
+    vals.insert("legs".to_string(), Some(Value::from(3)));
+    vals.insert("good".to_string(), None);
+    record.set_values(vals).await.unwrap();
+
+    assert_eq!(record.get_all_memo_ids().await.unwrap().len(), 5);
+    assert_eq!(record.get_i64("legs").await.unwrap(), Some(3));
+    assert_eq!(record.get_bool("good").await.unwrap(), None);
+
+    // Nothing to write
+    owner.set_values(HashMap::<String, Option<Value>>::new()).await.unwrap();
+    owner.set_relations(HashMap::new()).await.unwrap();
+    owner.set_edges(HashMap::new()).await.unwrap();
+    assert_eq!(owner.get_all_memo_ids().await.unwrap().len(), 1);
+}